@@ -18,6 +18,26 @@ impl<R: Read> BytesIter<R> {
             buf: Vec::with_capacity(size),
         }
     }
+
+    /// Flattens the chunks into a stream of individual bytes, for
+    /// consumers that work a byte at a time but still want buffered reads.
+    pub fn flat_bytes(self) -> FlatBytes<R> {
+        FlatBytes {
+            bytes_iter: self,
+            buf_iter: vec![].into_iter(),
+        }
+    }
+
+    /// Reassembles the chunks into lines, which may span any number of
+    /// chunks, for line-oriented tools.
+    pub fn lines(self) -> ByteLines<R> {
+        ByteLines {
+            bytes_iter: self,
+            chunk: vec![],
+            pos: 0,
+            unterminated: false,
+        }
+    }
 }
 
 impl<R: Read> Iterator for BytesIter<R> {
@@ -43,6 +63,81 @@ impl<R: Read> Iterator for BytesIter<R> {
     }
 }
 
+pub struct FlatBytes<R: Read> {
+    bytes_iter: BytesIter<R>,
+    buf_iter: std::vec::IntoIter<u8>,
+}
+
+impl<R: Read> Iterator for FlatBytes<R> {
+    type Item = Result<u8, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.buf_iter.next() {
+            Some(byte) => Some(Ok(byte)),
+            None => match self.bytes_iter.next()? {
+                Ok(buf_new) => {
+                    self.buf_iter = buf_new.into_iter();
+                    self.next()
+                }
+                Err(err) => Some(Err(err)),
+            },
+        }
+    }
+}
+
+/// The lines of a `BytesIter`, without their newlines. A final line
+/// lacking a newline is still returned.
+pub struct ByteLines<R: Read> {
+    bytes_iter: BytesIter<R>,
+    chunk: Vec<u8>,
+    /// How much of `chunk` has been returned already.
+    pos: usize,
+    unterminated: bool,
+}
+
+impl<R: Read> ByteLines<R> {
+    /// Whether the final line returned lacked a newline.
+    pub fn unterminated(&self) -> bool {
+        self.unterminated
+    }
+}
+
+impl<R: Read> Iterator for ByteLines<R> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = vec![];
+        loop {
+            let rest = &self.chunk[self.pos..];
+            match rest.iter().position(|bt| *bt == b'\n') {
+                Some(len) => {
+                    line.extend_from_slice(&rest[..len]);
+                    self.pos += len + 1;
+                    return Some(Ok(line));
+                }
+                None => line.extend_from_slice(rest),
+            }
+            match self.bytes_iter.next() {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.chunk.clear();
+                    self.pos = 0;
+                    return if line.is_empty() {
+                        None
+                    } else {
+                        self.unterminated = true;
+                        Some(Ok(line))
+                    };
+                }
+            }
+        }
+    }
+}
+
 // Copyright (c) 2017 Ted Mielczarek
 #[cfg(test)]
 mod tests {
@@ -84,6 +179,7 @@ mod tests {
     }
     */
 
+    #[allow(clippy::while_let_on_iterator)]
     fn sliced(b: &[u8], size: usize) -> Vec<Vec<u8>> {
         let mut v = vec![];
         let mut iter = BytesIter::new(b, size);
@@ -122,6 +218,35 @@ slice.chunks produced {} chunks with lengths: {:?}",
         test(bytes, 4);
     }
 
+    #[test]
+    fn test_lines_across_chunks() {
+        let bytes = b"one\n\ntwo three\nfour";
+        (1..=bytes.len()).for_each(|size| {
+            let lines = BytesIter::new(&bytes[..], size)
+                .lines()
+                .collect::<Result<Vec<_>, Error>>()
+                .unwrap();
+            assert_eq!(
+                lines,
+                vec![&b"one"[..], b"", b"two three", b"four"],
+                "chunk size {}",
+                size
+            );
+        });
+    }
+
+    #[test]
+    fn test_flat_bytes_across_chunks() {
+        let bytes = b"0123456789abcdef";
+        (1..=bytes.len() + 1).for_each(|size| {
+            let flat = BytesIter::new(&bytes[..], size)
+                .flat_bytes()
+                .collect::<Result<Vec<u8>, Error>>()
+                .unwrap();
+            assert_eq!(flat, bytes.to_vec(), "chunk size {}", size);
+        });
+    }
+
     #[test]
     fn test_chunks_larger_than_bufread_default_buffer() {
         let bytes = (0..DEFAULT_BUF_SIZE * 4)
@@ -0,0 +1,421 @@
+/*
+`change` from chapter 5 of Software Tools is the companion to `find`:
+it copies its input, replacing what matches a pattern on each line. In
+the replacement, `&` stands for the matched text; with the extended
+regular expressions of `regex.rs` (`-E`), groups may also be referred
+to by number.
+
+Every match on a line is replaced, as in the book, unless only the
+first is wanted. An empty match right after another is skipped, so that
+`x*` changes "xxa" to "-a-" rather than "--a-".
+
+Files may be changed in place with `-i`; each is rewritten through
+`write_atomically`, so an interrupted run leaves either the old file or
+the new one, and a file with nothing to change is left alone. When
+several files are changed, the number of changes made to each is
+reported on STDERR.
+ */
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::pattern::Pattern;
+use crate::regex::{Regex, RegexConf};
+use crate::util::{open_input, write_atomically, STD_STREAM_NAME};
+
+const WHOLE_MATCH: u8 = b'&';
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct ChangeConf {
+    /// Use extended regular expressions rather than the book's patterns.
+    pub extended: bool,
+    /// Replace only the first match on each line.
+    pub first_only: bool,
+    /// Rewrite each file rather than writing to STDOUT.
+    pub in_place: bool,
+}
+
+/// A compiled pattern in either dialect.
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Pattern(Pattern),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn compile(conf: &ChangeConf, pat: &[u8]) -> Result<Matcher, SfwError> {
+        if conf.extended {
+            Regex::compile(pat, &RegexConf::default()).map(Matcher::Regex)
+        } else {
+            Pattern::compile(pat).map(Matcher::Pattern)
+        }
+    }
+
+    /// The number of groups, not counting the whole match.
+    pub fn group_count(&self) -> usize {
+        match self {
+            Matcher::Pattern(_) => 0,
+            Matcher::Regex(regex) => regex.group_count(),
+        }
+    }
+
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        match self {
+            Matcher::Pattern(pattern) => pattern.is_match(line),
+            Matcher::Regex(regex) => regex.is_match(line),
+        }
+    }
+
+    /// The spans of the match starting at or after `start` and of its
+    /// groups; the first is always present.
+    fn captures_at(
+        &self,
+        line: &[u8],
+        start: usize,
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        match self {
+            Matcher::Pattern(pattern) => {
+                pattern.find_at(line, start).map(|span| vec![Some(span)])
+            }
+            Matcher::Regex(regex) => {
+                regex.captures_at(line, start).map(|caps| {
+                    (0..caps.len()).map(|group| caps.get(group)).collect()
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum RepElem {
+    Bytes(Vec<u8>),
+    /// The text matched by a group; 0 is the whole match.
+    Group(usize),
+}
+
+/// The text that replaces each match.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Replacement {
+    elems: Vec<RepElem>,
+}
+
+impl Replacement {
+    /// Parses `rep`, in which `&` is the matched text and, after
+    /// `escape`, a digit is a group, `t` a tab, `n` a newline, and any
+    /// other byte itself.
+    pub fn parse(rep: &[u8], escape: u8) -> Replacement {
+        let mut elems = vec![];
+        let mut bytes = vec![];
+        let mut ix = 0;
+        while ix < rep.len() {
+            let group = match rep[ix] {
+                WHOLE_MATCH => Some(0),
+                bt if bt == escape && ix + 1 < rep.len() => {
+                    ix += 1;
+                    match rep[ix] {
+                        digit @ b'0'..=b'9' => Some((digit - b'0') as usize),
+                        bt => {
+                            bytes.push(match bt {
+                                b't' => b'\t',
+                                b'n' => b'\n',
+                                bt => bt,
+                            });
+                            None
+                        }
+                    }
+                }
+                bt => {
+                    bytes.push(bt);
+                    None
+                }
+            };
+            if let Some(group) = group {
+                if !bytes.is_empty() {
+                    elems.push(RepElem::Bytes(std::mem::take(&mut bytes)));
+                }
+                elems.push(RepElem::Group(group));
+            }
+            ix += 1;
+        }
+        if !bytes.is_empty() {
+            elems.push(RepElem::Bytes(bytes));
+        }
+        Replacement { elems }
+    }
+
+    /// The highest group referred to.
+    pub fn max_group(&self) -> usize {
+        self.elems
+            .iter()
+            .filter_map(|elem| match elem {
+                RepElem::Group(group) => Some(*group),
+                RepElem::Bytes(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Appends the replacement for a match in `line` to `out`; groups
+    /// that didn't take part in the match are left empty.
+    fn expand(
+        &self,
+        line: &[u8],
+        spans: &[Option<(usize, usize)>],
+        out: &mut Vec<u8>,
+    ) {
+        self.elems.iter().for_each(|elem| match elem {
+            RepElem::Bytes(bytes) => out.extend_from_slice(bytes),
+            RepElem::Group(group) => {
+                if let Some((start, end)) = spans.get(*group).copied().flatten()
+                {
+                    out.extend_from_slice(&line[start..end]);
+                }
+            }
+        });
+    }
+}
+
+const CHANGE_USAGE: &str = r#"
+change [OPTION]... PATTERN REPLACEMENT [FILE]...
+
+Copies each FILE (or STDIN) to STDOUT, replacing the text on each line
+that matches PATTERN (see find) with REPLACEMENT, which may be empty.
+In REPLACEMENT, & stands for the matched text; after the escape (@, or
+\ with -E), a digit N stands for the text matched by group N (0 being
+the whole match), t for a tab, n for a newline, and anything else for
+itself.
+When there are several files, the number of changes made to each is
+reported on STDERR.
+
+Valid options are:
+-E            PATTERN is an extended regular expression
+--first       replace only the first match on each line
+-i            change the files in place
+
+"#;
+
+pub fn run_change_seahorse_cmd() -> Command {
+    Command::new("change")
+        .description(
+            "change: replace text matching a pattern; output to STDOUT",
+        )
+        .usage(CHANGE_USAGE)
+        .action(run_change_seahorse_action)
+        .flag(
+            Flag::new("extended", FlagType::Bool)
+                .alias("E")
+                .description("change -E 'colou?r' hue some_file"),
+        )
+        .flag(
+            Flag::new("first", FlagType::Bool)
+                .description("change --first pattern replacement some_file"),
+        )
+        .flag(
+            Flag::new("in-place", FlagType::Bool)
+                .alias("i")
+                .description("change -i pattern replacement some_file"),
+        )
+}
+
+pub fn run_change_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let pattern = args.next().user_err("change: missing PATTERN");
+    let replacement = args.next().user_err("change: missing REPLACEMENT");
+    let srcs = args.cloned().collect::<Vec<String>>();
+    let conf = ChangeConf {
+        extended: ctxt.bool_flag("extended"),
+        first_only: ctxt.bool_flag("first"),
+        in_place: ctxt.bool_flag("in-place"),
+    };
+    run_change(&conf, pattern, replacement, &srcs);
+}
+
+/// Convenience function for running change in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_change(
+    conf: &ChangeConf,
+    pattern: &str,
+    replacement: &str,
+    srcs: &[String],
+) {
+    let matcher =
+        Matcher::compile(conf, pattern.as_bytes()).user_err("Error in change");
+    let escape = if conf.extended { b'\\' } else { b'@' };
+    let replacement = Replacement::parse(replacement.as_bytes(), escape);
+    if replacement.max_group() > matcher.group_count() {
+        user_exit(SfwError::usage(format!(
+            "change: PATTERN has no group {}",
+            replacement.max_group()
+        )));
+    }
+    if conf.in_place && srcs.is_empty() {
+        user_exit(SfwError::usage("change: -i needs a FILE".to_string()));
+    }
+    let default_src = [String::from(STD_STREAM_NAME)];
+    let srcs = if srcs.is_empty() { &default_src } else { srcs };
+    let report = |src: &str, changes: u64| {
+        if srcs.len() > 1 {
+            let plural = if changes == 1 { "" } else { "s" };
+            eprintln!("{}: {} change{}", src, changes, plural);
+        }
+        Ok(())
+    };
+    let mut f_out = BufWriter::new(io::stdout());
+    srcs.iter()
+        .try_for_each(|src| {
+            let changes = if conf.in_place {
+                let f_in = File::open(src)
+                    .sfw_path_err("Couldn't open", src.as_str())?;
+                if !has_match(&matcher, f_in)? {
+                    return report(src, 0);
+                }
+                let mut changes = 0;
+                write_atomically(src, |file_out| {
+                    let f_in = File::open(src)
+                        .sfw_path_err("Couldn't open", src.as_str())?;
+                    changes =
+                        change(conf, &matcher, &replacement, f_in, file_out)?;
+                    Ok(())
+                })?;
+                changes
+            } else {
+                let f_in = open_input(Some(src))?;
+                change(conf, &matcher, &replacement, f_in, &mut f_out)?
+            };
+            report(src, changes)
+        })
+        .and_then(|_| f_out.flush().sfw_err("Couldn't flush output"))
+        .user_err("Error in change");
+}
+
+/// Whether any line of `f_in` has a match, i.e. whether `change` would
+/// make any changes to it.
+fn has_match<R: Read>(matcher: &Matcher, f_in: R) -> Result<bool, SfwError> {
+    for line in BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines() {
+        if matcher.is_match(&line.sfw_err("Couldn't read input")?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Copies `f_in` to `f_out`, replacing matches of `matcher`, and returns
+/// the number of replacements made. A last line without a newline is
+/// left without one.
+pub fn change<R: Read, W: Write + ?Sized>(
+    conf: &ChangeConf,
+    matcher: &Matcher,
+    replacement: &Replacement,
+    f_in: R,
+    f_out: &mut W,
+) -> Result<u64, SfwError> {
+    let mut changes = 0;
+    let mut lines = BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines();
+    while let Some(line) = lines.next() {
+        let line = line.sfw_err("Couldn't read input")?;
+        let (out, line_changes) =
+            change_line(conf, matcher, replacement, &line);
+        changes += line_changes;
+        f_out.write_all(&out).sfw_err("Couldn't write output")?;
+        if !lines.unterminated() {
+            f_out.write_all(b"\n").sfw_err("Couldn't write output")?;
+        }
+    }
+    Ok(changes)
+}
+
+/// The line with its matches replaced, and how many there were.
+pub fn change_line(
+    conf: &ChangeConf,
+    matcher: &Matcher,
+    replacement: &Replacement,
+    line: &[u8],
+) -> (Vec<u8>, u64) {
+    let mut out = vec![];
+    let mut changes = 0;
+    let mut pos = 0;
+    let mut last_end = None;
+    while let Some(spans) = matcher.captures_at(line, pos) {
+        let (start, end) = match spans.first().copied().flatten() {
+            Some(span) => span,
+            None => break,
+        };
+        out.extend_from_slice(&line[pos..start]);
+        if start < end || last_end != Some(start) {
+            replacement.expand(line, &spans, &mut out);
+            changes += 1;
+            last_end = Some(end);
+        }
+        pos = end;
+        if conf.first_only && changes > 0 {
+            break;
+        }
+        if start == end {
+            // Step over a byte, so the next match starts later.
+            match line.get(end) {
+                Some(bt) => out.push(*bt),
+                None => break,
+            }
+            pos += 1;
+        }
+    }
+    out.extend_from_slice(&line[pos..]);
+    (out, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change_str(
+        conf: &ChangeConf,
+        pat: &str,
+        rep: &str,
+        line: &str,
+    ) -> String {
+        let matcher = Matcher::compile(conf, pat.as_bytes()).unwrap();
+        let escape = if conf.extended { b'\\' } else { b'@' };
+        let rep = Replacement::parse(rep.as_bytes(), escape);
+        let (out, _) = change_line(conf, &matcher, &rep, line.as_bytes());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_change_patterns() {
+        let conf = ChangeConf::default();
+        assert_eq!(change_str(&conf, "a*b", "<&>", "xaabyb"), "x<aab>y<b>");
+        assert_eq!(change_str(&conf, "x*", "-", "xxa"), "-a-");
+        assert_eq!(change_str(&conf, "^ *", "", "  indented"), "indented");
+        assert_eq!(change_str(&conf, "o", "@&@t", "foo"), "f&\t&\t");
+        let conf = ChangeConf {
+            first_only: true,
+            ..ChangeConf::default()
+        };
+        assert_eq!(change_str(&conf, "o", "0", "foo"), "f0o");
+    }
+
+    #[test]
+    fn test_change_groups_and_counts() {
+        let conf = ChangeConf {
+            extended: true,
+            ..ChangeConf::default()
+        };
+        assert_eq!(
+            change_str(&conf, "([a-z]+)=([0-9]+)", "\\2=\\1", "a=1, bc=23"),
+            "1=a, 23=bc"
+        );
+        assert_eq!(change_str(&conf, "(x)|y", "[\\1]", "xy"), "[x][]");
+        let matcher = Matcher::compile(&conf, b"o+").unwrap();
+        let rep = Replacement::parse(b"0", b'\\');
+        let mut out = vec![];
+        let changes =
+            change(&conf, &matcher, &rep, &b"foo\nbar\nboo"[..], &mut out)
+                .unwrap();
+        assert_eq!((changes, out), (2, b"f0\nbar\nb0".to_vec()));
+    }
+}
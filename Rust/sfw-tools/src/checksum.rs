@@ -0,0 +1,270 @@
+use std::io::{Error, Read};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+
+/// The reflected IEEE 802.3 polynomial, as used by zlib, PNG, gzip, etc.
+const CRC32_POLY: u32 = 0xEDB8_8320;
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut ii = 0;
+    while ii < 256 {
+        let mut crc = ii as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[ii] = crc;
+        ii += 1;
+    }
+    table
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// A running CRC32 checksum; bytes may be fed in with `update` as they
+/// stream by, so the data never needs to be held in memory all at once.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    pub fn update(&mut self, b_slice: &[u8]) {
+        self.state = b_slice.iter().fold(self.state, |crc, bt| {
+            CRC32_TABLE[((crc ^ *bt as u32) & 0xFF) as usize] ^ (crc >> 8)
+        });
+    }
+
+    pub const fn value(&self) -> u32 {
+        !self.state
+    }
+}
+
+pub fn crc32(b_slice: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(b_slice);
+    crc.value()
+}
+
+pub fn crc32_reader<R: Read>(reader: R) -> Result<u32, Error> {
+    BytesIter::new(reader, DEFAULT_BUF_SIZE)
+        .try_fold(Crc32::new(), |mut crc, b_slice| {
+            crc.update(&b_slice?);
+            Ok(crc)
+        })
+        .map(|crc| crc.value())
+}
+
+/// Streams both readers and returns the offset of the first byte at
+/// which they differ, or `None` if they are identical. If one reader is
+/// a prefix of the other, the offset is the length of the shorter one.
+pub fn first_diff_offset<R1: Read, R2: Read>(
+    left: R1,
+    right: R2,
+) -> Result<Option<u64>, Error> {
+    let mut left_iter = BytesIter::new(left, DEFAULT_BUF_SIZE).flat_bytes();
+    let mut right_iter = BytesIter::new(right, DEFAULT_BUF_SIZE).flat_bytes();
+    let mut offset: u64 = 0;
+    loop {
+        match (
+            left_iter.next().transpose()?,
+            right_iter.next().transpose()?,
+        ) {
+            (None, None) => return Ok(None),
+            (Some(lb), Some(rb)) if lb == rb => offset += 1,
+            _ => return Ok(Some(offset)),
+        }
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1,
+    0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+    0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+    0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+    0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+    0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+    0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c,
+    0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_BLOCK_SIZE: usize = 64;
+pub const SHA256_SIZE: usize = 32;
+
+/// A running SHA-256 digest (FIPS 180-4), fed incrementally like `Crc32`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: Vec<u8>,
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            state: SHA256_INIT,
+            block: Vec::with_capacity(SHA256_BLOCK_SIZE),
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, b_slice: &[u8]) {
+        self.len += b_slice.len() as u64;
+        b_slice.iter().for_each(|bt| {
+            self.block.push(*bt);
+            if self.block.len() == SHA256_BLOCK_SIZE {
+                self.compress();
+            }
+        });
+    }
+
+    pub fn finalize(mut self) -> [u8; SHA256_SIZE] {
+        let bit_len = self.len.wrapping_mul(8);
+        self.block.push(0x80);
+        if self.block.len() > SHA256_BLOCK_SIZE - 8 {
+            self.block.resize(SHA256_BLOCK_SIZE, 0);
+            self.compress();
+        }
+        self.block.resize(SHA256_BLOCK_SIZE - 8, 0);
+        self.block.extend_from_slice(&bit_len.to_be_bytes());
+        self.compress();
+        let mut digest = [0_u8; SHA256_SIZE];
+        digest
+            .chunks_mut(4)
+            .zip(self.state.iter())
+            .for_each(|(out, word)| out.copy_from_slice(&word.to_be_bytes()));
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut ww = [0_u32; 64];
+        self.block.chunks(4).enumerate().for_each(|(ii, word)| {
+            ww[ii] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        });
+        (16..64).for_each(|ii| {
+            let s0 = ww[ii - 15].rotate_right(7)
+                ^ ww[ii - 15].rotate_right(18)
+                ^ (ww[ii - 15] >> 3);
+            let s1 = ww[ii - 2].rotate_right(17)
+                ^ ww[ii - 2].rotate_right(19)
+                ^ (ww[ii - 2] >> 10);
+            ww[ii] = ww[ii - 16]
+                .wrapping_add(s0)
+                .wrapping_add(ww[ii - 7])
+                .wrapping_add(s1);
+        });
+        let [a, b, c, d, e, f, g, h] = (0..64).fold(self.state, |vv, ii| {
+            let [a, b, c, d, e, f, g, h] = vv;
+            let s1 =
+                e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[ii])
+                .wrapping_add(ww[ii]);
+            let s0 =
+                a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+            [
+                temp1.wrapping_add(temp2),
+                a,
+                b,
+                c,
+                d.wrapping_add(temp1),
+                e,
+                f,
+                g,
+            ]
+        });
+        self.state
+            .iter_mut()
+            .zip([a, b, c, d, e, f, g, h].iter())
+            .for_each(|(st, vv)| *st = st.wrapping_add(*vv));
+        self.block.clear();
+    }
+}
+
+pub fn sha256(b_slice: &[u8]) -> [u8; SHA256_SIZE] {
+    let mut digest = Sha256::new();
+    digest.update(b_slice);
+    digest.finalize()
+}
+
+pub fn to_hex(b_slice: &[u8]) -> String {
+    b_slice.iter().map(|bt| format!("{:02x}", bt)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_crc32_incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_first_diff_offset() {
+        let same = first_diff_offset(&b"abcdef"[..], &b"abcdef"[..]);
+        assert_eq!(same.unwrap(), None);
+        let diff = first_diff_offset(&b"abcdef"[..], &b"abcXef"[..]);
+        assert_eq!(diff.unwrap(), Some(3));
+        let prefix = first_diff_offset(&b"abc"[..], &b"abcdef"[..]);
+        assert_eq!(prefix.unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            to_hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            to_hex(&sha256(long)),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+}
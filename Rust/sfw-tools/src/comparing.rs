@@ -0,0 +1,283 @@
+/*
+The `compare` program from chapter 3 of Software Tools reads two files a
+line at a time and reports every line that differs, along with its line
+number. When one file runs out first, it is a prefix of the other, and
+this is reported too, as is a last line that only one file ends with a
+newline. Like `cmp` and `diff`, the exit status reflects the result: 0
+if the files are the same, 1 if they differ, and 2 if there was
+trouble.
+
+The `-b` option compares bytes rather than lines, reporting only the
+first difference, as `cmp` does.
+ */
+
+use std::io::{self, Error, Read, Write};
+use std::process;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{is_newline, open_input};
+
+const COMPARE_USAGE: &str = r#"
+compare [OPTION] FILE1 FILE2
+
+Reports each line that differs between FILE1 and FILE2, with its line
+number. Either file may be - for STDIN.
+Exits with status 0 if the files are the same, 1 if they differ, and 2
+if there was trouble.
+
+Valid options are:
+-b            compare bytes, and report the first difference only
+
+"#;
+
+pub fn run_compare_seahorse_cmd() -> Command {
+    Command::new("compare")
+        .description(
+            "compare: report the differences between two files\
+            ; output to STDOUT",
+        )
+        .usage(COMPARE_USAGE)
+        .action(run_compare_seahorse_action)
+        .flag(
+            Flag::new("bytes", FlagType::Bool)
+                .alias("b")
+                .description("compare -b file1 file2"),
+        )
+}
+
+pub fn run_compare_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let name1 = args.next().user_err("compare: missing FILE1");
+    let name2 = args.next().user_err("compare: missing FILE2");
+    run_compare(name1, name2, ctxt.bool_flag("bytes"));
+}
+
+/// Convenience function for running compare in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Otherwise, the program exits with status 0 for identical files
+/// and 1 for files that differ.
+pub fn run_compare(name1: &str, name2: &str, bytes: bool) {
+    let f_in1 = open_input(Some(name1))
+        .user_err_status("Error in compare", EXIT_TROUBLE);
+    let f_in2 = open_input(Some(name2))
+        .user_err_status("Error in compare", EXIT_TROUBLE);
+    let f_out = io::stdout();
+    let same = if bytes {
+        compare_bytes((name1, f_in1), (name2, f_in2), f_out)
+    } else {
+        compare((name1, f_in1), (name2, f_in2), f_out)
+    }
+    .user_err_status("Error in compare", EXIT_TROUBLE);
+    process::exit(if same { EXIT_SUCCESS } else { EXIT_FAILURE })
+}
+
+/// Writes a report of the lines that differ between two named inputs,
+/// returning `true` if they are identical.
+pub fn compare<R1, R2, W>(
+    (name1, f_in1): (&str, R1),
+    (name2, f_in2): (&str, R2),
+    mut f_out: W,
+) -> Result<bool, SfwError>
+where
+    R1: Read,
+    R2: Read,
+    W: Write,
+{
+    let mut lines1 = BytesIter::new(f_in1, DEFAULT_BUF_SIZE).lines();
+    let mut lines2 = BytesIter::new(f_in2, DEFAULT_BUF_SIZE).lines();
+    let mut same = true;
+    let mut line_num: u64 = 0;
+    loop {
+        let line1 = lines1
+            .next()
+            .transpose()
+            .sfw_path_err("Couldn't read", name1)?;
+        let line2 = lines2
+            .next()
+            .transpose()
+            .sfw_path_err("Couldn't read", name2)?;
+        match (line1, line2) {
+            (None, None) => {
+                // The lines read alike, but the files still differ if
+                // only one ends with a newline.
+                let ends = (lines1.unterminated(), lines2.unterminated());
+                if let (true, false) | (false, true) = ends {
+                    let name = if ends.0 { name1 } else { name2 };
+                    writeln!(
+                        f_out,
+                        "compare: EOF on {} without newline",
+                        name
+                    )?;
+                    same = false;
+                }
+                break;
+            }
+            (Some(_), None) => {
+                write_eof(&mut f_out, name2, line_num, "line", same)?;
+                same = false;
+                break;
+            }
+            (None, Some(_)) => {
+                write_eof(&mut f_out, name1, line_num, "line", same)?;
+                same = false;
+                break;
+            }
+            (Some(line1), Some(line2)) => {
+                line_num += 1;
+                if line1 != line2 {
+                    same = false;
+                    writeln!(f_out, "{}:", line_num)?;
+                    f_out.write_all(b"< ")?;
+                    f_out.write_all(&line1)?;
+                    f_out.write_all(b"\n> ")?;
+                    f_out.write_all(&line2)?;
+                    f_out.write_all(b"\n")?;
+                }
+            }
+        }
+    }
+    f_out.flush().sfw_err("Couldn't flush output")?;
+    Ok(same)
+}
+
+/// Writes the first byte offset (and line) at which two named inputs
+/// differ, as `cmp` does, returning `true` if they are identical.
+/// Offsets and line numbers are counted from 1.
+pub fn compare_bytes<R1, R2, W>(
+    (name1, f_in1): (&str, R1),
+    (name2, f_in2): (&str, R2),
+    mut f_out: W,
+) -> Result<bool, SfwError>
+where
+    R1: Read,
+    R2: Read,
+    W: Write,
+{
+    let mut bytes1 = BytesIter::new(f_in1, DEFAULT_BUF_SIZE).flat_bytes();
+    let mut bytes2 = BytesIter::new(f_in2, DEFAULT_BUF_SIZE).flat_bytes();
+    let mut offset: u64 = 0;
+    let mut line_num: u64 = 1;
+    let same = loop {
+        let byte1 = bytes1
+            .next()
+            .transpose()
+            .sfw_path_err("Couldn't read", name1)?;
+        let byte2 = bytes2
+            .next()
+            .transpose()
+            .sfw_path_err("Couldn't read", name2)?;
+        match (byte1, byte2) {
+            (None, None) => break true,
+            (Some(_), None) => {
+                write_eof(&mut f_out, name2, offset, "byte", true)?;
+                break false;
+            }
+            (None, Some(_)) => {
+                write_eof(&mut f_out, name1, offset, "byte", true)?;
+                break false;
+            }
+            (Some(byte1), Some(byte2)) if byte1 == byte2 => {
+                offset += 1;
+                if is_newline(byte1) {
+                    line_num += 1;
+                }
+            }
+            (Some(_), Some(_)) => {
+                writeln!(
+                    f_out,
+                    "{} {} differ: byte {}, line {}",
+                    name1,
+                    name2,
+                    offset + 1,
+                    line_num
+                )?;
+                break false;
+            }
+        }
+    };
+    f_out.flush().sfw_err("Couldn't flush output")?;
+    Ok(same)
+}
+
+/// Reports that the named input ended before the other; if no
+/// differences were found up to then, it is a prefix of the other.
+fn write_eof<W: Write>(
+    f_out: &mut W,
+    name: &str,
+    count: u64,
+    unit: &str,
+    prefix: bool,
+) -> Result<(), Error> {
+    let plural = if count == 1 { "" } else { "s" };
+    write!(
+        f_out,
+        "compare: EOF on {} after {} {}{}",
+        name, count, unit, plural
+    )?;
+    if prefix {
+        write!(f_out, "; it is a prefix of the other file")?;
+    }
+    writeln!(f_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare_str(left: &str, right: &str, bytes: bool) -> (bool, String) {
+        let mut out = vec![];
+        let inputs = (("a", left.as_bytes()), ("b", right.as_bytes()));
+        let same = if bytes {
+            compare_bytes(inputs.0, inputs.1, &mut out)
+        } else {
+            compare(inputs.0, inputs.1, &mut out)
+        };
+        (same.unwrap(), String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_compare_lines() {
+        assert_eq!(compare_str("x\ny\n", "x\ny\n", false), (true, "".into()));
+        assert_eq!(
+            compare_str("x\ny\nz\n", "x\nY\nz\n", false),
+            (false, "2:\n< y\n> Y\n".into())
+        );
+        assert_eq!(
+            compare_str("x\n", "x\ny\n", false),
+            (
+                false,
+                "compare: EOF on a after 1 line; \
+                 it is a prefix of the other file\n"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_compare_missing_newline() {
+        assert_eq!(
+            compare_str("x\ny", "x\ny\n", false),
+            (false, "compare: EOF on a without newline\n".into())
+        );
+        assert_eq!(
+            compare_str("x\ny\n", "x\ny", false),
+            (false, "compare: EOF on b without newline\n".into())
+        );
+        assert_eq!(compare_str("x\ny", "x\ny", false), (true, "".into()));
+    }
+
+    #[test]
+    fn test_compare_bytes() {
+        assert_eq!(
+            compare_str("ab\ncd", "ab\ncX", true),
+            (false, "a b differ: byte 5, line 2\n".into())
+        );
+        let (same, out) = compare_str("abc", "ab", true);
+        assert!(!same);
+        assert!(out.starts_with("compare: EOF on b after 2 bytes"));
+    }
+}
@@ -62,9 +62,8 @@ pub fn run_compress(src: &str, dst: Box<dyn Write>) {
     compress(src, dst).user_err("Error in compress");
 }
 
-pub fn compress<W: Write>(src: &str, mut f_out: W) -> Result<(), Error> {
-    let f_in =
-        File::open(src).sfw_err(&format!("Couldn't open source '{}'", src))?;
+pub fn compress<W: Write>(src: &str, mut f_out: W) -> Result<(), SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     let f_in_iter = BytesIter::new(f_in, MAX_CHUNK_SIZE);
     let mut out_buf: Vec<u8> = Vec::with_capacity(MAX_CHUNK_SIZE);
     compress_go(
@@ -84,7 +83,7 @@ fn compress_go<'a, R, W>(
     mut bytes_iter: BytesIter<R>,
     mut buf_iter: Peekable<std::vec::IntoIter<u8>>,
     out_buf: &mut Vec<u8>,
-) -> Result<(), Error>
+) -> Result<(), SfwError>
 where
     R: Read,
     W: Write,
@@ -135,7 +134,7 @@ where
 fn write_buf_out<W: Write>(
     out_buf: &mut Vec<u8>,
     f_out: &mut W,
-) -> Result<(), Error> {
+) -> Result<(), SfwError> {
     let out_len = out_buf.len();
     let out_len = u8::try_from(out_len).map_err(|_| {
         Error::new(
@@ -186,10 +185,11 @@ pub fn run_expand(src: &str, dst: Box<dyn Write>) {
     expand(src, dst).user_err("Error in expand");
 }
 
-pub fn expand<W: Write>(src: &str, mut f_out: W) -> Result<(), Error> {
-    let f_in = File::open(src).sfw_err("Couldn't open source")?;
+pub fn expand<W: Write>(src: &str, mut f_out: W) -> Result<(), SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     let f_in_iter = BytesIter::new(f_in, MAX_CHUNK_SIZE);
-    expand_go(&mut f_out, f_in_iter, vec![].into_iter())
+    expand_go(&mut f_out, f_in_iter, vec![].into_iter(), 0)
+        .map_err(|err| err.with_path(src))
 }
 
 #[tailcall]
@@ -197,41 +197,54 @@ fn expand_go<'a, R, W>(
     f_out: &mut W,
     mut bytes_iter: BytesIter<R>,
     mut buf_iter: std::vec::IntoIter<u8>,
-) -> Result<(), Error>
+    offset: u64,
+) -> Result<(), SfwError>
 where
     R: Read,
     W: Write,
 {
     match buf_iter.next() {
         Some(byte) => {
-            match byte {
+            let record_len = match byte {
                 0 => {
-                    let repeat_char = buf_iter
-                        .next()
-                        .sfw_err("Couldn't read repeat character")?;
-                    let repeat_count = buf_iter
-                        .next()
-                        .sfw_err("Couldn't read repeat count")?;
+                    let repeat_char = buf_iter.next().ok_or_else(|| {
+                        SfwError::data_at(
+                            "Couldn't read repeat character",
+                            offset,
+                        )
+                    })?;
+                    let repeat_count = buf_iter.next().ok_or_else(|| {
+                        SfwError::data_at("Couldn't read repeat count", offset)
+                    })?;
                     f_out.write_all(
                         &(0..repeat_count)
                             .map(|_| repeat_char)
                             .collect::<Vec<u8>>(),
                     )?;
+                    3
                 }
                 read_size => {
                     let read_size = read_size as usize;
-                    let non_repeat_string =
-                        buf_iter.by_ref().safe_take(read_size)?;
-                    f_out.write_all(&non_repeat_string)?
+                    let non_repeat_string = buf_iter
+                        .by_ref()
+                        .safe_take(read_size)
+                        .map_err(|err| match err {
+                            SfwError::Data { msg, .. } => {
+                                SfwError::data_at(msg, offset)
+                            }
+                            other => other,
+                        })?;
+                    f_out.write_all(&non_repeat_string)?;
+                    1 + read_size as u64
                 }
             };
-            expand_go(f_out, bytes_iter, buf_iter)
+            expand_go(f_out, bytes_iter, buf_iter, offset + record_len)
         }
         None => {
             match bytes_iter.next() {
                 Some(buf_new) => {
                     let buf_iter = buf_new?.into_iter(); //shadow
-                    expand_go(f_out, bytes_iter, buf_iter)
+                    expand_go(f_out, bytes_iter, buf_iter, offset)
                 }
                 None => Ok(()), /* Finished */
             }
@@ -0,0 +1,342 @@
+/*
+`concat` from chapter 3 of Software Tools (better known as `cat`) copies
+each of its input files to the standard output in turn. Its options
+number lines, squeeze runs of blank lines, and make non-printing
+characters visible.
+
+These all depend on where lines begin and end, which needn't line up
+with the chunks read by `BytesIter`: a line may span several chunks, and
+a chunk may end just after a newline. So, much as `Flux` carries a
+chunk's boundary character types in `wc`, the `ConcatState` carried from
+one chunk (and file) to the next records whether we are at the start of
+a line, whether the last line was blank, and the line number reached.
+ */
+
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::open_input;
+
+const DEL: u8 = 0x7f;
+const META_BIT: u8 = 0x80;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct ConcatConf {
+    /// Number every output line.
+    pub number_lines: bool,
+    /// Number non-blank output lines; takes precedence over `number_lines`.
+    pub number_nonblank: bool,
+    /// Print only one of each run of blank lines.
+    pub squeeze_blank: bool,
+    /// Show control characters as `^X` and non-ASCII bytes as `M-X`.
+    pub show_nonprinting: bool,
+    /// Show a `$` at the end of each line.
+    pub show_ends: bool,
+    /// Show tabs as `^I`.
+    pub show_tabs: bool,
+}
+
+/// What `concat` needs to know about the output so far.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ConcatState {
+    at_line_start: bool,
+    prev_blank: bool,
+    line_num: u64,
+}
+
+impl Default for ConcatState {
+    fn default() -> Self {
+        ConcatState {
+            at_line_start: true,
+            prev_blank: false,
+            line_num: 0,
+        }
+    }
+}
+
+const CONCAT_USAGE: &str = r#"
+concat [OPTION]... [FILE]...
+
+Writes each FILE in turn to STDOUT; with no FILE, or when FILE is -,
+reads STDIN. Also available as cat.
+
+Valid options are:
+-n            number all output lines
+-b            number non-blank output lines
+-s            squeeze runs of blank lines into one
+-v            show non-printing characters as ^X and M-X
+-A            as -v, but also show tabs as ^I and line ends as $
+
+"#;
+
+pub fn run_concat_seahorse_cmd() -> Command {
+    Command::new("concat")
+        .alias("cat")
+        .description("concat: concatenate files to STDOUT")
+        .usage(CONCAT_USAGE)
+        .action(run_concat_seahorse_action)
+        .flag(
+            Flag::new("number", FlagType::Bool)
+                .alias("n")
+                .description("concat -n some_file"),
+        )
+        .flag(
+            Flag::new("number-nonblank", FlagType::Bool)
+                .alias("b")
+                .description("concat -b some_file"),
+        )
+        .flag(
+            Flag::new("squeeze-blank", FlagType::Bool)
+                .alias("s")
+                .description("concat -s some_file"),
+        )
+        .flag(
+            Flag::new("show-nonprinting", FlagType::Bool)
+                .alias("v")
+                .description("concat -v some_file"),
+        )
+        .flag(
+            Flag::new("show-all", FlagType::Bool)
+                .alias("A")
+                .description("concat -A some_file"),
+        )
+}
+
+pub fn run_concat_seahorse_action(ctxt: &Context) {
+    let show_all = ctxt.bool_flag("show-all");
+    let conf = ConcatConf {
+        number_lines: ctxt.bool_flag("number"),
+        number_nonblank: ctxt.bool_flag("number-nonblank"),
+        squeeze_blank: ctxt.bool_flag("squeeze-blank"),
+        show_nonprinting: show_all || ctxt.bool_flag("show-nonprinting"),
+        show_ends: show_all,
+        show_tabs: show_all,
+    };
+    run_concat(&conf, &ctxt.args);
+}
+
+/// Convenience function for running concat in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Inputs that can't be opened are reported and skipped, but still
+/// give a failing exit status once the rest have been written.
+pub fn run_concat(conf: &ConcatConf, srcs: &[String]) {
+    let mut f_out = BufWriter::new(io::stdout());
+    let srcs = if srcs.is_empty() {
+        vec![None]
+    } else {
+        srcs.iter().map(|src| Some(src.as_str())).collect()
+    };
+    let status = concat_files(conf, &srcs, &mut f_out, |err| {
+        eprintln!("Error in concat: {}", err)
+    })
+    .and_then(|status| {
+        f_out.flush().sfw_err("Couldn't flush output")?;
+        Ok(status)
+    })
+    .user_err("Error in concat");
+    process::exit(status)
+}
+
+/// Writes each of `srcs` (where `None` is STDIN) to `f_out` in turn, as
+/// one stream. An input that can't be opened is passed to `on_open_err`
+/// and skipped; the exit status for the first of these is returned, or
+/// `EXIT_SUCCESS` if there were none.
+pub fn concat_files<W, F>(
+    conf: &ConcatConf,
+    srcs: &[Option<&str>],
+    f_out: &mut W,
+    mut on_open_err: F,
+) -> Result<i32, SfwError>
+where
+    W: Write,
+    F: FnMut(&SfwError),
+{
+    let init = (ConcatState::default(), EXIT_SUCCESS);
+    let (_, status) = srcs.iter().try_fold(
+        init,
+        |(state, status), src| -> Result<_, SfwError> {
+            match open_input(*src) {
+                Ok(f_in) => Ok((concat(conf, state, f_in, f_out)?, status)),
+                Err(err) => {
+                    // Keep any error message in order with the output.
+                    f_out.flush().sfw_err("Couldn't flush output")?;
+                    on_open_err(&err);
+                    match status {
+                        EXIT_SUCCESS => Ok((state, err.exit_code())),
+                        _ => Ok((state, status)),
+                    }
+                }
+            }
+        },
+    )?;
+    Ok(status)
+}
+
+/// Copies `f_in` to `f_out`, continuing on from `state` (as left by any
+/// previous input), and returns the state at the end of `f_in`.
+pub fn concat<R: Read, W: Write>(
+    conf: &ConcatConf,
+    state: ConcatState,
+    f_in: R,
+    f_out: &mut W,
+) -> Result<ConcatState, SfwError> {
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE).try_fold(state, |state, b_slice| {
+        let mut out = Vec::with_capacity(DEFAULT_BUF_SIZE);
+        let state = concat_chunk(conf, state, &b_slice?, &mut out);
+        f_out.write_all(&out)?;
+        Ok(state)
+    })
+}
+
+/// Processes one chunk of input, appending the output to `out`.
+pub fn concat_chunk(
+    conf: &ConcatConf,
+    state: ConcatState,
+    chunk: &[u8],
+    out: &mut Vec<u8>,
+) -> ConcatState {
+    chunk.iter().fold(state, |mut state, byte| {
+        if state.at_line_start {
+            let blank = *byte == b'\n';
+            if blank && conf.squeeze_blank && state.prev_blank {
+                return state;
+            }
+            state.prev_blank = blank;
+            let numbered = if conf.number_nonblank {
+                !blank
+            } else {
+                conf.number_lines
+            };
+            if numbered {
+                state.line_num += 1;
+                out.extend_from_slice(
+                    format!("{:6}\t", state.line_num).as_bytes(),
+                );
+            }
+            state.at_line_start = false;
+        }
+        match *byte {
+            b'\n' => {
+                if conf.show_ends {
+                    out.push(b'$');
+                }
+                out.push(b'\n');
+                state.at_line_start = true;
+            }
+            b'\t' if !conf.show_tabs => out.push(b'\t'),
+            byte if conf.show_nonprinting || byte == b'\t' => {
+                push_visible(byte, out)
+            }
+            byte => out.push(byte),
+        }
+        state
+    })
+}
+
+/// Appends `byte` in the `^X` / `M-X` notation of `cat -v`.
+fn push_visible(byte: u8, out: &mut Vec<u8>) {
+    if byte >= META_BIT {
+        out.extend_from_slice(b"M-");
+    }
+    match byte & !META_BIT {
+        DEL => out.extend_from_slice(b"^?"),
+        ctrl if ctrl < b' ' => {
+            out.push(b'^');
+            out.push(ctrl + b'@');
+        }
+        plain => out.push(plain),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn concat_str(conf: &ConcatConf, input: &[u8]) -> String {
+        let mut out = vec![];
+        concat(conf, ConcatState::default(), input, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_concat_numbering_and_squeeze() {
+        let input = b"a\n\n\n\nb\n";
+        let conf = ConcatConf {
+            number_lines: true,
+            squeeze_blank: true,
+            ..ConcatConf::default()
+        };
+        assert_eq!(
+            concat_str(&conf, input),
+            "     1\ta\n     2\t\n     3\tb\n"
+        );
+        let conf = ConcatConf {
+            number_nonblank: true,
+            ..conf
+        };
+        assert_eq!(concat_str(&conf, input), "     1\ta\n\n     2\tb\n");
+    }
+
+    #[test]
+    fn test_concat_show_all() {
+        let conf = ConcatConf {
+            show_nonprinting: true,
+            show_ends: true,
+            show_tabs: true,
+            ..ConcatConf::default()
+        };
+        assert_eq!(concat_str(&conf, b"a\tb\x01\x7f\xe9\n"), "a^Ib^A^?M-i$\n");
+    }
+
+    #[test]
+    fn test_concat_files_continues_after_open_error() {
+        let path =
+            env::temp_dir().join(format!("sfwtools-concat.{}", process::id()));
+        fs::write(&path, "two\n").unwrap();
+        let path = path.to_str().unwrap();
+        let conf = ConcatConf {
+            number_lines: true,
+            ..ConcatConf::default()
+        };
+        let srcs = [Some(path), Some("/nonexistent/sfwtools"), Some(path)];
+        let mut out = vec![];
+        let mut errors = vec![];
+        let status = concat_files(&conf, &srcs, &mut out, |err| {
+            errors.push(err.to_string())
+        });
+        fs::remove_file(path).unwrap();
+        assert_eq!(status.unwrap(), EXIT_NOINPUT);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "     1\ttwo\n     2\ttwo\n"
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("/nonexistent/sfwtools"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn test_concat_state_across_chunks() {
+        // Splitting the input at every point must not change the output.
+        let input = b"x\n\n\n\ty\n\nz";
+        let conf = ConcatConf {
+            number_nonblank: true,
+            squeeze_blank: true,
+            show_ends: true,
+            ..ConcatConf::default()
+        };
+        let expected = concat_str(&conf, input);
+        (0..=input.len()).for_each(|split| {
+            let mut out = vec![];
+            let state = ConcatState::default();
+            let state = concat_chunk(&conf, state, &input[..split], &mut out);
+            concat_chunk(&conf, state, &input[split..], &mut out);
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        });
+    }
+}
@@ -1,9 +1,12 @@
-use std::fs::File;
-use std::io::{Error, Write};
+use std::fs::{self, File};
+use std::io::{self, Error, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use seahorse::{App, Command, Context};
+use seahorse::{App, Command, Context, Flag, FlagType};
 
 use crate::bytes_iter::BytesIter;
+use crate::checksum::*;
 use crate::constants::*;
 use crate::error::*;
 
@@ -14,20 +17,51 @@ pub fn cp_app() -> App {
         .command(run_cp_seahorse_cmd())
 }
 
-const CP_USAGE: &str = "cp SOURCE_FILE DEST_FILE";
+const CP_USAGE: &str = r#"
+cp [OPTION] SOURCE DEST
+
+Valid options are:
+-r            copy directories recursively, recreating symbolic links
+--verify      checksum the copy against the source after writing
+--progress    report bytes copied, rate and ETA on STDERR
+
+"#;
 
 pub fn run_cp_seahorse_cmd() -> Command {
     Command::new("cp")
         .description("cp: copy a file to another file")
         .usage(CP_USAGE)
         .action(run_cp_seahorse_action)
+        .flag(
+            Flag::new("recursive", FlagType::Bool)
+                .alias("r")
+                .description("cp -r src_dir dst_dir"),
+        )
+        .flag(
+            Flag::new("verify", FlagType::Bool)
+                .description("cp --verify src dst"),
+        )
+        .flag(
+            Flag::new("progress", FlagType::Bool)
+                .description("cp --progress src dst"),
+        )
 }
 
 pub fn run_cp_seahorse_action(ctxt: &Context) {
     let mut args = ctxt.args.iter();
     let src = args.next().user_err("cp: missing source");
     let dst = args.next().user_err("cp: missing destination");
-    run_cp(src, dst);
+    let conf = CpConf {
+        recursive: ctxt.bool_flag("recursive"),
+        verify: ctxt.bool_flag("verify"),
+    };
+    if ctxt.bool_flag("progress") {
+        run_cp_progress(src, dst, &conf);
+    } else if conf != CpConf::default() {
+        cp_with(src, dst, &conf, |_| ()).user_err("Error in cp");
+    } else {
+        run_cp(src, dst);
+    }
 }
 
 /// Convenience function for running cp in idiomatic fashion
@@ -36,14 +70,538 @@ pub fn run_cp(src: &str, dst: &str) {
     cp(src, dst).user_err("Error in cp");
 }
 
-pub fn cp(src: &str, dst: &str) -> Result<(), Error> {
-    let f_in = File::open(src).sfw_err("Couldn't open source")?;
+/// Convenience function for running cp_verify in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_cp_verify(src: &str, dst: &str) {
+    cp_verify(src, dst).user_err("Error in cp --verify");
+}
+
+/// Convenience function for running cp with a progress display on
+/// STDERR, followed by a summary line once the copy is complete.
+pub fn run_cp_progress(src: &str, dst: &str, conf: &CpConf) {
+    let start = Instant::now();
+    let mut display = ProgressDisplay::new(start);
+    let progress = cp_with(src, dst, conf, |prog| display.update(prog))
+        .user_err("Error in cp");
+    display.finish(&progress);
+}
+
+pub fn cp(src: &str, dst: &str) -> Result<(), SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     let mut f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
-    let mut f_out = File::create(dst)
-        .sfw_err(&format!("Couldn't open destination: {}", dst))?;
+    let mut f_out =
+        File::create(dst).sfw_path_err("Couldn't open destination", dst)?;
+
+    f_in_iter
+        .try_for_each(|b_slice_res| match b_slice_res {
+            Ok(b_slice) => f_out.write_all(&b_slice),
+            Err(err) => Err(err),
+        })
+        .sfw_path_err("Couldn't copy to destination", dst)
+}
+
+/// Copies `src` to `dst` while computing a CRC32 of the data as it
+/// streams through, then re-reads the destination and compares checksums.
+/// On a mismatch, both files are compared again to report the first
+/// differing byte offset. Returns the checksum of the copied data.
+pub fn cp_verify(src: &str, dst: &str) -> Result<u32, SfwError> {
+    let mut progress = CpProgress::default();
+    cp_file_go(
+        Path::new(src),
+        Path::new(dst),
+        true,
+        &mut progress,
+        &mut |_| (),
+    )
+    .map(|crc| crc.value())
+}
+
+/// Options for `cp_with`; the default is a plain single-file copy.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct CpConf {
+    /// Copy the contents of a source directory into the destination.
+    pub recursive: bool,
+    /// Checksum each destination file against its source (see `cp_verify`).
+    pub verify: bool,
+}
+
+/// A snapshot of how far along a copy is, passed to the progress callback.
+/// The totals are computed before any data is copied.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct CpProgress {
+    pub bytes_copied: u64,
+    pub bytes_total: u64,
+    pub files_copied: usize,
+    pub files_total: usize,
+}
 
-    f_in_iter.try_for_each(|b_slice_res| match b_slice_res {
-        Ok(b_slice) => f_out.write_all(&b_slice),
-        Err(err) => Err(err),
+impl CpProgress {
+    pub fn percent(&self) -> f64 {
+        if self.bytes_total == 0 {
+            100.0
+        } else {
+            100.0 * self.bytes_copied as f64 / self.bytes_total as f64
+        }
+    }
+}
+
+/// Copies `src` to `dst` as configured by `conf`, calling `on_progress`
+/// after every chunk written and after every completed file.
+/// Returns the final progress, i.e. the totals for the whole copy.
+pub fn cp_with<F>(
+    src: &str,
+    dst: &str,
+    conf: &CpConf,
+    mut on_progress: F,
+) -> Result<CpProgress, SfwError>
+where
+    F: FnMut(&CpProgress),
+{
+    let src_path = Path::new(src);
+    let dst_path = Path::new(dst);
+    let src_meta =
+        fs::metadata(src_path).sfw_path_err("Couldn't open source", src)?;
+    let mut progress = CpProgress::default();
+    if src_meta.is_dir() {
+        if !conf.recursive {
+            return Err(SfwError::usage(format!(
+                "'{}' is a directory (not copied without -r)",
+                src
+            )));
+        }
+        let entries = tree_files(src_path, Path::new(""))?;
+        progress.files_total = entries.len();
+        progress.bytes_total = entries.iter().map(TreeEntry::len).sum();
+        on_progress(&progress);
+        fs::create_dir_all(dst_path)
+            .sfw_path_err("Couldn't create directory", dst)?;
+        entries.iter().try_for_each(|entry| {
+            let rel_path = entry.path();
+            let dst_file = dst_path.join(rel_path);
+            if let Some(parent) = dst_file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match entry {
+                TreeEntry::File(..) => cp_file_go(
+                    &src_path.join(rel_path),
+                    &dst_file,
+                    conf.verify,
+                    &mut progress,
+                    &mut on_progress,
+                )
+                .map(|_| ()),
+                TreeEntry::Link(_) => {
+                    copy_link(&src_path.join(rel_path), &dst_file)?;
+                    progress.files_copied += 1;
+                    on_progress(&progress);
+                    Ok(())
+                }
+            }
+        })?;
+    } else {
+        progress.files_total = 1;
+        progress.bytes_total = src_meta.len();
+        on_progress(&progress);
+        cp_file_go(
+            src_path,
+            dst_path,
+            conf.verify,
+            &mut progress,
+            &mut on_progress,
+        )?;
+    }
+    Ok(progress)
+}
+
+/// A file found below a source directory, relative to the top of the tree.
+enum TreeEntry {
+    /// A regular file, along with its size.
+    File(Box<Path>, u64),
+    /// A symbolic link, which is recreated rather than followed.
+    Link(Box<Path>),
+}
+
+impl TreeEntry {
+    fn path(&self) -> &Path {
+        match self {
+            TreeEntry::File(path, _) | TreeEntry::Link(path) => path,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            TreeEntry::File(_, len) => *len,
+            TreeEntry::Link(_) => 0,
+        }
+    }
+}
+
+/// Lists the files below `dir` as paths relative to the top-level source
+/// directory. Only real directories are descended into, so symbolic link
+/// cycles can't cause infinite recursion.
+fn tree_files(root: &Path, rel: &Path) -> Result<Vec<TreeEntry>, SfwError> {
+    let dir = root.join(rel);
+    let mut entries = fs::read_dir(&dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, Error>>())
+        .sfw_path_err("Couldn't read directory", &dir)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    entries.iter().try_fold(vec![], |mut acc, entry| {
+        let rel_path = rel.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            acc.push(TreeEntry::Link(rel_path.into_boxed_path()));
+        } else if file_type.is_dir() {
+            acc.append(&mut tree_files(root, &rel_path)?);
+        } else {
+            let len = entry.metadata()?.len();
+            acc.push(TreeEntry::File(rel_path.into_boxed_path(), len));
+        }
+        Ok(acc)
     })
 }
+
+/// Recreates the symbolic link `src` at `dst`, replacing any link that is
+/// already there, so that copying a tree twice behaves like copying files.
+fn copy_link(src: &Path, dst: &Path) -> Result<(), SfwError> {
+    let target = fs::read_link(src).sfw_path_err("Couldn't read link", src)?;
+    if fs::symlink_metadata(dst).is_ok_and(|meta| meta.file_type().is_symlink())
+    {
+        fs::remove_file(dst).sfw_path_err("Couldn't replace link", dst)?;
+    }
+    symlink(src, &target, dst).sfw_path_err("Couldn't create link", dst)
+}
+
+#[cfg(unix)]
+fn symlink(_src: &Path, target: &Path, link: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(src: &Path, target: &Path, link: &Path) -> Result<(), Error> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+    if fs::metadata(src).is_ok_and(|meta| meta.is_dir()) {
+        symlink_dir(target, link)
+    } else {
+        symlink_file(target, link)
+    }
+}
+
+fn cp_file_go<F>(
+    src: &Path,
+    dst: &Path,
+    verify: bool,
+    progress: &mut CpProgress,
+    on_progress: &mut F,
+) -> Result<Crc32, SfwError>
+where
+    F: FnMut(&CpProgress),
+{
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
+    let mut f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
+    let mut f_out =
+        File::create(dst).sfw_path_err("Couldn't open destination", dst)?;
+
+    let src_crc =
+        f_in_iter.try_fold(Crc32::new(), |mut crc, b_slice_res| {
+            let b_slice = b_slice_res?;
+            if verify {
+                crc.update(&b_slice);
+            }
+            f_out
+                .write_all(&b_slice)
+                .sfw_path_err("Couldn't copy to destination", dst)?;
+            progress.bytes_copied += b_slice.len() as u64;
+            on_progress(progress);
+            Ok::<_, SfwError>(crc)
+        })?;
+    if verify {
+        verify_copy(src, dst, &f_out, src_crc)?;
+    }
+    progress.files_copied += 1;
+    on_progress(progress);
+    Ok(src_crc)
+}
+
+fn verify_copy(
+    src: &Path,
+    dst: &Path,
+    f_out: &File,
+    src_crc: Crc32,
+) -> Result<(), SfwError> {
+    f_out
+        .sync_all()
+        .sfw_path_err("Couldn't sync destination", dst)?;
+    let dst_crc = File::open(dst)
+        .and_then(crc32_reader)
+        .sfw_path_err("Couldn't re-read destination", dst)?;
+    if src_crc.value() == dst_crc {
+        Ok(())
+    } else {
+        let offset = first_diff_offset(File::open(src)?, File::open(dst)?)?;
+        Err(SfwError::Data {
+            msg: format!(
+                "verification failed: checksum {:08x} != {:08x}",
+                src_crc.value(),
+                dst_crc,
+            ),
+            path: Some(dst.to_path_buf()),
+            line: None,
+            offset,
+        })
+    }
+}
+
+/// How often the progress line on STDERR may be redrawn.
+const PROGRESS_REFRESH: Duration = Duration::from_millis(250);
+
+/// Renders `CpProgress` updates as a single, periodically refreshed
+/// line on STDERR. When STDERR isn't a terminal, only the final summary
+/// is written.
+pub struct ProgressDisplay {
+    start: Instant,
+    last_draw: Option<Instant>,
+    last_width: usize,
+    interactive: bool,
+}
+
+impl ProgressDisplay {
+    pub fn new(start: Instant) -> Self {
+        ProgressDisplay {
+            start,
+            last_draw: None,
+            last_width: 0,
+            interactive: io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn update(&mut self, progress: &CpProgress) {
+        if !self.interactive {
+            return;
+        }
+        let now = Instant::now();
+        let due = self
+            .last_draw
+            .is_none_or(|last| now.duration_since(last) >= PROGRESS_REFRESH);
+        if due {
+            self.last_draw = Some(now);
+            let line = progress_line(progress, now.duration_since(self.start));
+            let pad = self.last_width.saturating_sub(line.len());
+            eprint!("\r{}{}", line, " ".repeat(pad));
+            self.last_width = line.len();
+        }
+    }
+
+    pub fn finish(&mut self, progress: &CpProgress) {
+        let elapsed = self.start.elapsed();
+        let summary = format!(
+            "copied {} in {} file(s) in {:.2}s ({}/s)",
+            human_bytes(progress.bytes_copied),
+            progress.files_copied,
+            elapsed.as_secs_f64(),
+            human_bytes(bytes_per_sec(progress.bytes_copied, elapsed) as u64)
+        );
+        if self.interactive {
+            let pad = self.last_width.saturating_sub(summary.len());
+            eprintln!("\r{}{}", summary, " ".repeat(pad));
+        } else {
+            eprintln!("{}", summary);
+        }
+    }
+}
+
+fn bytes_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+pub fn progress_line(progress: &CpProgress, elapsed: Duration) -> String {
+    let rate = bytes_per_sec(progress.bytes_copied, elapsed);
+    let remaining = progress.bytes_total.saturating_sub(progress.bytes_copied);
+    let eta = if rate > 0.0 {
+        format!("{:.0}s", remaining as f64 / rate)
+    } else {
+        String::from("?")
+    };
+    let files = if progress.files_total > 1 {
+        format!(
+            " [{}/{} files]",
+            progress.files_copied, progress.files_total
+        )
+    } else {
+        String::from("")
+    };
+    format!(
+        "{} / {} ({:.1}%) {}/s ETA {}{}",
+        human_bytes(progress.bytes_copied),
+        human_bytes(progress.bytes_total),
+        progress.percent(),
+        human_bytes(rate as u64),
+        eta,
+        files
+    )
+}
+
+const BYTE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+pub fn human_bytes(bytes: u64) -> String {
+    let (value, unit) = BYTE_UNITS[1..].iter().fold(
+        (bytes as f64, BYTE_UNITS[0]),
+        |(value, unit), next| {
+            if value >= 1024.0 {
+                (value / 1024.0, *next)
+            } else {
+                (value, unit)
+            }
+        },
+    );
+    if unit == BYTE_UNITS[0] {
+        format!("{} {}", bytes, unit)
+    } else {
+        format!("{:.1} {}", value, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sfwtools-cp-{}.{}", name, process::id()))
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|ix| (ix % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_cp_verify() {
+        let src = temp_path("verify-src");
+        let dst = temp_path("verify-dst");
+        let data = test_data(3 * DEFAULT_BUF_SIZE + 17);
+        fs::write(&src, &data).unwrap();
+        let crc = cp_verify(src.to_str().unwrap(), dst.to_str().unwrap());
+        let copied = fs::read(&dst).unwrap();
+        fs::remove_file(&src).unwrap();
+        fs::remove_file(&dst).unwrap();
+        assert_eq!(crc.unwrap(), crc32_reader(&data[..]).unwrap());
+        assert_eq!(copied, data);
+    }
+
+    #[test]
+    fn test_verify_copy_reports_first_difference() {
+        let src = temp_path("corrupt-src");
+        let dst = temp_path("corrupt-dst");
+        let data = test_data(2 * DEFAULT_BUF_SIZE);
+        let mut corrupted = data.clone();
+        corrupted[DEFAULT_BUF_SIZE + 5] ^= 0xff;
+        fs::write(&src, &data).unwrap();
+        fs::write(&dst, &corrupted).unwrap();
+        let mut src_crc = Crc32::new();
+        src_crc.update(&data);
+        let result = File::open(&dst)
+            .map_err(SfwError::from)
+            .and_then(|f_out| verify_copy(&src, &dst, &f_out, src_crc));
+        fs::remove_file(&src).unwrap();
+        fs::remove_file(&dst).unwrap();
+        let expected = Some(DEFAULT_BUF_SIZE as u64 + 5);
+        assert!(matches!(
+            result,
+            Err(SfwError::Data { offset, .. }) if offset == expected
+        ));
+    }
+
+    #[test]
+    fn test_cp_with_recursive() {
+        let src = temp_path("tree-src");
+        let dst = temp_path("tree-dst");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("one"), "hello").unwrap();
+        fs::write(src.join("sub/two"), "abc").unwrap();
+        fs::write(src.join("sub/empty"), "").unwrap();
+        let (src_str, dst_str) = (src.to_str().unwrap(), dst.to_str().unwrap());
+        let refused = cp_with(src_str, dst_str, &CpConf::default(), |_| ());
+        let conf = CpConf {
+            recursive: true,
+            verify: true,
+        };
+        let mut seen = vec![];
+        let progress = cp_with(src_str, dst_str, &conf, |progress| {
+            seen.push((progress.bytes_copied, progress.files_copied))
+        });
+        let copied = fs::read_to_string(dst.join("sub/two"));
+        fs::remove_dir_all(&src).unwrap();
+        fs::remove_dir_all(&dst).unwrap();
+        assert!(matches!(refused, Err(SfwError::Usage(_))));
+        let progress = progress.unwrap();
+        assert_eq!((progress.files_total, progress.bytes_total), (3, 8));
+        assert_eq!((progress.files_copied, progress.bytes_copied), (3, 8));
+        // The totals first, then each chunk and each completed file, in
+        // name order: one, sub/empty, sub/two.
+        assert_eq!(seen, vec![(0, 0), (5, 0), (5, 1), (5, 2), (8, 2), (8, 3)]);
+        assert_eq!(copied.unwrap(), "abc");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cp_with_recursive_symlinks() {
+        use std::os::unix::fs::symlink;
+        let src = temp_path("links-src");
+        let dst = temp_path("links-dst");
+        fs::create_dir_all(src.join("real")).unwrap();
+        fs::write(src.join("real/file"), "data").unwrap();
+        symlink("real", src.join("dir-link")).unwrap();
+        symlink("real/file", src.join("file-link")).unwrap();
+        let (src_str, dst_str) = (src.to_str().unwrap(), dst.to_str().unwrap());
+        let conf = CpConf {
+            recursive: true,
+            verify: false,
+        };
+        let first = cp_with(src_str, dst_str, &conf, |_| ());
+        let second = cp_with(src_str, dst_str, &conf, |_| ());
+        let dir_link = fs::read_link(dst.join("dir-link"));
+        let file_link = fs::read_link(dst.join("file-link"));
+        let through_link = fs::read_to_string(dst.join("dir-link/file"));
+        fs::remove_dir_all(&src).unwrap();
+        fs::remove_dir_all(&dst).unwrap();
+        let progress = first.unwrap();
+        assert_eq!((progress.files_copied, progress.bytes_copied), (3, 4));
+        assert!(second.is_ok());
+        assert_eq!(dir_link.unwrap(), Path::new("real"));
+        assert_eq!(file_link.unwrap(), Path::new("real/file"));
+        assert_eq!(through_link.unwrap(), "data");
+    }
+
+    #[test]
+    fn test_progress_formatting() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(5 << 30), "5.0 GiB");
+        let progress = CpProgress {
+            bytes_copied: 512,
+            bytes_total: 2048,
+            files_copied: 1,
+            files_total: 2,
+        };
+        assert_eq!(
+            progress_line(&progress, Duration::from_secs(2)),
+            "512 B / 2.0 KiB (25.0%) 256 B/s ETA 6s [1/2 files]"
+        );
+        let single = CpProgress {
+            files_total: 1,
+            ..progress
+        };
+        assert_eq!(
+            progress_line(&single, Duration::ZERO),
+            "512 B / 2.0 KiB (25.0%) 0 B/s ETA ?"
+        );
+        assert_eq!(CpProgress::default().percent(), 100.0);
+    }
+}
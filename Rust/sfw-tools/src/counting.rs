@@ -1,5 +1,4 @@
 use std::fs::File;
-use std::io::Error;
 
 use fp_core::{empty::*, monoid::*, semigroup::*};
 use seahorse::{App, Command, Context, Flag, FlagType};
@@ -7,7 +6,7 @@ use seahorse::{App, Command, Context, Flag, FlagType};
 use crate::bytes_iter::BytesIter;
 use crate::constants::*;
 use crate::error::*;
-use crate::util::{is_newline, opt_as_empty_str};
+use crate::util::{is_newline, opt_as_empty_str, print_line};
 
 pub fn wc_app() -> App {
     App::new("wc")
@@ -79,19 +78,19 @@ pub fn run_wc_seahorse_action(ctxt: &Context) {
         }
         counts = build_counts;
     }
-    println!("{}", Counts::format(&counts));
+    print_line(&Counts::format(&counts)).user_err("Error in wc");
 }
 
 /// Convenience function for running wc in idiomatic fashion
 /// (i.e.) errors are printed to user and the program exits.
 pub fn run_wc_lines(src: &str) {
     let wc_res = wc_lines(src).user_err("Error in wc_lines");
-    println!("{}", Counts::format(&Counts::null().lines(wc_res)));
+    print_line(&Counts::format(&Counts::null().lines(wc_res)))
+        .user_err("Error in wc_lines");
 }
 
-pub fn wc_lines(src: &str) -> Result<usize, Error> {
-    let f_in =
-        File::open(src).sfw_err(&format!("Couldn't open source: {}", &src))?;
+pub fn wc_lines(src: &str) -> Result<usize, SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     wc_lines_file(&f_in)
 }
 
@@ -99,7 +98,7 @@ pub fn wc_lines(src: &str) -> Result<usize, Error> {
 /// hazards of boundary conditions in programming. Certainly this is still
 /// a problem in Rust, but using Rust's functional programming facilities,
 /// and types can help to greatly reduce the occurrence of such errors.
-pub fn wc_lines_file(f_in: &File) -> Result<usize, Error> {
+pub fn wc_lines_file(f_in: &File) -> Result<usize, SfwError> {
     BytesIter::new(f_in, DEFAULT_BUF_SIZE)
         .try_fold(0_usize, |ac_tot, b_slice| {
             Ok(ac_tot + num_newlines(&b_slice?))
@@ -110,16 +109,15 @@ pub fn wc_lines_file(f_in: &File) -> Result<usize, Error> {
 /// (i.e.) errors are printed to user and the program exits.
 pub fn run_wc_bytes(src: &str) {
     let wc_res = wc_bytes(src).user_err("Error in wc_bytes");
-    println!("{} {}", wc_res, &src);
+    print_line(&format!("{} {}", wc_res, &src)).user_err("Error in wc_bytes");
 }
 
-pub fn wc_bytes(src: &str) -> Result<usize, Error> {
-    let f_in =
-        File::open(src).sfw_err(&format!("Couldn't open source: {}", &src))?;
+pub fn wc_bytes(src: &str) -> Result<usize, SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     wc_bytes_file(&f_in)
 }
 
-pub fn wc_bytes_file(f_in: &File) -> Result<usize, Error> {
+pub fn wc_bytes_file(f_in: &File) -> Result<usize, SfwError> {
     BytesIter::new(f_in, DEFAULT_BUF_SIZE)
         .try_fold(0_usize, |ac_tot, b_slice| Ok(ac_tot + b_slice?.len()))
 }
@@ -128,16 +126,16 @@ pub fn wc_bytes_file(f_in: &File) -> Result<usize, Error> {
 /// (i.e.) errors are printed to user and the program exits.
 pub fn run_wc_words(src: &str) {
     let wc_res = wc_words(src).user_err("Error in wc_words");
-    println!("{}", Counts::format(&Counts::null().words(wc_res)));
+    print_line(&Counts::format(&Counts::null().words(wc_res)))
+        .user_err("Error in wc_words");
 }
 
-pub fn wc_words(src: &str) -> Result<usize, Error> {
-    let f_in =
-        File::open(src).sfw_err(&format!("Couldn't open source: {}", &src))?;
+pub fn wc_words(src: &str) -> Result<usize, SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     wc_words_file(&f_in)
 }
 
-pub fn wc_words_file(f_in: &File) -> Result<usize, Error> {
+pub fn wc_words_file(f_in: &File) -> Result<usize, SfwError> {
     BytesIter::new(f_in, DEFAULT_BUF_SIZE)
         .try_fold(0_usize, |ac_tot, b_slice| {
             Ok(ac_tot + word_count(b_slice?.as_slice()))
@@ -425,16 +423,15 @@ impl From<&[u8]> for FluxMay {
 /// (i.e.) errors are printed to user and the program exits.
 pub fn run_wc_all(src: &str) {
     let wc_res = wc_all(src).user_err("Error in wc_all");
-    println!("{}", Counts::format(&wc_res));
+    print_line(&Counts::format(&wc_res)).user_err("Error in wc_all");
 }
 
-pub fn wc_all(src: &str) -> Result<Counts, Error> {
-    let f_in =
-        File::open(src).sfw_err(&format!("Couldn't open source: {}", &src))?;
+pub fn wc_all(src: &str) -> Result<Counts, SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     wc_all_file(&f_in)
 }
 
-pub fn wc_all_file(f_in: &File) -> Result<Counts, Error> {
+pub fn wc_all_file(f_in: &File) -> Result<Counts, SfwError> {
     BytesIter::new(f_in, DEFAULT_BUF_SIZE)
         .try_fold(FluxEmpty, |flux_may, b_slice| {
             Ok(Semigroup::combine(
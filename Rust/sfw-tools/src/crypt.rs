@@ -0,0 +1,907 @@
+/*
+`crypt` from Software Tools is a symmetric filter: each byte is XORed with
+the next byte of the key, cycling through the key, so running the output
+through `crypt` again with the same key restores the input. This is kept
+for compatibility, but it is trivially broken and detects no tampering.
+
+The `--aead` mode is the modern alternative: a key is derived from the
+passphrase with PBKDF2-HMAC-SHA256, and the data is encrypted and
+authenticated with ChaCha20-Poly1305 (RFC 8439). The primitives are
+implemented here, as this crate avoids heavy dependencies; they are
+checked against the test vectors from their specifications.
+
+The AEAD stream format is a header followed by segments:
+
+  header:  MAGIC (8) | version (1) | PBKDF2 iterations (4, BE)
+           | salt (16) | nonce prefix (7)
+  segment: ciphertext (up to SEGMENT_SIZE) | tag (16)
+
+Each segment's nonce is the prefix, the segment number (4, BE) and a
+flag byte that is 1 only for the final segment, and the header is the
+associated data of every segment. Thus reordered, truncated or modified
+segments, and a modified header, all fail authentication. The final
+segment is always present, though it may be empty.
+ */
+
+use std::fs::File;
+use std::io::ErrorKind::{InvalidData, UnexpectedEof};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::checksum::*;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output};
+
+const CRYPT_USAGE: &str = r#"
+crypt [OPTION] KEY [SOURCE_FILE [DEST_FILE]]
+
+Without options, bytes are XORed with the KEY, as in Software Tools;
+applying crypt twice with the same KEY restores the original.
+
+Valid options are:
+--aead        encrypt with a key derived from the passphrase KEY, and
+              authenticate the data so that tampering is detected
+-d            with --aead, decrypt (and verify) instead of encrypting
+
+Note that KEY may be visible to other users of the system while crypt
+is running.
+"#;
+
+pub fn run_crypt_seahorse_cmd() -> Command {
+    Command::new("crypt")
+        .description(
+            "crypt: encrypt or decrypt with a key\
+            ; output to STDOUT is the default",
+        )
+        .usage(CRYPT_USAGE)
+        .action(run_crypt_seahorse_action)
+        .flag(
+            Flag::new("aead", FlagType::Bool)
+                .description("crypt --aead passphrase some_file"),
+        )
+        .flag(
+            Flag::new("decrypt", FlagType::Bool)
+                .alias("d")
+                .description("crypt --aead -d passphrase some_file"),
+        )
+}
+
+pub fn run_crypt_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let key = args.next().user_err("crypt: missing KEY");
+    let f_in =
+        open_input(args.next().map(String::as_str)).user_err("Error in crypt");
+    let f_out =
+        open_output(args.next().map(String::as_str)).user_err("Error in crypt");
+    match (ctxt.bool_flag("aead"), ctxt.bool_flag("decrypt")) {
+        (false, true) => user_exit(SfwError::usage(
+            "crypt: -d is only meaningful with --aead",
+        )),
+        (false, false) => run_crypt(key.as_bytes(), f_in, f_out),
+        (true, false) => aead_encrypt(key.as_bytes(), f_in, f_out)
+            .user_err("Error in crypt --aead"),
+        (true, true) => aead_decrypt(key.as_bytes(), f_in, f_out)
+            .user_err("Error in crypt --aead -d"),
+    }
+}
+
+/// Convenience function for running crypt in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_crypt(key: &[u8], src: Box<dyn Read>, dst: Box<dyn Write>) {
+    crypt(key, src, dst).user_err("Error in crypt");
+}
+
+/// The book's `crypt`: XOR with a repeating key. Symmetric, so it both
+/// encrypts and decrypts.
+pub fn crypt<R: Read, W: Write>(
+    key: &[u8],
+    f_in: R,
+    f_out: W,
+) -> Result<(), SfwError> {
+    let mut xor_out = XorWriter::new(key, f_out)?;
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+        .try_for_each(|b_slice| xor_out.write_all(&b_slice?))?;
+    xor_out.flush().sfw_err("Couldn't flush output")
+}
+
+fn check_key(key: &[u8]) -> Result<(), SfwError> {
+    if key.is_empty() {
+        Err(SfwError::usage("crypt: KEY must not be empty"))
+    } else {
+        Ok(())
+    }
+}
+
+fn xor_key(key: &[u8], pos: usize, b_slice: &mut [u8]) -> usize {
+    b_slice
+        .iter_mut()
+        .zip(key.iter().cycle().skip(pos))
+        .for_each(|(bt, kk)| *bt ^= kk);
+    (pos + b_slice.len()) % key.len()
+}
+
+/// A `Read` adapter that applies the book's XOR `crypt` to the data read.
+pub struct XorReader<R: Read> {
+    inner: R,
+    key: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> XorReader<R> {
+    pub fn new(key: &[u8], inner: R) -> Result<Self, SfwError> {
+        check_key(key)?;
+        Ok(XorReader {
+            inner,
+            key: key.to_vec(),
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let nn = self.inner.read(buf)?;
+        self.pos = xor_key(&self.key, self.pos, &mut buf[..nn]);
+        Ok(nn)
+    }
+}
+
+/// A `Write` adapter that applies the book's XOR `crypt` to the data
+/// before passing it on.
+pub struct XorWriter<W: Write> {
+    inner: W,
+    key: Vec<u8>,
+    pos: usize,
+}
+
+impl<W: Write> XorWriter<W> {
+    pub fn new(key: &[u8], inner: W) -> Result<Self, SfwError> {
+        check_key(key)?;
+        Ok(XorWriter {
+            inner,
+            key: key.to_vec(),
+            pos: 0,
+        })
+    }
+}
+
+impl<W: Write> Write for XorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = buf.to_vec();
+        self.pos = xor_key(&self.key, self.pos, &mut out);
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+//
+// ChaCha20, Poly1305 and their AEAD combination, per RFC 8439.
+//
+
+pub const AEAD_KEY_SIZE: usize = 32;
+pub const AEAD_NONCE_SIZE: usize = 12;
+pub const AEAD_TAG_SIZE: usize = 16;
+
+const CHACHA_CONSTANTS: [u32; 4] =
+    [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+const CHACHA_BLOCK_SIZE: usize = 64;
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn quarter_round(st: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    st[a] = st[a].wrapping_add(st[b]);
+    st[d] = (st[d] ^ st[a]).rotate_left(16);
+    st[c] = st[c].wrapping_add(st[d]);
+    st[b] = (st[b] ^ st[c]).rotate_left(12);
+    st[a] = st[a].wrapping_add(st[b]);
+    st[d] = (st[d] ^ st[a]).rotate_left(8);
+    st[c] = st[c].wrapping_add(st[d]);
+    st[b] = (st[b] ^ st[c]).rotate_left(7);
+}
+
+pub fn chacha20_block(
+    key: &[u8; AEAD_KEY_SIZE],
+    counter: u32,
+    nonce: &[u8; AEAD_NONCE_SIZE],
+) -> [u8; CHACHA_BLOCK_SIZE] {
+    let mut state = [0_u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    key.chunks(4)
+        .enumerate()
+        .for_each(|(ii, word)| state[4 + ii] = le_u32(word));
+    state[12] = counter;
+    nonce
+        .chunks(4)
+        .enumerate()
+        .for_each(|(ii, word)| state[13 + ii] = le_u32(word));
+    let mut working = state;
+    (0..10).for_each(|_| {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    });
+    let mut out = [0_u8; CHACHA_BLOCK_SIZE];
+    out.chunks_mut(4).enumerate().for_each(|(ii, bytes)| {
+        bytes
+            .copy_from_slice(&working[ii].wrapping_add(state[ii]).to_le_bytes())
+    });
+    out
+}
+
+pub fn chacha20_xor(
+    key: &[u8; AEAD_KEY_SIZE],
+    counter: u32,
+    nonce: &[u8; AEAD_NONCE_SIZE],
+    data: &mut [u8],
+) {
+    data.chunks_mut(CHACHA_BLOCK_SIZE)
+        .enumerate()
+        .for_each(|(ii, chunk)| {
+            let stream =
+                chacha20_block(key, counter.wrapping_add(ii as u32), nonce);
+            chunk
+                .iter_mut()
+                .zip(stream.iter())
+                .for_each(|(bt, ks)| *bt ^= ks);
+        });
+}
+
+const POLY_MASK: u32 = 0x3ff_ffff;
+
+/// Poly1305 in 26-bit limbs, after Andrew Moon's poly1305-donna.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        Poly1305 {
+            r: [
+                le_u32(&key[0..]) & 0x3ff_ffff,
+                (le_u32(&key[3..]) >> 2) & 0x3ff_ff03,
+                (le_u32(&key[6..]) >> 4) & 0x3ff_c0ff,
+                (le_u32(&key[9..]) >> 6) & 0x3f0_3fff,
+                (le_u32(&key[12..]) >> 8) & 0x00f_ffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le_u32(&key[16..]),
+                le_u32(&key[20..]),
+                le_u32(&key[24..]),
+                le_u32(&key[28..]),
+            ],
+        }
+    }
+
+    /// Absorbs a message, zero-padded to a multiple of 16 bytes; this is
+    /// how RFC 8439's AEAD construction feeds each of its parts.
+    fn update_padded(&mut self, msg: &[u8]) {
+        msg.chunks(16).for_each(|chunk| {
+            let mut block = [0_u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.block(&block, 1 << 24);
+        });
+    }
+
+    fn block(&mut self, msg: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(u64::from);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        h[0] += le_u32(&msg[0..]) & POLY_MASK;
+        h[1] += (le_u32(&msg[3..]) >> 2) & POLY_MASK;
+        h[2] += (le_u32(&msg[6..]) >> 4) & POLY_MASK;
+        h[3] += (le_u32(&msg[9..]) >> 6) & POLY_MASK;
+        h[4] += (le_u32(&msg[12..]) >> 8) | hibit;
+        let [h0, h1, h2, h3, h4] = h.map(u64::from);
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+        let mask = u64::from(POLY_MASK);
+        let d1 = d1 + (d0 >> 26);
+        let d2 = d2 + (d1 >> 26);
+        let d3 = d3 + (d2 >> 26);
+        let d4 = d4 + (d3 >> 26);
+        let h0 = (d0 & mask) + (d4 >> 26) * 5;
+        *h = [
+            (h0 & mask) as u32,
+            ((d1 & mask) + (h0 >> 26)) as u32,
+            (d2 & mask) as u32,
+            (d3 & mask) as u32,
+            (d4 & mask) as u32,
+        ];
+    }
+
+    fn finalize(self) -> [u8; AEAD_TAG_SIZE] {
+        let mut h = self.h;
+        // Fully carry h
+        let mut carry = h[1] >> 26;
+        h[1] &= POLY_MASK;
+        (2..5).for_each(|ii| {
+            h[ii] += carry;
+            carry = h[ii] >> 26;
+            h[ii] &= POLY_MASK;
+        });
+        h[0] += carry * 5;
+        carry = h[0] >> 26;
+        h[0] &= POLY_MASK;
+        h[1] += carry;
+
+        // Compute h + -p, and select it if h >= p
+        let mut g = [0_u32; 5];
+        g[0] = h[0] + 5;
+        carry = g[0] >> 26;
+        g[0] &= POLY_MASK;
+        (1..4).for_each(|ii| {
+            g[ii] = h[ii] + carry;
+            carry = g[ii] >> 26;
+            g[ii] &= POLY_MASK;
+        });
+        g[4] = h[4].wrapping_add(carry).wrapping_sub(1 << 26);
+        let select_g = (g[4] >> 31).wrapping_sub(1);
+        (0..5).for_each(|ii| h[ii] = (h[ii] & !select_g) | (g[ii] & select_g));
+
+        // h = (h + pad) mod 2^128
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0_u8; AEAD_TAG_SIZE];
+        words.iter().zip(self.pad.iter()).enumerate().fold(
+            0_u64,
+            |carry, (ii, (word, pad))| {
+                let sum = u64::from(*word) + u64::from(*pad) + carry;
+                tag[4 * ii..4 * ii + 4]
+                    .copy_from_slice(&(sum as u32).to_le_bytes());
+                sum >> 32
+            },
+        );
+        tag
+    }
+}
+
+pub fn poly1305(key: &[u8; 32], msg: &[u8]) -> [u8; AEAD_TAG_SIZE] {
+    let mut mac = Poly1305::new(key);
+    msg.chunks(16).for_each(|chunk| {
+        let mut block = [0_u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        if chunk.len() == 16 {
+            mac.block(&block, 1 << 24);
+        } else {
+            block[chunk.len()] = 1;
+            mac.block(&block, 0);
+        }
+    });
+    mac.finalize()
+}
+
+fn aead_tag(
+    key: &[u8; AEAD_KEY_SIZE],
+    nonce: &[u8; AEAD_NONCE_SIZE],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; AEAD_TAG_SIZE] {
+    let mut otk = [0_u8; 32];
+    otk.copy_from_slice(&chacha20_block(key, 0, nonce)[..32]);
+    let mut mac = Poly1305::new(&otk);
+    mac.update_padded(aad);
+    mac.update_padded(ciphertext);
+    let mut lengths = [0_u8; 16];
+    lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    mac.block(&lengths, 1 << 24);
+    mac.finalize()
+}
+
+/// Encrypts `data` in place and returns the authentication tag.
+pub fn aead_seal(
+    key: &[u8; AEAD_KEY_SIZE],
+    nonce: &[u8; AEAD_NONCE_SIZE],
+    aad: &[u8],
+    data: &mut [u8],
+) -> [u8; AEAD_TAG_SIZE] {
+    chacha20_xor(key, 1, nonce, data);
+    aead_tag(key, nonce, aad, data)
+}
+
+/// Verifies the tag and, only if it matches, decrypts `data` in place.
+pub fn aead_open(
+    key: &[u8; AEAD_KEY_SIZE],
+    nonce: &[u8; AEAD_NONCE_SIZE],
+    aad: &[u8],
+    data: &mut [u8],
+    tag: &[u8],
+) -> Result<(), SfwError> {
+    let expected = aead_tag(key, nonce, aad, data);
+    // Compare in constant time, so as not to leak how much of a forged
+    // tag was correct.
+    let diff = expected
+        .iter()
+        .zip(tag.iter())
+        .fold(0_u8, |acc, (aa, bb)| acc | (aa ^ bb));
+    if diff == 0 && tag.len() == AEAD_TAG_SIZE {
+        chacha20_xor(key, 1, nonce, data);
+        Ok(())
+    } else {
+        Err(SfwError::data("authentication failed"))
+    }
+}
+
+//
+// Key derivation
+//
+
+struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    fn new(key: &[u8]) -> Self {
+        let mut block_key = [0_u8; 64];
+        if key.len() > 64 {
+            block_key[..SHA256_SIZE].copy_from_slice(&sha256(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+        let mut inner = Sha256::new();
+        inner.update(&block_key.map(|bt| bt ^ 0x36));
+        let mut outer = Sha256::new();
+        outer.update(&block_key.map(|bt| bt ^ 0x5c));
+        HmacSha256 { inner, outer }
+    }
+
+    fn mac(&self, msg: &[u8]) -> [u8; SHA256_SIZE] {
+        let mut inner = self.inner.clone();
+        inner.update(msg);
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finalize());
+        outer.finalize()
+    }
+}
+
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; SHA256_SIZE] {
+    HmacSha256::new(key).mac(msg)
+}
+
+/// PBKDF2 (RFC 8018) with HMAC-SHA256, producing a single 32-byte block.
+pub fn pbkdf2_sha256(
+    passphrase: &[u8],
+    salt: &[u8],
+    iterations: u32,
+) -> [u8; SHA256_SIZE] {
+    let hmac = HmacSha256::new(passphrase);
+    let first = hmac.mac(&[salt, &1_u32.to_be_bytes()].concat());
+    let (_, key) =
+        (1..iterations).fold((first, first), |(prev, mut key), _| {
+            let next = hmac.mac(&prev);
+            key.iter_mut()
+                .zip(next.iter())
+                .for_each(|(kk, nn)| *kk ^= nn);
+            (next, key)
+        });
+    key
+}
+
+//
+// The AEAD stream format
+//
+
+pub const AEAD_MAGIC: &[u8; 8] = b"SFWCRYPT";
+pub const AEAD_VERSION: u8 = 1;
+pub const AEAD_ITERATIONS: u32 = 100_000;
+/// The most PBKDF2 iterations accepted from a header, which is read
+/// before anything is authenticated, so as to bound the work a crafted
+/// stream can demand.
+const MAX_AEAD_ITERATIONS: u32 = 10 * AEAD_ITERATIONS;
+const SALT_SIZE: usize = 16;
+const NONCE_PREFIX_SIZE: usize = 7;
+const HEADER_SIZE: usize = 8 + 1 + 4 + SALT_SIZE + NONCE_PREFIX_SIZE;
+/// Plaintext bytes per segment.
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+
+#[cfg(unix)]
+fn random_bytes(buf: &mut [u8]) -> Result<(), SfwError> {
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(buf))
+        .sfw_err("Couldn't read random bytes")
+}
+
+#[cfg(not(unix))]
+fn random_bytes(_buf: &mut [u8]) -> Result<(), SfwError> {
+    Err(SfwError::usage(
+        "crypt --aead: no random source on this platform",
+    ))
+}
+
+struct AeadStream {
+    key: [u8; AEAD_KEY_SIZE],
+    header: [u8; HEADER_SIZE],
+    segment: u32,
+}
+
+impl AeadStream {
+    fn from_header(
+        passphrase: &[u8],
+        header: [u8; HEADER_SIZE],
+    ) -> Result<Self, SfwError> {
+        if &header[..8] != AEAD_MAGIC {
+            return Err(SfwError::data("not an sfwtools AEAD stream"));
+        }
+        if header[8] != AEAD_VERSION {
+            return Err(SfwError::data(format!(
+                "unsupported AEAD stream version {}",
+                header[8]
+            )));
+        }
+        let iterations =
+            u32::from_be_bytes([header[9], header[10], header[11], header[12]]);
+        if iterations == 0 || iterations > MAX_AEAD_ITERATIONS {
+            return Err(SfwError::data(format!(
+                "invalid PBKDF2 iteration count {} (the most allowed is {})",
+                iterations, MAX_AEAD_ITERATIONS
+            )));
+        }
+        let salt = &header[13..13 + SALT_SIZE];
+        Ok(AeadStream {
+            key: pbkdf2_sha256(passphrase, salt, iterations),
+            header,
+            segment: 0,
+        })
+    }
+
+    fn nonce(&self, last: bool) -> [u8; AEAD_NONCE_SIZE] {
+        let mut nonce = [0_u8; AEAD_NONCE_SIZE];
+        nonce[..NONCE_PREFIX_SIZE]
+            .copy_from_slice(&self.header[HEADER_SIZE - NONCE_PREFIX_SIZE..]);
+        nonce[NONCE_PREFIX_SIZE..11]
+            .copy_from_slice(&self.segment.to_be_bytes());
+        nonce[11] = u8::from(last);
+        nonce
+    }
+
+    fn next_segment(&mut self) -> Result<(), SfwError> {
+        self.segment = self.segment.checked_add(1).ok_or_else(|| {
+            SfwError::data("too many segments in AEAD stream")
+        })?;
+        Ok(())
+    }
+}
+
+/// A `Write` adapter that encrypts and authenticates everything written
+/// to it. `finish` must be called to write the final segment; otherwise,
+/// a reader will reject the stream as truncated.
+pub struct AeadWriter<W: Write> {
+    inner: Option<W>,
+    stream: AeadStream,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> AeadWriter<W> {
+    pub fn new(passphrase: &[u8], mut inner: W) -> Result<Self, SfwError> {
+        check_key(passphrase)?;
+        let mut header = [0_u8; HEADER_SIZE];
+        header[..8].copy_from_slice(AEAD_MAGIC);
+        header[8] = AEAD_VERSION;
+        header[9..13].copy_from_slice(&AEAD_ITERATIONS.to_be_bytes());
+        random_bytes(&mut header[13..])?;
+        let stream = AeadStream::from_header(passphrase, header)?;
+        inner.write_all(&header)?;
+        Ok(AeadWriter {
+            inner: Some(inner),
+            stream,
+            buf: Vec::with_capacity(SEGMENT_SIZE),
+        })
+    }
+
+    fn write_segment(&mut self, last: bool) -> io::Result<()> {
+        let nonce = self.stream.nonce(last);
+        let tag = aead_seal(
+            &self.stream.key,
+            &nonce,
+            &self.stream.header,
+            &mut self.buf,
+        );
+        let inner = self.inner.as_mut().ok_or_else(|| {
+            io::Error::new(InvalidData, "AEAD stream already finished")
+        })?;
+        inner.write_all(&self.buf)?;
+        inner.write_all(&tag)?;
+        self.buf.clear();
+        Ok(self.stream.next_segment()?)
+    }
+
+    /// Writes the final segment and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, SfwError> {
+        self.write_segment(true)?;
+        let mut inner =
+            self.inner.take().sfw_err("AEAD stream already finished")?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for AeadWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full segment is only written once more data arrives, since
+        // the final segment must be flagged as such.
+        if self.buf.len() == SEGMENT_SIZE && !buf.is_empty() {
+            self.write_segment(false)?;
+        }
+        let nn = buf.len().min(SEGMENT_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..nn]);
+        Ok(nn)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A `Read` adapter that decrypts a stream written by `AeadWriter`.
+/// Data is only returned once the segment containing it has been
+/// authenticated, and a missing final segment is reported as an error.
+pub struct AeadReader<R: Read> {
+    inner: BufReader<R>,
+    stream: AeadStream,
+    plain: Vec<u8>,
+    plain_pos: usize,
+    done: bool,
+}
+
+impl<R: Read> AeadReader<R> {
+    pub fn new(passphrase: &[u8], inner: R) -> Result<Self, SfwError> {
+        check_key(passphrase)?;
+        let mut inner =
+            BufReader::with_capacity(SEGMENT_SIZE + AEAD_TAG_SIZE, inner);
+        let mut header = [0_u8; HEADER_SIZE];
+        inner
+            .read_exact(&mut header)
+            .map_err(|err| match err.kind() {
+                UnexpectedEof => {
+                    SfwError::data("AEAD stream header is truncated")
+                }
+                _ => SfwError::from(err),
+            })?;
+        Ok(AeadReader {
+            inner,
+            stream: AeadStream::from_header(passphrase, header)?,
+            plain: vec![],
+            plain_pos: 0,
+            done: false,
+        })
+    }
+
+    fn read_segment(&mut self) -> Result<(), SfwError> {
+        let offset = HEADER_SIZE as u64
+            + u64::from(self.stream.segment)
+                * (SEGMENT_SIZE + AEAD_TAG_SIZE) as u64;
+        let mut sealed = Vec::with_capacity(SEGMENT_SIZE + AEAD_TAG_SIZE);
+        (&mut self.inner)
+            .take((SEGMENT_SIZE + AEAD_TAG_SIZE) as u64)
+            .read_to_end(&mut sealed)?;
+        let last = self.inner.fill_buf()?.is_empty();
+        if sealed.len() < AEAD_TAG_SIZE {
+            return Err(SfwError::data_at("AEAD stream is truncated", offset));
+        }
+        let tag = sealed.split_off(sealed.len() - AEAD_TAG_SIZE);
+        let nonce = self.stream.nonce(last);
+        aead_open(
+            &self.stream.key,
+            &nonce,
+            &self.stream.header,
+            &mut sealed,
+            &tag,
+        )
+        .map_err(|_| {
+            SfwError::data_at(
+                "authentication failed: wrong passphrase, \
+                     or the data was truncated or modified",
+                offset,
+            )
+        })?;
+        self.stream.next_segment()?;
+        self.plain = sealed;
+        self.plain_pos = 0;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for AeadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.plain_pos == self.plain.len() && !self.done {
+            self.read_segment()?;
+        }
+        let nn = buf.len().min(self.plain.len() - self.plain_pos);
+        buf[..nn]
+            .copy_from_slice(&self.plain[self.plain_pos..self.plain_pos + nn]);
+        self.plain_pos += nn;
+        Ok(nn)
+    }
+}
+
+pub fn aead_encrypt<R: Read, W: Write>(
+    passphrase: &[u8],
+    f_in: R,
+    f_out: W,
+) -> Result<(), SfwError> {
+    let mut aead_out = AeadWriter::new(passphrase, f_out)?;
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+        .try_for_each(|b_slice| aead_out.write_all(&b_slice?))?;
+    aead_out.finish().map(|_| ())
+}
+
+/// Decrypts and verifies a stream written by `aead_encrypt`. Since data
+/// is written out a segment at a time, a stream that fails verification
+/// part way through may already have had some (verified) output written.
+pub fn aead_decrypt<R: Read, W: Write>(
+    passphrase: &[u8],
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let aead_in = AeadReader::new(passphrase, f_in)?;
+    BytesIter::new(aead_in, SEGMENT_SIZE).try_for_each(|b_slice| {
+        Ok::<_, SfwError>(f_out.write_all(&b_slice?)?)
+    })?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|ii| u8::from_str_radix(&hex[ii..ii + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_xor_crypt_roundtrip() {
+        let mut secret = vec![];
+        crypt(b"key", &b"attack at dawn"[..], &mut secret).unwrap();
+        assert_ne!(secret, b"attack at dawn");
+        let mut plain = vec![];
+        let mut reader = XorReader::new(b"key", &secret[..]).unwrap();
+        reader.read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, b"attack at dawn");
+    }
+
+    #[test]
+    fn test_poly1305_rfc8439() {
+        let mut key = [0_u8; 32];
+        key.copy_from_slice(&from_hex(
+            "85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b",
+        ));
+        let tag = poly1305(&key, b"Cryptographic Forum Research Group");
+        assert_eq!(to_hex(&tag), "a8061dc1305136c6c22b8baf0c0127a9");
+    }
+
+    #[test]
+    fn test_aead_rfc8439() {
+        let mut key = [0_u8; AEAD_KEY_SIZE];
+        key.copy_from_slice(&from_hex(
+            "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        ));
+        let mut nonce = [0_u8; AEAD_NONCE_SIZE];
+        nonce.copy_from_slice(&from_hex("070000004041424344454647"));
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let plain = b"Ladies and Gentlemen of the class of '99: If I could \
+            offer you only one tip for the future, sunscreen would be it.";
+        let mut data = plain.to_vec();
+        let tag = aead_seal(&key, &nonce, &aad, &mut data);
+        assert_eq!(to_hex(&data[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(to_hex(&tag), "1ae10b594f09e26a7e902ecbd0600691");
+        aead_open(&key, &nonce, &aad, &mut data, &tag).unwrap();
+        assert_eq!(data, plain.to_vec());
+    }
+
+    #[test]
+    fn test_pbkdf2_sha256() {
+        assert_eq!(
+            to_hex(&pbkdf2_sha256(b"password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+    }
+
+    #[test]
+    fn test_aead_stream_roundtrip_and_tamper() {
+        let plain = (0..SEGMENT_SIZE * 2 + 10)
+            .map(|ii| (ii % 251) as u8)
+            .collect::<Vec<u8>>();
+        let mut sealed = vec![];
+        aead_encrypt(b"hunter2", &plain[..], &mut sealed).unwrap();
+        let mut opened = vec![];
+        aead_decrypt(b"hunter2", &sealed[..], &mut opened).unwrap();
+        assert_eq!(opened, plain);
+
+        let mut tampered = sealed.clone();
+        tampered[HEADER_SIZE + 5] ^= 1;
+        let res = aead_decrypt(b"hunter2", &tampered[..], &mut vec![]);
+        assert_eq!(res.unwrap_err().exit_code(), EXIT_DATAERR);
+
+        let truncated = &sealed[..HEADER_SIZE + SEGMENT_SIZE + AEAD_TAG_SIZE];
+        assert!(aead_decrypt(b"hunter2", truncated, &mut vec![]).is_err());
+        assert!(aead_decrypt(b"hunter3", &sealed[..], &mut vec![]).is_err());
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("device error"))
+        }
+    }
+
+    #[test]
+    fn test_aead_io_errors_are_not_data_errors() {
+        let plain = vec![7_u8; SEGMENT_SIZE * 2];
+        let mut sealed = vec![];
+        aead_encrypt(b"hunter2", &plain[..], &mut sealed).unwrap();
+        [4, HEADER_SIZE + SEGMENT_SIZE + 10]
+            .iter()
+            .for_each(|&len| {
+                let f_in = (&sealed[..len]).chain(FailingReader);
+                let err =
+                    aead_decrypt(b"hunter2", f_in, &mut vec![]).unwrap_err();
+                assert_eq!(
+                    err.io_kind(),
+                    Some(io::ErrorKind::Other),
+                    "{}",
+                    err
+                );
+                assert_ne!(err.exit_code(), EXIT_DATAERR);
+            });
+        let err =
+            aead_decrypt(b"hunter2", &sealed[..4], &mut vec![]).unwrap_err();
+        assert!(matches!(err, SfwError::Data { .. }), "{}", err);
+        let mut tampered = sealed.clone();
+        tampered[HEADER_SIZE + SEGMENT_SIZE + 20] ^= 1;
+        let err = aead_decrypt(b"hunter2", &tampered[..], &mut vec![]);
+        let offset = (HEADER_SIZE + SEGMENT_SIZE + AEAD_TAG_SIZE) as u64;
+        assert!(matches!(
+            err,
+            Err(SfwError::Data { offset: Some(at), .. }) if at == offset
+        ));
+    }
+
+    #[test]
+    fn test_aead_rejects_excessive_iterations() {
+        let mut sealed = vec![];
+        aead_encrypt(b"hunter2", &b"secret"[..], &mut sealed).unwrap();
+        [0, MAX_AEAD_ITERATIONS + 1, u32::MAX]
+            .iter()
+            .for_each(|count| {
+                let mut crafted = sealed.clone();
+                crafted[9..13].copy_from_slice(&count.to_be_bytes());
+                let err = aead_decrypt(b"hunter2", &crafted[..], &mut vec![])
+                    .unwrap_err();
+                assert_eq!(err.exit_code(), EXIT_DATAERR);
+                assert!(err.to_string().contains("iteration count"));
+            });
+    }
+}
@@ -0,0 +1,984 @@
+/*
+The book's `compare` only matches up lines at the same position, so a
+single inserted line makes every following line differ. `diff` instead
+finds a shortest edit script: the fewest line deletions and insertions
+that turn the old file into the new one, using Myers' O(ND) algorithm
+("An O(ND) Difference Algorithm and Its Variations", 1986).
+
+The basic greedy algorithm records its progress for every edit distance
+D, which takes O(D^2) space. For very different inputs, this would be
+too large, so we switch to the paper's linear-space refinement, which
+finds the middle "snake" of an optimal path by searching from both ends
+at once, and then recurses on either side of it.
+
+The edit script is shown in one of three formats: unified (as used by
+`patch` and most code review tools), context, or an `ed` script.
+ */
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::process;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{is_newline, open_input, opt_usize_flag};
+
+/// A single step of an edit script, referring to lines by their
+/// (0-based) index in the old or new file.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Edit {
+    Keep { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+impl Edit {
+    pub fn is_change(&self) -> bool {
+        !matches!(self, Edit::Keep { .. })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DiffFormat {
+    Unified,
+    Context,
+    Ed,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DiffConf {
+    pub format: DiffFormat,
+    /// Lines of unchanged context shown around each change.
+    pub context: usize,
+    /// Treat all runs of whitespace as equal, and ignore trailing
+    /// whitespace.
+    pub ignore_space_change: bool,
+    /// Ignore whitespace entirely when comparing lines.
+    pub ignore_all_space: bool,
+    /// Don't report changes that only insert or delete blank lines.
+    pub ignore_blank_lines: bool,
+}
+
+impl Default for DiffConf {
+    fn default() -> Self {
+        DiffConf {
+            format: DiffFormat::Unified,
+            context: 3,
+            ignore_space_change: false,
+            ignore_all_space: false,
+            ignore_blank_lines: false,
+        }
+    }
+}
+
+/// The lines of a file being compared.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DiffText {
+    pub name: String,
+    /// Lines without their newlines.
+    pub lines: Vec<Vec<u8>>,
+    /// True if the last line isn't followed by a newline.
+    pub unterminated: bool,
+}
+
+impl DiffText {
+    pub fn read<R: Read>(name: &str, f_in: R) -> Result<Self, SfwError> {
+        let (mut lines, last) = BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+            .try_fold((vec![], vec![]), |(mut lines, mut line), b_slice| {
+                b_slice?.into_iter().for_each(|byte| {
+                    if is_newline(byte) {
+                        lines.push(std::mem::take(&mut line));
+                    } else {
+                        line.push(byte);
+                    }
+                });
+                Ok::<_, io::Error>((lines, line))
+            })
+            .sfw_path_err("Couldn't read", name)?;
+        let unterminated = !last.is_empty();
+        if unterminated {
+            lines.push(last);
+        }
+        Ok(DiffText {
+            name: name.to_string(),
+            lines,
+            unterminated,
+        })
+    }
+
+    fn is_unterminated(&self, index: usize) -> bool {
+        self.unterminated && index + 1 == self.lines.len()
+    }
+}
+
+const DIFF_USAGE: &str = r#"
+diff [OPTION]... FILE1 FILE2
+
+Shows how to change FILE1 into FILE2, by default as a unified diff with
+3 lines of context. Either file may be - for STDIN.
+Exits with status 0 if the files are the same, 1 if they differ, and 2
+if there was trouble.
+
+Valid options are:
+-u N, -U N    unified format, with N lines of context
+-c N, -C N    context format, with N lines of context
+-e            an ed script
+-b            ignore changes in the amount of whitespace
+-w            ignore all whitespace
+-B            ignore changes that only insert or delete blank lines
+
+"#;
+
+pub fn run_diff_seahorse_cmd() -> Command {
+    Command::new("diff")
+        .description(
+            "diff: show the differences between two files\
+            ; output to STDOUT",
+        )
+        .usage(DIFF_USAGE)
+        .action(run_diff_seahorse_action)
+        .flag(
+            Flag::new("unified", FlagType::Int)
+                .alias("u")
+                .alias("U")
+                .description("diff -u 3 file1 file2"),
+        )
+        .flag(
+            Flag::new("context", FlagType::Int)
+                .alias("c")
+                .alias("C")
+                .description("diff -c 3 file1 file2"),
+        )
+        .flag(
+            Flag::new("ed", FlagType::Bool)
+                .alias("e")
+                .description("diff -e file1 file2"),
+        )
+        .flag(
+            Flag::new("ignore-space-change", FlagType::Bool)
+                .alias("b")
+                .description("diff -b file1 file2"),
+        )
+        .flag(
+            Flag::new("ignore-all-space", FlagType::Bool)
+                .alias("w")
+                .description("diff -w file1 file2"),
+        )
+        .flag(
+            Flag::new("ignore-blank-lines", FlagType::Bool)
+                .alias("B")
+                .description("diff -B file1 file2"),
+        )
+}
+
+pub fn run_diff_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let name1 = args.next().user_err("diff: missing FILE1");
+    let name2 = args.next().user_err("diff: missing FILE2");
+    let unified = opt_usize_flag(ctxt, "unified").user_err("Error in diff");
+    let context = opt_usize_flag(ctxt, "context").user_err("Error in diff");
+    let (format, context) = match (unified, context, ctxt.bool_flag("ed")) {
+        (_, None, false) => (DiffFormat::Unified, unified),
+        (None, Some(lines), false) => (DiffFormat::Context, Some(lines)),
+        (None, None, true) => (DiffFormat::Ed, None),
+        _ => user_exit(SfwError::usage(
+            "diff: only one of -u, -c and -e may be given",
+        )),
+    };
+    let conf = DiffConf {
+        format,
+        context: context.unwrap_or(DiffConf::default().context),
+        ignore_space_change: ctxt.bool_flag("ignore-space-change"),
+        ignore_all_space: ctxt.bool_flag("ignore-all-space"),
+        ignore_blank_lines: ctxt.bool_flag("ignore-blank-lines"),
+    };
+    run_diff(name1, name2, &conf);
+}
+
+/// Convenience function for running diff in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Otherwise, the program exits with status 0 for identical files
+/// and 1 for files that differ.
+pub fn run_diff(name1: &str, name2: &str, conf: &DiffConf) {
+    let f_in1 =
+        open_input(Some(name1)).user_err_status("Error in diff", EXIT_TROUBLE);
+    let f_in2 =
+        open_input(Some(name2)).user_err_status("Error in diff", EXIT_TROUBLE);
+    let same = diff((name1, f_in1), (name2, f_in2), conf, io::stdout())
+        .user_err_status("Error in diff", EXIT_TROUBLE);
+    process::exit(if same { EXIT_SUCCESS } else { EXIT_FAILURE })
+}
+
+/// Writes the differences between two named inputs in the configured
+/// format, returning `true` if no differences were found.
+pub fn diff<R1, R2, W>(
+    (name1, f_in1): (&str, R1),
+    (name2, f_in2): (&str, R2),
+    conf: &DiffConf,
+    mut f_out: W,
+) -> Result<bool, SfwError>
+where
+    R1: Read,
+    R2: Read,
+    W: Write,
+{
+    let old = DiffText::read(name1, f_in1)?;
+    let new = DiffText::read(name2, f_in2)?;
+    let edits = diff_texts(&old, &new, conf);
+    let hunk_count = write_diff(&old, &new, &edits, conf, &mut f_out)?;
+    f_out.flush().sfw_err("Couldn't flush output")?;
+    Ok(hunk_count == 0)
+}
+
+/// The edit script turning `old` into `new`, comparing lines as
+/// configured by the whitespace options in `conf`.
+pub fn diff_texts(
+    old: &DiffText,
+    new: &DiffText,
+    conf: &DiffConf,
+) -> Vec<Edit> {
+    // Lines are interned as numbers, so that the algorithm compares
+    // each pair of lines cheaply.
+    let mut ids: HashMap<(Vec<u8>, bool), usize> = HashMap::new();
+    let mut intern = |text: &DiffText| {
+        text.lines
+            .iter()
+            .enumerate()
+            .map(|(ii, line)| {
+                let key = (normalize(line, conf), text.is_unterminated(ii));
+                let next_id = ids.len();
+                *ids.entry(key).or_insert(next_id)
+            })
+            .collect::<Vec<usize>>()
+    };
+    let old_ids = intern(old);
+    let new_ids = intern(new);
+    myers_diff(&old_ids, &new_ids)
+}
+
+fn normalize(line: &[u8], conf: &DiffConf) -> Vec<u8> {
+    if conf.ignore_all_space {
+        line.iter()
+            .copied()
+            .filter(|bt| !bt.is_ascii_whitespace())
+            .collect()
+    } else if conf.ignore_space_change {
+        let mut squeezed = line.iter().fold(vec![], |mut acc: Vec<u8>, bt| {
+            if !bt.is_ascii_whitespace() {
+                acc.push(*bt);
+            } else if acc.last() != Some(&b' ') {
+                acc.push(b' ');
+            }
+            acc
+        });
+        if squeezed.last() == Some(&b' ') {
+            squeezed.pop();
+        }
+        squeezed
+    } else {
+        line.to_vec()
+    }
+}
+
+/// A shortest edit script turning `old` into `new`. The greedy O(ND)
+/// algorithm is used unless its O(D^2) trace grows too large, in which
+/// case the linear-space variant takes over.
+pub fn myers_diff<T: Eq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = common_prefix(old, new);
+    let suffix = common_suffix(&old[prefix..], &new[prefix..]);
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let mut edits = keeps(0, 0, prefix);
+    match greedy_diff(old_mid, new_mid, prefix, prefix) {
+        Some(mid_edits) => edits.extend(mid_edits),
+        None => linear_diff_go(old_mid, new_mid, prefix, prefix, &mut edits),
+    }
+    edits.extend(keeps(old.len() - suffix, new.len() - suffix, suffix));
+    canonical_order(edits)
+}
+
+/// As `myers_diff`, but always using the linear-space variant.
+pub fn myers_diff_linear<T: Eq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = vec![];
+    linear_diff_go(old, new, 0, 0, &mut edits);
+    canonical_order(edits)
+}
+
+/// The number of `isize`s the greedy algorithm may use for its trace.
+const TRACE_LIMIT: usize = 1 << 22;
+
+fn common_prefix<T: Eq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .zip(new.iter())
+        .take_while(|(aa, bb)| aa == bb)
+        .count()
+}
+
+fn common_suffix<T: Eq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(aa, bb)| aa == bb)
+        .count()
+}
+
+fn keeps(old_start: usize, new_start: usize, count: usize) -> Vec<Edit> {
+    (0..count)
+        .map(|ii| Edit::Keep {
+            old: old_start + ii,
+            new: new_start + ii,
+        })
+        .collect()
+}
+
+/// Myers' greedy algorithm: `v[k]` holds the furthest x reached on
+/// diagonal k = x - y, for each edit distance d in turn. A copy of `v`
+/// is kept for each d, so that the path can be traced back afterwards;
+/// `None` is returned if these copies would exceed `TRACE_LIMIT`.
+fn greedy_diff<T: Eq>(
+    old: &[T],
+    new: &[T],
+    old_off: usize,
+    new_off: usize,
+) -> Option<Vec<Edit>> {
+    let (nn, mm) = (old.len() as isize, new.len() as isize);
+    let max = nn + mm;
+    let ix = |k: isize| (max + 1 + k) as usize;
+    let mut v = vec![0_isize; 2 * max as usize + 3];
+    let mut trace: Vec<Vec<isize>> = vec![];
+    let mut trace_size = 0;
+    'search: for d in 0..=max {
+        let snapshot = v[ix(-d - 1)..=ix(d + 1)].to_vec();
+        trace_size += snapshot.len();
+        if trace_size > TRACE_LIMIT {
+            return None;
+        }
+        trace.push(snapshot);
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[ix(k - 1)] < v[ix(k + 1)]) {
+                v[ix(k + 1)]
+            } else {
+                v[ix(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < nn && y < mm && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[ix(k)] = x;
+            if x >= nn && y >= mm {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = vec![];
+    let (mut x, mut y) = (nn, mm);
+    trace.iter().enumerate().rev().for_each(|(d, snapshot)| {
+        let d = d as isize;
+        let at = |k: isize| snapshot[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep {
+                old: old_off + x as usize,
+                new: new_off + y as usize,
+            });
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                edits.push(Edit::Insert {
+                    new: new_off + y as usize,
+                });
+            } else {
+                x -= 1;
+                edits.push(Edit::Delete {
+                    old: old_off + x as usize,
+                });
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    });
+    edits.reverse();
+    Some(edits)
+}
+
+/// The linear-space variant: having trimmed any common prefix and
+/// suffix, split the problem at the middle snake of an optimal path.
+/// Each half has about half the edit distance, so the recursion depth
+/// is O(log D).
+fn linear_diff_go<T: Eq>(
+    old: &[T],
+    new: &[T],
+    old_off: usize,
+    new_off: usize,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix(old, new);
+    edits.extend(keeps(old_off, new_off, prefix));
+    let suffix = common_suffix(&old[prefix..], &new[prefix..]);
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let (old_off_mid, new_off_mid) = (old_off + prefix, new_off + prefix);
+    if old_mid.is_empty() {
+        edits.extend((0..new_mid.len()).map(|ii| Edit::Insert {
+            new: new_off_mid + ii,
+        }));
+    } else if new_mid.is_empty() {
+        edits.extend((0..old_mid.len()).map(|ii| Edit::Delete {
+            old: old_off_mid + ii,
+        }));
+    } else {
+        let (x0, y0, x1, y1) = middle_snake(old_mid, new_mid);
+        linear_diff_go(
+            &old_mid[..x0],
+            &new_mid[..y0],
+            old_off_mid,
+            new_off_mid,
+            edits,
+        );
+        edits.extend(keeps(old_off_mid + x0, new_off_mid + y0, x1 - x0));
+        linear_diff_go(
+            &old_mid[x1..],
+            &new_mid[y1..],
+            old_off_mid + x1,
+            new_off_mid + y1,
+            edits,
+        );
+    }
+    edits.extend(keeps(
+        old_off + old.len() - suffix,
+        new_off + new.len() - suffix,
+        suffix,
+    ));
+}
+
+/// Runs the greedy search forwards from the start and backwards from the
+/// end until the two meet, returning the start and end of the snake
+/// where they overlap. `vb` is indexed by diagonals of the reversed
+/// inputs, so forward diagonal k corresponds to backward diagonal
+/// delta - k.
+fn middle_snake<T: Eq>(old: &[T], new: &[T]) -> (usize, usize, usize, usize) {
+    let (nn, mm) = (old.len() as isize, new.len() as isize);
+    let delta = nn - mm;
+    let odd = delta % 2 != 0;
+    let max = (nn + mm + 1) / 2;
+    let ix = |k: isize| (max + 1 + k) as usize;
+    let mut vf = vec![0_isize; 2 * max as usize + 3];
+    let mut vb = vf.clone();
+    let furthest = |v: &[isize], k: isize, d: isize| {
+        if k == -d || (k != d && v[ix(k - 1)] < v[ix(k + 1)]) {
+            v[ix(k + 1)]
+        } else {
+            v[ix(k - 1)] + 1
+        }
+    };
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let x0 = furthest(&vf, k, d);
+            let y0 = x0 - k;
+            let (mut x, mut y) = (x0, y0);
+            while x < nn && y < mm && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            vf[ix(k)] = x;
+            let c = delta - k;
+            if odd && -d < c && c < d && x + vb[ix(c)] >= nn {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let x0 = furthest(&vb, c, d);
+            let y0 = x0 - c;
+            let (mut x, mut y) = (x0, y0);
+            while x < nn
+                && y < mm
+                && old[(nn - x - 1) as usize] == new[(mm - y - 1) as usize]
+            {
+                x += 1;
+                y += 1;
+            }
+            vb[ix(c)] = x;
+            let k = delta - c;
+            if !odd && -d <= k && k <= d && x + vf[ix(k)] >= nn {
+                return (
+                    (nn - x) as usize,
+                    (mm - y) as usize,
+                    (nn - x0) as usize,
+                    (mm - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet")
+}
+
+/// Within each run of changes, puts the deletions before the insertions,
+/// as all the output formats expect.
+fn canonical_order(edits: Vec<Edit>) -> Vec<Edit> {
+    let mut ordered = Vec::with_capacity(edits.len());
+    let mut inserts = vec![];
+    edits.into_iter().for_each(|edit| match edit {
+        Edit::Insert { .. } => inserts.push(edit),
+        Edit::Delete { .. } => ordered.push(edit),
+        Edit::Keep { .. } => {
+            ordered.append(&mut inserts);
+            ordered.push(edit);
+        }
+    });
+    ordered.append(&mut inserts);
+    ordered
+}
+
+/// A maximal run of changes, as a range of indices into the edit script.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct ChangeRun {
+    start: usize,
+    end: usize,
+    deletes: bool,
+    inserts: bool,
+}
+
+fn change_runs(edits: &[Edit]) -> Vec<ChangeRun> {
+    let mut runs: Vec<ChangeRun> = vec![];
+    edits.iter().enumerate().for_each(|(ii, edit)| {
+        let is_delete = matches!(edit, Edit::Delete { .. });
+        match runs.last_mut() {
+            _ if !edit.is_change() => (),
+            Some(run) if run.end == ii => {
+                run.end += 1;
+                run.deletes |= is_delete;
+                run.inserts |= !is_delete;
+            }
+            _ => runs.push(ChangeRun {
+                start: ii,
+                end: ii + 1,
+                deletes: is_delete,
+                inserts: !is_delete,
+            }),
+        }
+    });
+    runs
+}
+
+/// The number of old and new lines preceding each edit, and (as the
+/// last element) in total.
+fn positions(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let last = edits.iter().fold((0, 0), |(old, new), edit| {
+        positions.push((old, new));
+        match edit {
+            Edit::Keep { .. } => (old + 1, new + 1),
+            Edit::Delete { .. } => (old + 1, new),
+            Edit::Insert { .. } => (old, new + 1),
+        }
+    });
+    positions.push(last);
+    positions
+}
+
+fn is_blank(line: &[u8], conf: &DiffConf) -> bool {
+    normalize(line, conf).is_empty()
+}
+
+/// The runs of changes to report, after dropping any that `conf` says
+/// to ignore.
+fn reported_runs(
+    old: &DiffText,
+    new: &DiffText,
+    edits: &[Edit],
+    conf: &DiffConf,
+) -> Vec<ChangeRun> {
+    change_runs(edits)
+        .into_iter()
+        .filter(|run| {
+            !conf.ignore_blank_lines
+                || !edits[run.start..run.end].iter().all(|edit| match edit {
+                    Edit::Delete { old: ii } => is_blank(&old.lines[*ii], conf),
+                    Edit::Insert { new: ii } => is_blank(&new.lines[*ii], conf),
+                    Edit::Keep { .. } => true,
+                })
+        })
+        .collect()
+}
+
+/// Groups the reported changes into hunks, each a range of indices into
+/// the edit script with up to `context` unchanged lines on either side.
+/// Hunks whose context would overlap are merged.
+fn hunks(
+    runs: &[ChangeRun],
+    edit_count: usize,
+    context: usize,
+) -> Vec<(usize, usize)> {
+    runs.iter()
+        .fold(vec![], |mut hunks: Vec<(usize, usize)>, run| {
+            let start = run.start.saturating_sub(context);
+            let end = (run.end + context).min(edit_count);
+            match hunks.last_mut() {
+                Some(last) if start <= last.1 => last.1 = end,
+                _ => hunks.push((start, end)),
+            }
+            hunks
+        })
+}
+
+/// Writes the edit script in the configured format, returning the
+/// number of hunks (or for `ed` scripts, commands) written.
+pub fn write_diff<W: Write>(
+    old: &DiffText,
+    new: &DiffText,
+    edits: &[Edit],
+    conf: &DiffConf,
+    f_out: &mut W,
+) -> Result<usize, SfwError> {
+    let runs = reported_runs(old, new, edits, conf);
+    match conf.format {
+        DiffFormat::Unified => {
+            write_unified(old, new, edits, &runs, conf, f_out)
+        }
+        DiffFormat::Context => {
+            write_context(old, new, edits, &runs, conf, f_out)
+        }
+        DiffFormat::Ed => write_ed(new, edits, &runs, f_out),
+    }
+}
+
+const NO_NEWLINE: &[u8] = b"\\ No newline at end of file\n";
+
+fn write_line<W: Write>(
+    f_out: &mut W,
+    prefix: &[u8],
+    text: &DiffText,
+    index: usize,
+) -> Result<(), io::Error> {
+    f_out.write_all(prefix)?;
+    f_out.write_all(&text.lines[index])?;
+    f_out.write_all(b"\n")?;
+    if text.is_unterminated(index) {
+        f_out.write_all(NO_NEWLINE)?;
+    }
+    Ok(())
+}
+
+/// A unified range: the first line and count, where an empty range is
+/// given by the line before it, and a count of 1 is left out.
+fn unified_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn write_unified<W: Write>(
+    old: &DiffText,
+    new: &DiffText,
+    edits: &[Edit],
+    runs: &[ChangeRun],
+    conf: &DiffConf,
+    f_out: &mut W,
+) -> Result<usize, SfwError> {
+    let hunks = hunks(runs, edits.len(), conf.context);
+    if !hunks.is_empty() {
+        writeln!(f_out, "--- {}\n+++ {}", old.name, new.name)?;
+    }
+    let positions = positions(edits);
+    hunks.iter().try_for_each(|(start, end)| {
+        let (old_start, new_start) = positions[*start];
+        let (old_end, new_end) = positions[*end];
+        writeln!(
+            f_out,
+            "@@ -{} +{} @@",
+            unified_range(old_start, old_end - old_start),
+            unified_range(new_start, new_end - new_start)
+        )?;
+        edits[*start..*end].iter().try_for_each(|edit| match edit {
+            Edit::Keep { old: ii, .. } => write_line(f_out, b" ", old, *ii),
+            Edit::Delete { old: ii } => write_line(f_out, b"-", old, *ii),
+            Edit::Insert { new: ii } => write_line(f_out, b"+", new, *ii),
+        })
+    })?;
+    Ok(hunks.len())
+}
+
+/// A context range: the first and last lines, or just the last line if
+/// the range has fewer than two lines.
+fn context_range(start: usize, len: usize) -> String {
+    match len {
+        0 | 1 => format!("{}", start + len),
+        _ => format!("{},{}", start + 1, start + len),
+    }
+}
+
+fn write_context<W: Write>(
+    old: &DiffText,
+    new: &DiffText,
+    edits: &[Edit],
+    runs: &[ChangeRun],
+    conf: &DiffConf,
+    f_out: &mut W,
+) -> Result<usize, SfwError> {
+    let hunks = hunks(runs, edits.len(), conf.context);
+    if !hunks.is_empty() {
+        writeln!(f_out, "*** {}\n--- {}", old.name, new.name)?;
+    }
+    let positions = positions(edits);
+    // Lines in runs with both deletions and insertions are shown as
+    // changed, with a '!'.
+    let mut changed = vec![false; edits.len()];
+    change_runs(edits)
+        .iter()
+        .filter(|run| run.deletes && run.inserts)
+        .for_each(|run| changed[run.start..run.end].fill(true));
+    hunks.iter().try_for_each(|(start, end)| {
+        let (old_start, new_start) = positions[*start];
+        let (old_end, new_end) = positions[*end];
+        let hunk = &edits[*start..*end];
+        let changed = &changed[*start..*end];
+        writeln!(
+            f_out,
+            "***************\n*** {} ****",
+            context_range(old_start, old_end - old_start)
+        )?;
+        if hunk.iter().any(|edit| matches!(edit, Edit::Delete { .. })) {
+            hunk.iter()
+                .zip(changed)
+                .try_for_each(|(edit, changed)| match (edit, changed) {
+                    (Edit::Keep { old: ii, .. }, _) => {
+                        write_line(f_out, b"  ", old, *ii)
+                    }
+                    (Edit::Delete { old: ii }, true) => {
+                        write_line(f_out, b"! ", old, *ii)
+                    }
+                    (Edit::Delete { old: ii }, false) => {
+                        write_line(f_out, b"- ", old, *ii)
+                    }
+                    (Edit::Insert { .. }, _) => Ok(()),
+                })?;
+        }
+        writeln!(
+            f_out,
+            "--- {} ----",
+            context_range(new_start, new_end - new_start)
+        )?;
+        if hunk.iter().any(|edit| matches!(edit, Edit::Insert { .. })) {
+            hunk.iter()
+                .zip(changed)
+                .try_for_each(|(edit, changed)| match (edit, changed) {
+                    (Edit::Keep { new: ii, .. }, _) => {
+                        write_line(f_out, b"  ", new, *ii)
+                    }
+                    (Edit::Insert { new: ii }, true) => {
+                        write_line(f_out, b"! ", new, *ii)
+                    }
+                    (Edit::Insert { new: ii }, false) => {
+                        write_line(f_out, b"+ ", new, *ii)
+                    }
+                    (Edit::Delete { .. }, _) => Ok(()),
+                })?;
+        }
+        Ok::<_, io::Error>(())
+    })?;
+    Ok(hunks.len())
+}
+
+/// An `ed` line range, from 1-based first to last.
+fn ed_range(first: usize, last: usize) -> String {
+    if first == last {
+        format!("{}", first)
+    } else {
+        format!("{},{}", first, last)
+    }
+}
+
+/// Commands are written from the end of the file backwards, so that
+/// each one leaves the line numbers of those still to come unchanged.
+fn write_ed<W: Write>(
+    new: &DiffText,
+    edits: &[Edit],
+    runs: &[ChangeRun],
+    f_out: &mut W,
+) -> Result<usize, SfwError> {
+    let positions = positions(edits);
+    runs.iter().rev().try_for_each(|run| {
+        let (old_start, new_start) = positions[run.start];
+        let (old_end, new_end) = positions[run.end];
+        match (run.deletes, run.inserts) {
+            (false, _) => writeln!(f_out, "{}a", old_start)?,
+            (true, false) => {
+                writeln!(f_out, "{}d", ed_range(old_start + 1, old_end))?
+            }
+            (true, true) => {
+                writeln!(f_out, "{}c", ed_range(old_start + 1, old_end))?
+            }
+        }
+        if run.inserts {
+            new.lines[new_start..new_end].iter().try_for_each(|line| {
+                if line == b"." {
+                    return Err(SfwError::data(format!(
+                        "{}: an ed script can't insert a line holding \
+                         only '.'",
+                        new.name
+                    )));
+                }
+                f_out.write_all(line)?;
+                f_out.write_all(b"\n")?;
+                Ok(())
+            })?;
+            f_out.write_all(b".\n")?;
+        }
+        Ok::<_, SfwError>(())
+    })?;
+    Ok(runs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies an edit script, checking that it refers to the lines
+    /// in order.
+    fn apply<T: Clone + Eq + std::fmt::Debug>(
+        old: &[T],
+        new: &[T],
+        edits: &[Edit],
+    ) -> Vec<T> {
+        let (mut next_old, mut next_new) = (0, 0);
+        let mut out = vec![];
+        edits.iter().for_each(|edit| match *edit {
+            Edit::Keep { old: oo, new: nn } => {
+                assert_eq!((oo, nn), (next_old, next_new));
+                assert_eq!(old[oo], new[nn]);
+                out.push(old[oo].clone());
+                next_old += 1;
+                next_new += 1;
+            }
+            Edit::Delete { old: oo } => {
+                assert_eq!(oo, next_old);
+                next_old += 1;
+            }
+            Edit::Insert { new: nn } => {
+                assert_eq!(nn, next_new);
+                out.push(new[nn].clone());
+                next_new += 1;
+            }
+        });
+        assert_eq!((next_old, next_new), (old.len(), new.len()));
+        out
+    }
+
+    fn cost(edits: &[Edit]) -> usize {
+        edits.iter().filter(|edit| edit.is_change()).count()
+    }
+
+    #[test]
+    fn test_myers_shortest_edit_script() {
+        let old = b"ABCABBA";
+        let new = b"CBABAC";
+        let greedy = myers_diff(old, new);
+        let linear = myers_diff_linear(old, new);
+        assert_eq!(apply(old, new, &greedy), new.to_vec());
+        assert_eq!(apply(old, new, &linear), new.to_vec());
+        assert_eq!(cost(&greedy), 5);
+        assert_eq!(cost(&linear), 5);
+    }
+
+    #[test]
+    fn test_greedy_and_linear_agree_on_cost() {
+        // A simple LCG, so the inputs vary but the test is repeatable
+        let mut seed: u32 = 12345;
+        let mut rand = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % 4
+        };
+        (0..50).for_each(|_| {
+            let old: Vec<u32> = (0..rand() * 5).map(|_| rand()).collect();
+            let new: Vec<u32> = (0..rand() * 5).map(|_| rand()).collect();
+            let greedy = myers_diff(&old, &new);
+            let linear = myers_diff_linear(&old, &new);
+            assert_eq!(apply(&old, &new, &greedy), new);
+            assert_eq!(apply(&old, &new, &linear), new);
+            assert_eq!(cost(&greedy), cost(&linear));
+        });
+    }
+
+    fn diff_str(old: &str, new: &str, conf: &DiffConf) -> (bool, String) {
+        let mut out = vec![];
+        let same =
+            diff(("a", old.as_bytes()), ("b", new.as_bytes()), conf, &mut out);
+        (same.unwrap(), String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_unified_and_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10";
+        let conf = DiffConf {
+            context: 1,
+            ..DiffConf::default()
+        };
+        assert_eq!(
+            diff_str(old, new, &conf),
+            (
+                false,
+                "--- a\n+++ b\n@@ -4,3 +4,3 @@\n 4\n-5\n+five\n 6\n\
+                 @@ -9 +9,2 @@\n 9\n+10\n\\ No newline at end of file\n"
+                    .into()
+            )
+        );
+        let conf = DiffConf {
+            format: DiffFormat::Context,
+            ..conf
+        };
+        assert_eq!(
+            diff_str("x\ny\n", "x\nY\n", &conf).1,
+            "*** a\n--- b\n***************\n*** 1,2 ****\n  x\n! y\n\
+             --- 1,2 ----\n  x\n! Y\n"
+        );
+    }
+
+    #[test]
+    fn test_ed_script() {
+        let conf = DiffConf {
+            format: DiffFormat::Ed,
+            ..DiffConf::default()
+        };
+        assert_eq!(
+            diff_str("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n", &conf).1,
+            "4a\ne\n.\n2c\nB\n.\n"
+        );
+    }
+
+    #[test]
+    fn test_ignore_options() {
+        let conf = DiffConf {
+            ignore_space_change: true,
+            ignore_blank_lines: true,
+            ..DiffConf::default()
+        };
+        assert_eq!(
+            diff_str("a  b\nc\n", "a b \n\nc\n", &conf),
+            (true, "".into())
+        );
+        assert!(!diff_str("a b\n", "ab\n", &conf).0);
+        let conf = DiffConf {
+            ignore_all_space: true,
+            ..DiffConf::default()
+        };
+        assert!(diff_str("a b\n", "ab\n", &conf).0);
+    }
+}
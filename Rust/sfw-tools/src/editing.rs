@@ -0,0 +1,947 @@
+/*
+`edit` from chapter 6 of Software Tools is a line-oriented text editor
+in the style of `ed`. Commands are read one per line, from the terminal
+or from a script, and act on the lines of a buffer, which are addressed
+by number or by searching:
+
+  .  $  N        the current line, the last line, line N
+  /pat/  ?pat?   the next (or previous) line matching pat, wrapping
+                 around; an empty pattern is the last one used
+  a+N  a-N       N lines after (or before) address a, which may be
+                 omitted for the current line; N defaults to 1
+  a1,a2  a1;a2   lines a1 to a2; with `;`, a1 becomes the current line
+                 before a2 is read; `,` alone is 1,$ and `;` alone .,$
+
+Patterns are those of `find` (see `pattern.rs`), or with `-E` extended
+regular expressions (see `regex.rs`); `s` replaces as `change` does.
+
+Each line is kept once, shared (through `Rc`) between the buffer and the
+snapshot taken for `u`, so large files are edited without copying their
+text. Lines are told apart by identity, which lets `g` follow the lines
+it marked even as its commands move them about.
+
+On an error, `?` is printed; `h` explains the last one, and `H` (or
+`-v`) explains each as it happens. When reading a script, the first
+error stops the editor with a failing exit status.
+ */
+
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::process;
+use std::rc::Rc;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::changing::{change_line, ChangeConf, Matcher, Replacement};
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, write_atomically};
+
+type Line = Rc<[u8]>;
+
+/// Errors are reported to the user as messages, and don't end the
+/// editing session unless it is scripted.
+type EdResult<T> = Result<T, String>;
+
+/// The remaining lines of input, which `a`, `i` and `c` read text from.
+type Input<'a> = dyn Iterator<Item = io::Result<Vec<u8>>> + 'a;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct EditConf {
+    /// Explain errors rather than just printing `?`.
+    pub verbose: bool,
+    /// Don't print the byte counts of files read and written.
+    pub silent: bool,
+    /// Patterns are extended regular expressions.
+    pub extended: bool,
+    /// Stop at the first error, as when commands come from a script.
+    pub script: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// A command line being parsed.
+struct Cmd<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Cmd<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let bt = self.peek();
+        if bt.is_some() {
+            self.pos += 1;
+        }
+        bt
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ') | Some(b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let digits = self.text[self.pos..]
+            .iter()
+            .take_while(|bt| bt.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        let num = String::from_utf8_lossy(&self.text[self.pos..][..digits])
+            .parse::<usize>()
+            .unwrap_or(usize::MAX);
+        self.pos += digits;
+        Some(num)
+    }
+
+    /// The text up to the next `delim` not preceded by `escape`, which
+    /// is kept for the pattern compiler except before `delim`. A missing
+    /// closing delimiter is allowed at the end of the line.
+    fn delimited(&mut self, delim: u8, escape: u8) -> Vec<u8> {
+        let mut out = vec![];
+        while let Some(bt) = self.next() {
+            if bt == delim {
+                break;
+            }
+            if bt == escape {
+                match self.next() {
+                    Some(next) if next == delim => out.push(next),
+                    Some(next) => out.extend_from_slice(&[bt, next]),
+                    None => out.push(bt),
+                }
+            } else {
+                out.push(bt);
+            }
+        }
+        out
+    }
+
+    /// The file name that ends the command, if any, after a blank.
+    fn file_name(&mut self) -> EdResult<Option<String>> {
+        let rest = &self.text[self.pos..];
+        self.pos = self.text.len();
+        match rest.first() {
+            None => Ok(None),
+            Some(b' ' | b'\t') => {
+                let name = rest.trim_ascii();
+                Ok((!name.is_empty())
+                    .then(|| String::from_utf8_lossy(name).to_string()))
+            }
+            Some(_) => Err("invalid command suffix".to_string()),
+        }
+    }
+
+    fn end(&mut self) -> EdResult<()> {
+        self.skip_blanks();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err("invalid command suffix".to_string()),
+        }
+    }
+}
+
+struct Editor<'c> {
+    conf: &'c EditConf,
+    lines: Vec<Line>,
+    /// The current line; 0 only when the buffer is empty.
+    dot: usize,
+    undo: Option<(Vec<Line>, usize)>,
+    file_name: Option<String>,
+    modified: bool,
+    /// Whether the last command was refused for losing changes, so that
+    /// repeating it goes ahead.
+    warned: bool,
+    pattern: Option<Matcher>,
+    verbose: bool,
+    last_error: Option<String>,
+    in_global: bool,
+}
+
+fn write_err(err: io::Error) -> String {
+    format!("couldn't write output: {}", err)
+}
+
+impl<'c> Editor<'c> {
+    fn new(conf: &'c EditConf) -> Self {
+        Editor {
+            conf,
+            lines: vec![],
+            dot: 0,
+            undo: None,
+            file_name: None,
+            modified: false,
+            warned: false,
+            pattern: None,
+            verbose: conf.verbose,
+            last_error: None,
+            in_global: false,
+        }
+    }
+
+    fn escape(&self) -> u8 {
+        if self.conf.extended {
+            b'\\'
+        } else {
+            b'@'
+        }
+    }
+
+    /// Compiles `pat`, or recalls the last pattern if it is empty.
+    fn matcher(&mut self, pat: &[u8]) -> EdResult<Matcher> {
+        if pat.is_empty() {
+            return self
+                .pattern
+                .clone()
+                .ok_or_else(|| "no previous pattern".to_string());
+        }
+        let conf = ChangeConf {
+            extended: self.conf.extended,
+            ..ChangeConf::default()
+        };
+        let matcher =
+            Matcher::compile(&conf, pat).map_err(|err| err.to_string())?;
+        self.pattern = Some(matcher.clone());
+        Ok(matcher)
+    }
+
+    /// The number of the next (or previous) line matching the pattern
+    /// delimited by `delim`, searching from the current line.
+    fn search(&mut self, cmd: &mut Cmd, delim: u8) -> EdResult<usize> {
+        let pat = cmd.delimited(delim, self.escape());
+        let matcher = self.matcher(&pat)?;
+        let len = self.lines.len();
+        (1..=len)
+            .map(|step| match delim {
+                b'/' => (self.dot + step - 1) % len + 1,
+                _ => (self.dot + 2 * len - step - 1) % len + 1,
+            })
+            .find(|line| matcher.is_match(&self.lines[line - 1]))
+            .ok_or_else(|| "no match".to_string())
+    }
+
+    fn parse_address(&mut self, cmd: &mut Cmd) -> EdResult<Option<usize>> {
+        cmd.skip_blanks();
+        let mut addr = match cmd.peek() {
+            Some(b'.') => {
+                cmd.pos += 1;
+                Some(self.dot)
+            }
+            Some(b'$') => {
+                cmd.pos += 1;
+                Some(self.lines.len())
+            }
+            Some(bt) if bt.is_ascii_digit() => cmd.number(),
+            Some(delim @ (b'/' | b'?')) => {
+                cmd.pos += 1;
+                Some(self.search(cmd, delim)?)
+            }
+            _ => None,
+        };
+        loop {
+            cmd.skip_blanks();
+            let sign = match cmd.peek() {
+                Some(sign @ (b'+' | b'-')) => sign,
+                _ => break,
+            };
+            cmd.pos += 1;
+            let offset = cmd.number().unwrap_or(1);
+            let base = addr.unwrap_or(self.dot);
+            addr = Some(match sign {
+                b'+' => base.saturating_add(offset),
+                _ => base
+                    .checked_sub(offset)
+                    .ok_or_else(|| "invalid address".to_string())?,
+            });
+        }
+        match addr {
+            Some(addr) if addr > self.lines.len() => {
+                Err("invalid address".to_string())
+            }
+            addr => Ok(addr),
+        }
+    }
+
+    /// The first and last lines addressed, if any were.
+    fn parse_range(
+        &mut self,
+        cmd: &mut Cmd,
+    ) -> EdResult<Option<(usize, usize)>> {
+        let first = self.parse_address(cmd)?;
+        cmd.skip_blanks();
+        let sep = match cmd.peek() {
+            Some(sep @ (b',' | b';')) => sep,
+            _ => return Ok(first.map(|first| (first, first))),
+        };
+        cmd.pos += 1;
+        let start = match (first, sep) {
+            (Some(first), _) => first,
+            (None, b',') => 1,
+            (None, _) => self.dot,
+        };
+        if sep == b';' {
+            self.dot = start;
+        }
+        let end = match (self.parse_address(cmd)?, first) {
+            (Some(end), _) => end,
+            (None, Some(first)) => first,
+            (None, None) => self.lines.len(),
+        };
+        if start > end {
+            return Err("invalid address".to_string());
+        }
+        Ok(Some((start, end)))
+    }
+
+    /// The line addressed, or `default`; 0 is allowed.
+    fn one(&self, range: Option<(usize, usize)>, default: usize) -> usize {
+        range.map_or(default, |(_, end)| end)
+    }
+
+    /// The lines addressed, or the default range, which must all exist.
+    fn two(
+        &self,
+        range: Option<(usize, usize)>,
+        default: (usize, usize),
+    ) -> EdResult<(usize, usize)> {
+        let (start, end) = range.unwrap_or(default);
+        if start == 0 || end < start || end > self.lines.len() {
+            Err("invalid address".to_string())
+        } else {
+            Ok((start, end))
+        }
+    }
+
+    fn snapshot(&mut self) {
+        if !self.in_global {
+            self.undo = Some((self.lines.clone(), self.dot));
+        }
+    }
+
+    /// Inserts `text` after line `after`, making the last of it current.
+    fn insert(&mut self, after: usize, text: Vec<Line>) {
+        if !text.is_empty() {
+            self.dot = after + text.len();
+            self.lines.splice(after..after, text);
+            self.modified = true;
+        }
+    }
+
+    /// Deletes lines `start` to `end`; the line after them is current.
+    fn delete(&mut self, start: usize, end: usize) {
+        self.lines.drain(start - 1..end);
+        self.dot = start.min(self.lines.len());
+        self.modified = true;
+    }
+
+    fn print(
+        &mut self,
+        out: &mut dyn Write,
+        (start, end): (usize, usize),
+        list: bool,
+    ) -> EdResult<()> {
+        self.lines[start - 1..end]
+            .iter()
+            .try_for_each(|line| {
+                if list {
+                    out.write_all(&list_line(line))?;
+                } else {
+                    out.write_all(line)?;
+                }
+                out.write_all(b"\n")
+            })
+            .map_err(write_err)?;
+        self.dot = end;
+        Ok(())
+    }
+
+    fn report_bytes(&self, out: &mut dyn Write, bytes: usize) -> EdResult<()> {
+        if self.conf.silent {
+            return Ok(());
+        }
+        writeln!(out, "{}", bytes).map_err(write_err)
+    }
+
+    /// Replaces the buffer with the contents of the file `name`.
+    fn open(&mut self, name: String, out: &mut dyn Write) -> EdResult<()> {
+        let read = read_file(&name);
+        self.file_name = Some(name);
+        let (lines, bytes) = read?;
+        self.lines = lines;
+        self.dot = self.lines.len();
+        self.undo = None;
+        self.modified = false;
+        self.report_bytes(out, bytes)
+    }
+
+    fn exec(
+        &mut self,
+        text: &[u8],
+        input: &mut Input,
+        out: &mut dyn Write,
+    ) -> EdResult<Flow> {
+        let warned = std::mem::take(&mut self.warned);
+        let mut cmd = Cmd { text, pos: 0 };
+        let range = self.parse_range(&mut cmd)?;
+        cmd.skip_blanks();
+        let name = cmd.next();
+        let dot = (self.dot, self.dot);
+        let len = self.lines.len();
+        if self.in_global
+            && matches!(
+                name,
+                Some(b'a' | b'i' | b'c' | b'e' | b'g' | b'v' | b'u' | b'q')
+            )
+        {
+            return Err("command not allowed in a global command".to_string());
+        }
+        if range.is_some()
+            && matches!(name, Some(b'e' | b'f' | b'q' | b'u' | b'h' | b'H'))
+        {
+            return Err("unexpected address".to_string());
+        }
+        match name {
+            None => {
+                let line = self.one(range, self.dot + 1);
+                let lines = self.two(Some((line, line)), dot)?;
+                self.print(out, lines, false)?;
+            }
+            Some(list @ (b'p' | b'l')) => {
+                cmd.end()?;
+                let lines = self.two(range, dot)?;
+                self.print(out, lines, list == b'l')?;
+            }
+            Some(append @ (b'a' | b'i')) => {
+                cmd.end()?;
+                let line = self.one(range, self.dot);
+                let after = match append {
+                    b'a' => line,
+                    _ => line.saturating_sub(1),
+                };
+                let text = read_text(input)?;
+                self.snapshot();
+                self.insert(after, text);
+            }
+            Some(b'c') => {
+                cmd.end()?;
+                let (start, end) = self.two(range, dot)?;
+                let text = read_text(input)?;
+                self.snapshot();
+                self.delete(start, end);
+                self.insert(start - 1, text);
+            }
+            Some(b'd') => {
+                cmd.end()?;
+                let (start, end) = self.two(range, dot)?;
+                self.snapshot();
+                self.delete(start, end);
+            }
+            Some(copy @ (b'm' | b't')) => {
+                let (start, end) = self.two(range, dot)?;
+                let dest = self.parse_address(&mut cmd)?.unwrap_or(self.dot);
+                cmd.end()?;
+                self.snapshot();
+                if copy == b't' {
+                    let text = self.lines[start - 1..end]
+                        .iter()
+                        .map(|line| Line::from(&line[..]))
+                        .collect();
+                    self.insert(dest, text);
+                } else if (start..end).contains(&dest) {
+                    return Err("invalid destination".to_string());
+                } else {
+                    let text = self.lines.drain(start - 1..end).collect();
+                    let count = end - start + 1;
+                    let dest = if dest >= end { dest - count } else { dest };
+                    self.insert(dest, text);
+                }
+            }
+            Some(b's') => self.substitute(range, &mut cmd, out)?,
+            Some(invert @ (b'g' | b'v')) => {
+                self.global(range, &mut cmd, invert == b'v', input, out)?
+            }
+            Some(b'w') => {
+                let (start, end) = match range {
+                    None if self.lines.is_empty() => (1, 0),
+                    range => self.two(range, (1, len))?,
+                };
+                let name = cmd
+                    .file_name()?
+                    .or_else(|| self.file_name.clone())
+                    .ok_or_else(|| "no current filename".to_string())?;
+                self.file_name.get_or_insert_with(|| name.clone());
+                let lines = &self.lines[start - 1..end];
+                write_atomically(&name, |f_out| {
+                    lines.iter().try_for_each(|line| {
+                        f_out.write_all(line)?;
+                        f_out.write_all(b"\n")
+                    })?;
+                    Ok(())
+                })
+                .map_err(|err| err.to_string())?;
+                if (start, end) == (1, len) {
+                    self.modified = false;
+                }
+                let bytes = lines.iter().map(|line| line.len() + 1).sum();
+                self.report_bytes(out, bytes)?;
+            }
+            Some(b'r') => {
+                let after = self.one(range, len);
+                let name = cmd
+                    .file_name()?
+                    .or_else(|| self.file_name.clone())
+                    .ok_or_else(|| "no current filename".to_string())?;
+                let (text, bytes) = read_file(&name)?;
+                self.snapshot();
+                self.insert(after, text);
+                self.report_bytes(out, bytes)?;
+            }
+            Some(b'e') => {
+                let name = cmd
+                    .file_name()?
+                    .or_else(|| self.file_name.clone())
+                    .ok_or_else(|| "no current filename".to_string())?;
+                if self.modified && !warned {
+                    self.warned = true;
+                    return Err("warning: buffer modified".to_string());
+                }
+                self.open(name, out)?;
+            }
+            Some(b'f') => {
+                if let Some(name) = cmd.file_name()? {
+                    self.file_name = Some(name);
+                }
+                let name = self
+                    .file_name
+                    .clone()
+                    .ok_or_else(|| "no current filename".to_string())?;
+                writeln!(out, "{}", name).map_err(write_err)?;
+            }
+            Some(b'q') => {
+                cmd.end()?;
+                if self.modified && !warned {
+                    self.warned = true;
+                    return Err("warning: buffer modified".to_string());
+                }
+                return Ok(Flow::Quit);
+            }
+            Some(b'Q') => {
+                cmd.end()?;
+                return Ok(Flow::Quit);
+            }
+            Some(b'u') => {
+                cmd.end()?;
+                let (lines, dot) = self
+                    .undo
+                    .take()
+                    .ok_or_else(|| "nothing to undo".to_string())?;
+                let lines = std::mem::replace(&mut self.lines, lines);
+                self.undo =
+                    Some((lines, std::mem::replace(&mut self.dot, dot)));
+                self.modified = true;
+            }
+            Some(b'=') => {
+                cmd.end()?;
+                writeln!(out, "{}", self.one(range, len)).map_err(write_err)?;
+            }
+            Some(b'h') => {
+                cmd.end()?;
+                if let Some(err) = &self.last_error {
+                    writeln!(out, "{}", err).map_err(write_err)?;
+                }
+            }
+            Some(b'H') => {
+                cmd.end()?;
+                self.verbose = !self.verbose;
+                match &self.last_error {
+                    Some(err) if self.verbose => {
+                        writeln!(out, "{}", err).map_err(write_err)?
+                    }
+                    _ => (),
+                }
+            }
+            Some(_) => return Err("unknown command".to_string()),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// `s/pat/rep/[g][p]` replaces the first (or every) match on each
+    /// line addressed; a newline in `rep` splits the line.
+    fn substitute(
+        &mut self,
+        range: Option<(usize, usize)>,
+        cmd: &mut Cmd,
+        out: &mut dyn Write,
+    ) -> EdResult<()> {
+        let (start, end) = self.two(range, (self.dot, self.dot))?;
+        let delim = match cmd.next() {
+            Some(delim) if !delim.is_ascii_whitespace() => delim,
+            _ => return Err("invalid pattern delimiter".to_string()),
+        };
+        let escape = self.escape();
+        let pat = cmd.delimited(delim, escape);
+        let rep = cmd.delimited(delim, escape);
+        let (mut global, mut print) = (false, false);
+        while let Some(flag) = cmd.next() {
+            match flag {
+                b'g' => global = true,
+                b'p' => print = true,
+                _ => return Err("invalid command suffix".to_string()),
+            }
+        }
+        let matcher = self.matcher(&pat)?;
+        let replacement = Replacement::parse(&rep, escape);
+        if replacement.max_group() > matcher.group_count() {
+            return Err("invalid group reference".to_string());
+        }
+        let conf = ChangeConf {
+            first_only: !global,
+            ..ChangeConf::default()
+        };
+        // Within `g`, the global command has already taken the snapshot.
+        let before = (!self.in_global).then(|| (self.lines.clone(), self.dot));
+        let mut last = None;
+        let mut ix = start - 1;
+        for _ in start..=end {
+            let (line, changes) =
+                change_line(&conf, &matcher, &replacement, &self.lines[ix]);
+            if changes == 0 {
+                ix += 1;
+                continue;
+            }
+            let parts = line
+                .split(|bt| *bt == b'\n')
+                .map(Line::from)
+                .collect::<Vec<Line>>();
+            ix += parts.len();
+            self.lines
+                .splice(ix - parts.len()..=ix - parts.len(), parts);
+            last = Some(ix);
+        }
+        match last {
+            None if self.in_global => Ok(()),
+            None => Err("no match".to_string()),
+            Some(last) => {
+                if before.is_some() {
+                    self.undo = before;
+                }
+                self.dot = last;
+                self.modified = true;
+                if print {
+                    self.print(out, (last, last), false)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// `g/pat/cmd` runs `cmd` (by default `p`) with each line matching
+    /// `pat` in turn as the current line; `v` does so for the lines that
+    /// don't match.
+    fn global(
+        &mut self,
+        range: Option<(usize, usize)>,
+        cmd: &mut Cmd,
+        invert: bool,
+        input: &mut Input,
+        out: &mut dyn Write,
+    ) -> EdResult<()> {
+        let (start, end) = self.two(range, (1, self.lines.len()))?;
+        let delim = match cmd.next() {
+            Some(delim) if !delim.is_ascii_whitespace() => delim,
+            _ => return Err("invalid pattern delimiter".to_string()),
+        };
+        let pat = cmd.delimited(delim, self.escape());
+        let matcher = self.matcher(&pat)?;
+        let command = match cmd.text[cmd.pos..].trim_ascii() {
+            b"" => &b"p"[..],
+            command => command,
+        };
+        let marked = self.lines[start - 1..end]
+            .iter()
+            .filter(|line| matcher.is_match(line) != invert)
+            .cloned()
+            .collect::<Vec<Line>>();
+        self.snapshot();
+        self.in_global = true;
+        let mut hint = 0;
+        let res = marked.iter().try_for_each(|mark| {
+            // Marked lines stay in order unless moved, so the search for
+            // each starts where the last was found.
+            let found = (hint..self.lines.len())
+                .chain(0..hint)
+                .find(|ix| Rc::ptr_eq(&self.lines[*ix], mark));
+            if let Some(ix) = found {
+                hint = ix;
+                self.dot = ix + 1;
+                self.exec(command, input, out)?;
+            }
+            Ok(())
+        });
+        self.in_global = false;
+        res
+    }
+}
+
+/// Reads lines of text for `a`, `i` and `c`, up to a line holding only
+/// a `.`.
+fn read_text(input: &mut Input) -> EdResult<Vec<Line>> {
+    let mut text = vec![];
+    for line in input {
+        let line = line.map_err(|err| format!("couldn't read: {}", err))?;
+        if line == b"." {
+            break;
+        }
+        text.push(Line::from(line));
+    }
+    Ok(text)
+}
+
+/// The lines of the file `name`, and its size in bytes, counting a
+/// newline for each line.
+fn read_file(name: &str) -> EdResult<(Vec<Line>, usize)> {
+    let file = File::open(name).map_err(|err| format!("{}: {}", name, err))?;
+    let lines = BytesIter::new(file, DEFAULT_BUF_SIZE)
+        .lines()
+        .map(|line| line.map(Line::from))
+        .collect::<io::Result<Vec<Line>>>()
+        .map_err(|err| format!("{}: {}", name, err))?;
+    let bytes = lines.iter().map(|line| line.len() + 1).sum();
+    Ok((lines, bytes))
+}
+
+/// The line as `l` shows it: unprintable bytes are escaped and the end
+/// is marked with `$`.
+fn list_line(line: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    line.iter().for_each(|bt| match bt {
+        b'\\' => out.extend_from_slice(b"\\\\"),
+        b'\t' => out.extend_from_slice(b"\\t"),
+        0x08 => out.extend_from_slice(b"\\b"),
+        bt if bt.is_ascii_graphic() || *bt == b' ' => out.push(*bt),
+        bt => out.extend_from_slice(format!("\\{:03o}", bt).as_bytes()),
+    });
+    out.push(b'$');
+    out
+}
+
+const EDIT_USAGE: &str = r#"
+edit [OPTION]... [FILE]
+
+Edits FILE (or an empty buffer) with commands read from STDIN, or from a
+script. When the commands don't come from a terminal, the first error
+ends the session with status 1.
+
+Valid options are:
+-f SCRIPT     read commands from SCRIPT
+-s            don't print the sizes of files read and written
+-v            explain errors rather than just printing ?
+-E            patterns are extended regular expressions
+
+Lines are addressed by . (the current line), $ (the last), a number,
+/pat/ or ?pat? (the next or previous line matching pat), and a+N or a-N
+(N lines after or before a); a1,a2 is a range, as is a1;a2, which first
+makes a1 the current line. Commands, with their default addresses, are:
+
+(.)a  (.)i    append or insert text, ended by a line holding only .
+(.,.)c        change lines to text, ended by a line holding only .
+(.,.)d        delete lines
+(.,.)p        print lines
+(.,.)l        print lines showing unprintable characters
+(.,.)m a      move lines after line a
+(.,.)t a      copy lines after line a
+(.,.)s/pat/rep/[g][p]
+              replace the first (or every) match of pat, as change does
+(1,$)g/pat/cmd
+              run cmd (default p) on each line matching pat
+(1,$)v/pat/cmd
+              run cmd on each line not matching pat
+(1,$)w [file] write lines to file
+($)r [file]   read file in after the line
+e [file]      edit file
+f [file]      set or print the file name
+q             quit; Q quits even if there are unwritten changes
+u             undo the last change
+($)=          print the line number
+h             explain the last error; H toggles explaining errors
+
+"#;
+
+pub fn run_edit_seahorse_cmd() -> Command {
+    Command::new("edit")
+        .description("edit: line-oriented text editor")
+        .usage(EDIT_USAGE)
+        .action(run_edit_seahorse_action)
+        .flag(
+            Flag::new("script", FlagType::String)
+                .alias("f")
+                .description("edit -f script.ed some_file"),
+        )
+        .flag(
+            Flag::new("silent", FlagType::Bool)
+                .alias("s")
+                .description("edit -s some_file"),
+        )
+        .flag(
+            Flag::new("verbose", FlagType::Bool)
+                .alias("v")
+                .description("edit -v some_file"),
+        )
+        .flag(
+            Flag::new("extended", FlagType::Bool)
+                .alias("E")
+                .description("edit -E some_file"),
+        )
+}
+
+pub fn run_edit_seahorse_action(ctxt: &Context) {
+    let file = ctxt.args.first().map(String::as_str);
+    let script = ctxt.string_flag("script").ok();
+    let conf = EditConf {
+        verbose: ctxt.bool_flag("verbose"),
+        silent: ctxt.bool_flag("silent"),
+        extended: ctxt.bool_flag("extended"),
+        script: script.is_some() || !io::stdin().is_terminal(),
+    };
+    run_edit(&conf, file, script.as_deref());
+}
+
+/// Convenience function for running edit in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Otherwise, the program exits with status 1 if a script failed.
+pub fn run_edit(conf: &EditConf, file: Option<&str>, script: Option<&str>) {
+    let f_in = open_input(script).user_err("Error in edit");
+    let f_out = BufWriter::new(io::stdout());
+    let completed = edit(conf, file, f_in, f_out).user_err("Error in edit");
+    if !completed {
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+/// Edits `file` with the commands read from `f_in`, writing what they
+/// print to `f_out`. Returns false if a script was stopped by an error.
+pub fn edit<R: Read, W: Write>(
+    conf: &EditConf,
+    file: Option<&str>,
+    f_in: R,
+    mut f_out: W,
+) -> Result<bool, SfwError> {
+    let mut editor = Editor::new(conf);
+    let report = |editor: &mut Editor, msg: String, f_out: &mut W| {
+        writeln!(f_out, "?")?;
+        if editor.verbose {
+            writeln!(f_out, "{}", msg)?;
+        }
+        editor.last_error = Some(msg);
+        f_out.flush().sfw_err("Couldn't flush output")
+    };
+    if let Some(file) = file {
+        // A new file may be named; its contents will be written there.
+        if let Err(msg) = editor.open(file.to_string(), &mut f_out) {
+            report(&mut editor, msg, &mut f_out)?;
+        }
+    }
+    let mut commands = BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines();
+    while let Some(command) = commands.next() {
+        let command = command.sfw_err("Couldn't read commands")?;
+        match editor.exec(&command, &mut commands, &mut f_out) {
+            Ok(Flow::Continue) => (),
+            Ok(Flow::Quit) => break,
+            Err(msg) => {
+                report(&mut editor, msg, &mut f_out)?;
+                if conf.script {
+                    return Ok(false);
+                }
+            }
+        }
+        f_out.flush().sfw_err("Couldn't flush output")?;
+    }
+    f_out.flush().sfw_err("Couldn't flush output")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn edit_str(conf: &EditConf, file: Option<&str>, script: &str) -> String {
+        let mut out = vec![];
+        edit(conf, file, script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const BUFFER: &str = "a\nthe cat\nsat on\nthe mat\n.\n";
+
+    #[test]
+    fn test_edit_addresses_and_printing() {
+        let conf = EditConf::default();
+        let script = [BUFFER, "1p\n$p\n/sat/;+1p\n?cat?=\n-,.p\n,l\n"];
+        assert_eq!(
+            edit_str(&conf, None, &script.concat()),
+            "the cat\nthe mat\nsat on\nthe mat\n1\nsat on\nthe mat\n\
+             the cat$\nsat on$\nthe mat$\n"
+        );
+    }
+
+    #[test]
+    fn test_edit_changes_and_undo() {
+        let conf = EditConf::default();
+        let script = [
+            BUFFER,
+            "2m0\n,p\n1t$\n$d\nu\n$p\ng/the/s/the/a/p\n",
+            "v/cat/d\n,p\n1c\nx\ny\n.\n,p\n1s/x/1@n2/\n,p\n",
+        ];
+        assert_eq!(
+            edit_str(&conf, None, &script.concat()),
+            "sat on\nthe cat\nthe mat\nsat on\na cat\na mat\n\
+             a cat\nx\ny\n1\n2\ny\n"
+        );
+    }
+
+    #[test]
+    fn test_edit_undo_global_substitute() {
+        let conf = EditConf::default();
+        let script = [BUFFER, "1d\ng/t/s/t/T/g\n,p\nu\n,p\n"];
+        assert_eq!(
+            edit_str(&conf, None, &script.concat()),
+            "saT on\nThe maT\nsat on\nthe mat\n"
+        );
+    }
+
+    #[test]
+    fn test_edit_files_and_errors() {
+        let path = env::temp_dir().join(format!("edit-test.{}", process::id()));
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, "the cat\n").unwrap();
+        let conf = EditConf {
+            script: true,
+            extended: true,
+            ..EditConf::default()
+        };
+        let script = "s/(c)(at)/\\2\\1/\nw\nq\n";
+        assert_eq!(edit_str(&conf, Some(&path), script), "8\n8\n");
+        let mut out = vec![];
+        let completed = edit(&conf, Some(&path), &b"d\nq\nq\n"[..], &mut out);
+        assert_eq!((completed.unwrap(), out), (false, b"8\n?\n".to_vec()));
+        let conf = EditConf {
+            verbose: true,
+            silent: true,
+            ..EditConf::default()
+        };
+        let out = edit_str(&conf, Some(&path), "d\n,p\nq\nq\n");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(out, "?\ninvalid address\n?\nwarning: buffer modified\n");
+    }
+}
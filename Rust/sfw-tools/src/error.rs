@@ -1,8 +1,24 @@
+use std::error;
 use std::fmt::{self, Display};
-use std::io::{Error, ErrorKind::*};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::process;
 
-const USER_ERROR_CODE: i32 = 1;
+use seahorse::error::FlagError;
+
+/// Exit codes, following the shell conventions for general failures and
+/// usage errors, and BSD's `sysexits.h` for the more specific cases.
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+/// Used instead of the codes below by tools such as `compare`, whose exit
+/// status already distinguishes success (0) from failure (1), to mean
+/// that the result couldn't be determined at all.
+pub const EXIT_TROUBLE: i32 = 2;
+pub const EXIT_DATAERR: i32 = 65;
+pub const EXIT_NOINPUT: i32 = 66;
+pub const EXIT_CANTCREAT: i32 = 73;
+pub const EXIT_NOPERM: i32 = 77;
 
 pub struct NoneErrorRich<'a>(&'a str);
 const NONE_ERROR_DEFAULT: NoneErrorRich = NoneErrorRich("Can't unwrap None");
@@ -13,28 +29,301 @@ impl<'a> fmt::Display for NoneErrorRich<'a> {
     }
 }
 
-pub fn user_exit(msg: &str) {
-    eprintln!("{}", msg);
-    process::exit(USER_ERROR_CODE)
+/// The error type returned throughout the library. Each variant records
+/// what was being attempted, so that callers can tell (for instance) a
+/// missing source file from corrupt input data, and binaries can map
+/// errors onto distinct exit codes with `ErrorCode`.
+#[derive(Debug)]
+pub enum SfwError {
+    /// The tool was invoked incorrectly, e.g. with a missing argument.
+    Usage(String),
+    /// An underlying I/O operation failed.
+    Io {
+        op: Option<String>,
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The input was read successfully, but its contents are invalid.
+    /// Text inputs give the (1-based) line, binary ones the byte offset.
+    Data {
+        msg: String,
+        path: Option<PathBuf>,
+        line: Option<u64>,
+        offset: Option<u64>,
+    },
+    /// Additional context wrapped around an error from a lower level.
+    Context { op: String, source: Box<SfwError> },
+}
+
+impl SfwError {
+    pub fn usage<S: Into<String>>(msg: S) -> Self {
+        SfwError::Usage(msg.into())
+    }
+
+    pub fn data<S: Into<String>>(msg: S) -> Self {
+        SfwError::Data {
+            msg: msg.into(),
+            path: None,
+            line: None,
+            offset: None,
+        }
+    }
+
+    pub fn data_at<S: Into<String>>(msg: S, offset: u64) -> Self {
+        SfwError::Data {
+            msg: msg.into(),
+            path: None,
+            line: None,
+            offset: Some(offset),
+        }
+    }
+
+    pub fn data_at_line<S: Into<String>>(msg: S, line: u64) -> Self {
+        SfwError::Data {
+            msg: msg.into(),
+            path: None,
+            line: Some(line),
+            offset: None,
+        }
+    }
+
+    /// Records the path being operated on, if none has been recorded yet.
+    pub fn with_path<P: AsRef<Path>>(self, new_path: P) -> Self {
+        let new_path = new_path.as_ref().to_path_buf();
+        match self {
+            SfwError::Io {
+                op,
+                path: None,
+                source,
+            } => SfwError::Io {
+                op,
+                path: Some(new_path),
+                source,
+            },
+            SfwError::Data {
+                msg,
+                path: None,
+                line,
+                offset,
+            } => SfwError::Data {
+                msg,
+                path: Some(new_path),
+                line,
+                offset,
+            },
+            SfwError::Context { op, source } => SfwError::Context {
+                op,
+                source: Box::new(source.with_path(new_path)),
+            },
+            other => other,
+        }
+    }
+
+    /// The innermost error in a chain of `Context`s.
+    pub fn root(&self) -> &SfwError {
+        match self {
+            SfwError::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    /// The `io::ErrorKind` at the root of the chain, if it is an I/O error.
+    pub fn io_kind(&self) -> Option<ErrorKind> {
+        match self.root() {
+            SfwError::Io { source, .. } => Some(source.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SfwError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SfwError::Usage(msg) => write!(f, "{}", msg),
+            SfwError::Io { op, path, source } => {
+                if let Some(op) = op {
+                    write!(f, "{}: ", op)?;
+                }
+                if let Some(path) = path {
+                    write!(f, "{}: ", path.display())?;
+                }
+                write!(f, "{}", source)
+            }
+            SfwError::Data {
+                msg,
+                path,
+                line,
+                offset,
+            } => {
+                match (path, line) {
+                    (Some(path), Some(line)) => {
+                        write!(f, "{}:{}: ", path.display(), line)?
+                    }
+                    (Some(path), None) => write!(f, "{}: ", path.display())?,
+                    (None, Some(line)) => write!(f, "line {}: ", line)?,
+                    (None, None) => (),
+                }
+                write!(f, "{}", msg)?;
+                match offset {
+                    Some(offset) => write!(f, " (at byte offset {})", offset),
+                    None => Ok(()),
+                }
+            }
+            SfwError::Context { op, source } => write!(f, "{}: {}", op, source),
+        }
+    }
+}
+
+impl error::Error for SfwError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SfwError::Io { source, .. } => Some(source),
+            SfwError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SfwError {
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<SfwError>()) {
+            if let Some(Ok(inner)) = err.into_inner().map(|e| e.downcast()) {
+                return *inner;
+            }
+            unreachable!("checked that the error wraps an SfwError");
+        }
+        SfwError::Io {
+            op: None,
+            path: None,
+            source: err,
+        }
+    }
+}
+
+/// Lets an `SfwError` pass through the `Read` and `Write` traits, e.g.
+/// from inside an adapter, with data errors as `InvalidData`. Converting
+/// back with `SfwError::from` recovers the original error.
+impl From<SfwError> for io::Error {
+    fn from(err: SfwError) -> Self {
+        let kind = match err.root() {
+            SfwError::Io { source, .. } => source.kind(),
+            SfwError::Data { .. } => ErrorKind::InvalidData,
+            _ => ErrorKind::Other,
+        };
+        match err {
+            SfwError::Io {
+                op: None,
+                path: None,
+                source,
+            } => source,
+            err => io::Error::new(kind, err),
+        }
+    }
+}
+
+/// Maps an error onto the status a binary should exit with.
+pub trait ErrorCode {
+    fn exit_code(&self) -> i32;
+
+    /// True if the error is a write to a pipe whose reader has gone away.
+    fn is_broken_pipe(&self) -> bool {
+        false
+    }
+}
+
+impl ErrorCode for SfwError {
+    fn exit_code(&self) -> i32 {
+        match self.root() {
+            SfwError::Usage(_) => EXIT_USAGE,
+            SfwError::Io { source, .. } => source.exit_code(),
+            SfwError::Data { .. } => EXIT_DATAERR,
+            SfwError::Context { .. } => EXIT_FAILURE, // Unreachable via root
+        }
+    }
+
+    fn is_broken_pipe(&self) -> bool {
+        self.io_kind() == Some(ErrorKind::BrokenPipe)
+    }
+}
+
+impl ErrorCode for io::Error {
+    fn exit_code(&self) -> i32 {
+        match self.kind() {
+            ErrorKind::NotFound => EXIT_NOINPUT,
+            ErrorKind::PermissionDenied => EXIT_NOPERM,
+            ErrorKind::AlreadyExists => EXIT_CANTCREAT,
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => EXIT_DATAERR,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    fn is_broken_pipe(&self) -> bool {
+        self.kind() == ErrorKind::BrokenPipe
+    }
+}
+
+/// `Option`s are unwrapped with `user_err` when parsing arguments, so
+/// a `None` there means an argument is missing.
+impl<'a> ErrorCode for NoneErrorRich<'a> {
+    fn exit_code(&self) -> i32 {
+        EXIT_USAGE
+    }
+}
+
+impl ErrorCode for FlagError {
+    fn exit_code(&self) -> i32 {
+        EXIT_USAGE
+    }
+}
+
+/// A closed pipe on the output (as in `detab SOURCE | head`) only means
+/// the reader has seen all it wants, so rather than reporting an error,
+/// the program stops quietly and successfully; this keeps pipelines
+/// working under `set -o pipefail`.
+fn exit_quietly_on_broken_pipe<E: ErrorCode>(err: &E) {
+    if err.is_broken_pipe() {
+        process::exit(EXIT_SUCCESS)
+    }
+}
+
+pub fn user_exit(err: SfwError) -> ! {
+    exit_quietly_on_broken_pipe(&err);
+    eprintln!("{}", err);
+    process::exit(err.exit_code())
 }
 
-pub trait SfwRes<T, E: Display> {
+pub trait SfwRes<T, E: Display + ErrorCode> {
     fn unwrap_or_else<F: FnOnce(E) -> T>(self, op: F) -> T;
 
     /// Intended for use late in execution (e.g. in binaries),
-    /// so that the program immediately exits with a user-friendly error message.
+    /// so that the program immediately exits with a user-friendly error
+    /// message and an exit code reflecting the kind of error.
     fn user_err(self, fstr: &str) -> T
     where
         Self: Sized,
     {
         self.unwrap_or_else(|err| {
+            exit_quietly_on_broken_pipe(&err);
+            eprintln!("{}: {}", fstr, err);
+            process::exit(err.exit_code())
+        })
+    }
+
+    /// As `user_err`, but always exits with `status`, for tools whose
+    /// exit codes have a meaning of their own.
+    fn user_err_status(self, fstr: &str, status: i32) -> T
+    where
+        Self: Sized,
+    {
+        self.unwrap_or_else(|err| {
+            exit_quietly_on_broken_pipe(&err);
             eprintln!("{}: {}", fstr, err);
-            process::exit(USER_ERROR_CODE)
+            process::exit(status)
         })
     }
 }
 
-impl<T, E: Display> SfwRes<T, E> for Result<T, E> {
+impl<T, E: Display + ErrorCode> SfwRes<T, E> for Result<T, E> {
     fn unwrap_or_else<F: FnOnce(E) -> T>(self, op: F) -> T {
         self.unwrap_or_else(op)
     }
@@ -49,24 +338,78 @@ impl<'a, T> SfwRes<T, NoneErrorRich<'a>> for Option<T> {
 pub trait SfwResError<T> {
     /// Intended as a potentially non-fatal error, typically
     /// used in library code.
-    fn sfw_err(self, fstr: &str) -> Result<T, Error>
+    fn sfw_err(self, fstr: &str) -> Result<T, SfwError>
     where
         Self: Sized;
+
+    /// As `sfw_err`, but also records the path being operated on.
+    fn sfw_path_err<P: AsRef<Path>>(
+        self,
+        fstr: &str,
+        path: P,
+    ) -> Result<T, SfwError>
+    where
+        Self: Sized,
+    {
+        self.sfw_err(fstr).map_err(|err| err.with_path(path))
+    }
 }
 
-impl<T> SfwResError<T> for Result<T, Error> {
-    fn sfw_err(self, fstr: &str) -> Result<T, Error> {
-        self.map_err(|err| {
-            Error::new(Error::kind(&err), format!("{}: {}", fstr, err))
+impl<T> SfwResError<T> for Result<T, io::Error> {
+    fn sfw_err(self, fstr: &str) -> Result<T, SfwError> {
+        self.map_err(|err| SfwError::Io {
+            op: Some(fstr.to_string()),
+            path: None,
+            source: err,
         })
     }
 }
 
+impl<T> SfwResError<T> for Result<T, SfwError> {
+    fn sfw_err(self, fstr: &str) -> Result<T, SfwError> {
+        self.map_err(|err| SfwError::Context {
+            op: fstr.to_string(),
+            source: Box::new(err),
+        })
+    }
+}
+
+/// A `None` in library code means some expected input was absent.
 impl<T> SfwResError<T> for Option<T> {
-    fn sfw_err(self, fstr: &str) -> Result<T, Error> {
-        match self {
-            Some(s) => Ok(s),
-            None => Err(Error::new(NotFound, fstr)),
-        }
+    fn sfw_err(self, fstr: &str) -> Result<T, SfwError> {
+        self.ok_or_else(|| SfwError::data(fstr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let missing: Result<(), io::Error> =
+            Err(io::Error::new(ErrorKind::NotFound, "gone"));
+        let missing = missing.sfw_err("Couldn't open source");
+        assert_eq!(missing.unwrap_err().exit_code(), EXIT_NOINPUT);
+        assert_eq!(SfwError::usage("bad").exit_code(), EXIT_USAGE);
+        let corrupt: Result<(), SfwError> = Err(SfwError::data_at("bad", 3));
+        assert_eq!(
+            corrupt.sfw_err("Error in expand").unwrap_err().exit_code(),
+            EXIT_DATAERR
+        );
+    }
+
+    #[test]
+    fn test_error_chain_display() {
+        let err = Err::<(), _>(SfwError::data_at("truncated run", 7))
+            .sfw_path_err("Couldn't expand", "in.z")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Couldn't expand: in.z: truncated run (at byte offset 7)"
+        );
+        assert!(matches!(err.root(), SfwError::Data { .. }));
+        let err = SfwError::data_at_line("malformed hunk", 12).with_path("p");
+        assert_eq!(err.to_string(), "p:12: malformed hunk");
     }
 }
@@ -0,0 +1,272 @@
+/*
+`find` from chapter 5 of Software Tools prints the lines of its input
+that match a pattern, written in the book's pattern language (see
+`pattern.rs`). Lines are read one at a time through `BytesIter::lines`,
+so files of any size may be searched.
+
+Like `grep`, and like `compare`, the exit status reports the result: 0
+if any line was selected, 1 if none was, and 2 if there was trouble.
+ */
+
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::pattern::Pattern;
+use crate::util::{open_input, STD_STREAM_NAME};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct FindConf {
+    /// Select the lines that don't match.
+    pub invert: bool,
+    /// Only count lines as matching when the whole line matches.
+    pub whole_lines: bool,
+    /// Prefix lines with their line numbers.
+    pub line_numbers: bool,
+    /// Print only the number of lines selected.
+    pub count: bool,
+    /// Prefix output with the name of the file.
+    pub file_names: bool,
+}
+
+const FIND_USAGE: &str = r#"
+find [OPTION]... PATTERN [FILE]...
+
+Prints the lines of each FILE (or STDIN) that match PATTERN, prefixed
+with the name of the file when there are several.
+Exits with status 0 if any line was selected, 1 if none was, and 2 if
+there was trouble.
+
+Valid options are:
+-v            select lines that don't match
+-x            only count whole lines as matching
+-n            prefix lines with their line numbers
+-c            print only the number of lines selected
+
+PATTERN is made of:
+  c         the character c, unless it is special
+  .         any character
+  ^ or %    the beginning of the line, at the start of PATTERN
+  $         the end of the line, at the end of PATTERN
+  [...]     any character in the class; a-z denotes a range
+  [^...]    any character not in the class
+  *         any number (including none) of the preceding element
+  @c        c, literally; @t is a tab and @n a newline
+
+"#;
+
+pub fn run_find_seahorse_cmd() -> Command {
+    Command::new("find")
+        .description("find: print lines matching a pattern; output to STDOUT")
+        .usage(FIND_USAGE)
+        .action(run_find_seahorse_action)
+        .flag(
+            Flag::new("invert", FlagType::Bool)
+                .alias("v")
+                .description("find -v pattern some_file"),
+        )
+        .flag(
+            Flag::new("whole-lines", FlagType::Bool)
+                .alias("x")
+                .description("find -x pattern some_file"),
+        )
+        .flag(
+            Flag::new("line-numbers", FlagType::Bool)
+                .alias("n")
+                .description("find -n pattern some_file"),
+        )
+        .flag(
+            Flag::new("count", FlagType::Bool)
+                .alias("c")
+                .description("find -c pattern some_file"),
+        )
+}
+
+pub fn run_find_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let pattern = args
+        .next()
+        .user_err_status("find: missing PATTERN", EXIT_TROUBLE);
+    let srcs = args.cloned().collect::<Vec<String>>();
+    let conf = FindConf {
+        invert: ctxt.bool_flag("invert"),
+        whole_lines: ctxt.bool_flag("whole-lines"),
+        line_numbers: ctxt.bool_flag("line-numbers"),
+        count: ctxt.bool_flag("count"),
+        file_names: srcs.len() > 1,
+    };
+    run_find(&conf, pattern, &srcs);
+}
+
+/// Convenience function for running find in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Otherwise, the program exits with status 0 if any line was selected
+/// and 1 if none was.
+pub fn run_find(conf: &FindConf, pattern: &str, srcs: &[String]) {
+    let pattern = Pattern::compile(pattern.as_bytes())
+        .user_err_status("Error in find", EXIT_TROUBLE);
+    let default_src = [String::from(STD_STREAM_NAME)];
+    let srcs = if srcs.is_empty() { &default_src } else { srcs };
+    let mut f_out = BufWriter::new(io::stdout());
+    let status = find_files(conf, &pattern, srcs, &mut f_out)
+        .and_then(|status| {
+            f_out.flush().sfw_err("Couldn't flush output")?;
+            Ok(status)
+        })
+        .user_err_status("Error in find", EXIT_TROUBLE);
+    process::exit(status)
+}
+
+/// Searches each of the files `srcs` in turn, returning the exit status
+/// for the result: `EXIT_SUCCESS` if any line was selected, and
+/// `EXIT_FAILURE` if none was.
+pub fn find_files<W: Write>(
+    conf: &FindConf,
+    pattern: &Pattern,
+    srcs: &[String],
+    f_out: &mut W,
+) -> Result<i32, SfwError> {
+    let found = srcs.iter().try_fold(false, |found, src| {
+        let f_in = open_input(Some(src))?;
+        let selected = find(conf, pattern, (src, f_in), f_out)?;
+        Ok::<bool, SfwError>(found || selected > 0)
+    })?;
+    Ok(if found { EXIT_SUCCESS } else { EXIT_FAILURE })
+}
+
+/// Writes the lines of the named input selected by `pattern`, returning
+/// how many there were.
+pub fn find<R: Read, W: Write>(
+    conf: &FindConf,
+    pattern: &Pattern,
+    (name, f_in): (&str, R),
+    f_out: &mut W,
+) -> Result<u64, SfwError> {
+    let prefix = |f_out: &mut W| -> Result<(), SfwError> {
+        if conf.file_names {
+            write!(f_out, "{}:", name)?;
+        }
+        Ok(())
+    };
+    let anchored;
+    let pattern = if conf.whole_lines {
+        anchored = pattern.anchored();
+        &anchored
+    } else {
+        pattern
+    };
+    let mut selected = 0;
+    for (ix, line) in BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines().enumerate()
+    {
+        let line = line.sfw_path_err("Couldn't read", name)?;
+        if pattern.is_match(&line) == conf.invert {
+            continue;
+        }
+        selected += 1;
+        if !conf.count {
+            prefix(f_out)?;
+            if conf.line_numbers {
+                write!(f_out, "{}:", ix + 1)?;
+            }
+            f_out.write_all(&line)?;
+            f_out.write_all(b"\n")?;
+        }
+    }
+    if conf.count {
+        prefix(f_out)?;
+        writeln!(f_out, "{}", selected)?;
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn find_str(conf: &FindConf, pat: &str, input: &str) -> (u64, String) {
+        let pattern = Pattern::compile(pat.as_bytes()).unwrap();
+        let mut out = vec![];
+        let selected =
+            find(conf, &pattern, ("f", input.as_bytes()), &mut out).unwrap();
+        (selected, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_find_options() {
+        let input = "apple\nbanana\ncherry";
+        let conf = FindConf {
+            line_numbers: true,
+            file_names: true,
+            ..FindConf::default()
+        };
+        assert_eq!(find_str(&conf, "an*a", input), (1, "f:2:banana\n".into()));
+        let conf = FindConf {
+            invert: true,
+            ..FindConf::default()
+        };
+        assert_eq!(find_str(&conf, "^b", input), (2, "apple\ncherry\n".into()));
+        let conf = FindConf {
+            count: true,
+            ..FindConf::default()
+        };
+        assert_eq!(find_str(&conf, "[xyz]$", input), (1, "1\n".into()));
+    }
+
+    #[test]
+    fn test_find_whole_lines() {
+        let input = "aa\naab\n\nbaa\n";
+        let conf = FindConf {
+            whole_lines: true,
+            ..FindConf::default()
+        };
+        assert_eq!(find_str(&conf, "a*", input), (2, "aa\n\n".into()));
+        assert_eq!(find_str(&conf, "^a*b$", input), (1, "aab\n".into()));
+        assert_eq!(find_str(&conf, "a", input), (0, "".into()));
+        let conf = FindConf {
+            invert: true,
+            line_numbers: true,
+            ..conf
+        };
+        assert_eq!(find_str(&conf, "a*", input), (2, "2:aab\n4:baa\n".into()));
+    }
+
+    #[test]
+    fn test_find_files_and_status() {
+        let dir = env::temp_dir();
+        let paths = ["one", "two"].map(|name| {
+            dir.join(format!("sfwtools-find-{}.{}", name, process::id()))
+                .to_string_lossy()
+                .into_owned()
+        });
+        fs::write(&paths[0], "apple\nbanana\n").unwrap();
+        fs::write(&paths[1], "cherry\navocado\n").unwrap();
+        let conf = FindConf {
+            file_names: true,
+            ..FindConf::default()
+        };
+        let run = |pat: &str, srcs: &[String]| {
+            let pattern = Pattern::compile(pat.as_bytes()).unwrap();
+            let mut out = vec![];
+            find_files(&conf, &pattern, srcs, &mut out)
+                .map(|status| (status, String::from_utf8(out).unwrap()))
+        };
+        let found = run("^a", &paths);
+        let not_found = run("^z", &paths);
+        let missing = run("^a", &[format!("{}.missing", paths[0])]);
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+        assert_eq!(
+            found.unwrap(),
+            (
+                EXIT_SUCCESS,
+                format!("{}:apple\n{}:avocado\n", paths[0], paths[1])
+            )
+        );
+        assert_eq!(not_found.unwrap(), (EXIT_FAILURE, "".into()));
+        assert!(missing.is_err());
+    }
+}
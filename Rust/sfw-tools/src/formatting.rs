@@ -0,0 +1,639 @@
+/*
+`format` from chapter 7 of Software Tools is a text formatter in the
+style of roff. Lines of text are filled into output lines as wide as the
+right margin allows and justified by spreading the spaces between words,
+alternately from the left and the right so no river of white runs down
+the page. Lines beginning with `.` are commands:
+
+  .fi  .nf       fill (the default) or don't fill lines
+  .br            break: start a new output line
+  .ce [N]        center the next N lines (1)
+  .ul [N]        underline the next N lines (1)
+  .in [±N]       indent by N (0)
+  .ti [±N]       indent just the next output line by N (0)
+  .rm [±N]       set the right margin to N (60)
+  .pl [±N]       set the page length to N (66)
+  .ls [±N]       set the line spacing to N (1)
+  .sp [N]        leave N blank lines (1)
+  .bp [±N]       begin a page, numbered N (the next)
+  .he TEXT       set the header; # in it is the page number
+  .fo TEXT       set the footer; # in it is the page number
+
+Numbers with a sign are relative to the current value. A blank line
+leaves a blank line, and a line that begins with blanks breaks and is
+indented by as many, temporarily. Unknown commands are ignored.
+
+Each page is laid out as in the book: a blank line, the header and two
+more blank lines, then text, then two blank lines, the footer and one
+last blank line. Underlining is done with backspaces, as `_\bx`, which
+`unoverstrike` (or a pager like `less`) can render.
+
+Widths are measured in terminal columns: wide (East Asian) characters
+take two, combining marks none, and a backspace takes one back.
+ */
+
+use std::io::{Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, opt_usize_flag};
+
+const COMMAND: char = '.';
+const PAGE_NUM: char = '#';
+/// As many lines, or pages, as anyone could want.
+const HUGE: usize = 1000;
+/// Tab stops in leading blanks, as `print` sets them by default.
+const TAB_WIDTH: usize = 8;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FormatConf {
+    pub page_length: usize,
+    pub right_margin: usize,
+}
+
+impl Default for FormatConf {
+    fn default() -> Self {
+        FormatConf {
+            page_length: 66,
+            right_margin: 60,
+        }
+    }
+}
+
+/// The number of terminal columns `text` takes up.
+pub fn display_width(text: &str) -> usize {
+    text.chars().fold(0, |width, ch| match ch {
+        '\u{8}' => width.saturating_sub(1),
+        ch => width + char_width(ch),
+    })
+}
+
+fn char_width(ch: char) -> usize {
+    match ch as u32 {
+        0x00..=0x1f | 0x7f..=0x9f => 0,
+        // Combining marks and zero-width characters.
+        0x0300..=0x036f
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200b..=0x200f
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f
+        | 0xfe20..=0xfe2f => 0,
+        // Wide characters: Hangul Jamo, CJK, fullwidth forms and emoji.
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+/// A numeric command argument: absolute, or relative if signed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Arg {
+    Abs(i64),
+    Rel(i64),
+}
+
+fn parse_arg(args: &str) -> Option<Arg> {
+    let args = args.trim_start();
+    let (rel, digits) = match args.as_bytes().first() {
+        Some(b'+') => (true, &args[1..]),
+        Some(b'-') => (true, args),
+        _ => (false, args),
+    };
+    let end = digits
+        .char_indices()
+        .skip(1)
+        .find(|(_, ch)| !ch.is_ascii_digit())
+        .map_or(digits.len(), |(ix, _)| ix);
+    let num = digits[..end].parse::<i64>().ok()?;
+    Some(if rel { Arg::Rel(num) } else { Arg::Abs(num) })
+}
+
+/// The new value of a parameter set by a command, between `min` and
+/// `max`.
+fn set(
+    param: usize,
+    arg: Option<Arg>,
+    default: usize,
+    min: usize,
+    max: usize,
+) -> usize {
+    let value = match arg {
+        None => default as i64,
+        Some(Arg::Abs(num)) => num,
+        Some(Arg::Rel(delta)) => (param as i64).saturating_add(delta),
+    };
+    value.clamp(min as i64, max.max(min) as i64) as usize
+}
+
+/// Formats text written to it line by line, writing pages to `f_out`.
+pub struct Formatter<W: Write> {
+    f_out: W,
+    fill: bool,
+    line_spacing: usize,
+    indent: usize,
+    temp_indent: usize,
+    right_margin: usize,
+    center_count: usize,
+    underline_count: usize,
+    page_length: usize,
+    /// Lines above the header, between it and the text, between the
+    /// text and the footer, and below the footer.
+    margins: [usize; 4],
+    bottom: usize,
+    header: String,
+    footer: String,
+    page: usize,
+    next_page: usize,
+    /// The next line of the page to be written; 0 before the header.
+    line_num: usize,
+    /// The words of the output line being filled, and its width.
+    words: Vec<String>,
+    width: usize,
+    /// Which side of the line spaces are spread from first.
+    spread_left: bool,
+}
+
+impl<W: Write> Formatter<W> {
+    pub fn new(conf: &FormatConf, f_out: W) -> Self {
+        let margins = [2, 2, 2, 2];
+        let page_length =
+            conf.page_length.max(margins.iter().sum::<usize>() + 1);
+        Formatter {
+            f_out,
+            fill: true,
+            line_spacing: 1,
+            indent: 0,
+            temp_indent: 0,
+            right_margin: conf.right_margin.max(1),
+            center_count: 0,
+            underline_count: 0,
+            page_length,
+            margins,
+            bottom: page_length - margins[2] - margins[3],
+            header: String::new(),
+            footer: String::new(),
+            page: 0,
+            next_page: 1,
+            line_num: 0,
+            words: vec![],
+            width: 0,
+            spread_left: false,
+        }
+    }
+
+    /// Formats every line of `f_in`.
+    pub fn format_input<R: Read>(&mut self, f_in: R) -> Result<(), SfwError> {
+        BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+            .lines()
+            .try_for_each(|line| {
+                let line = line.sfw_err("Couldn't read input")?;
+                self.format_line(&String::from_utf8_lossy(&line))
+            })
+    }
+
+    pub fn format_line(&mut self, line: &str) -> Result<(), SfwError> {
+        match line.strip_prefix(COMMAND) {
+            Some(command) => self.command(command),
+            None => self.text(line),
+        }
+    }
+
+    /// Writes out what remains, finishing the last page, and returns the
+    /// output.
+    pub fn finish(mut self) -> Result<W, SfwError> {
+        self.brk()?;
+        if self.line_num > 0 {
+            self.space(HUGE)?;
+        }
+        self.f_out.flush().sfw_err("Couldn't flush output")?;
+        Ok(self.f_out)
+    }
+
+    fn command(&mut self, command: &str) -> Result<(), SfwError> {
+        let name = command.get(..2).unwrap_or(command);
+        let args = command.get(2..).unwrap_or("");
+        let arg = parse_arg(args);
+        match name {
+            "fi" => {
+                self.brk()?;
+                self.fill = true;
+            }
+            "nf" => {
+                self.brk()?;
+                self.fill = false;
+            }
+            "br" => self.brk()?,
+            "ls" => self.line_spacing = set(self.line_spacing, arg, 1, 1, HUGE),
+            "ce" => {
+                self.brk()?;
+                self.center_count = set(self.center_count, arg, 1, 0, HUGE);
+            }
+            "ul" => {
+                self.underline_count =
+                    set(self.underline_count, arg, 1, 0, HUGE)
+            }
+            "he" => self.header = title(args),
+            "fo" => self.footer = title(args),
+            "bp" => {
+                if self.line_num > 0 {
+                    self.space(HUGE)?;
+                }
+                self.next_page =
+                    set(self.page, arg, self.page + 1, 0, usize::MAX / 2);
+            }
+            "sp" => {
+                let lines = set(0, arg, 1, 0, HUGE);
+                self.space(lines)?;
+            }
+            "in" => {
+                self.brk()?;
+                self.indent =
+                    set(self.indent, arg, 0, 0, self.right_margin - 1);
+                self.temp_indent = self.indent;
+            }
+            "ti" => {
+                self.brk()?;
+                self.temp_indent =
+                    set(self.temp_indent, arg, 0, 0, self.right_margin - 1);
+            }
+            "rm" => {
+                let min = self.temp_indent.max(self.indent) + 1;
+                self.right_margin = set(self.right_margin, arg, 60, min, HUGE);
+            }
+            "pl" => {
+                let min = self.margins.iter().sum::<usize>() + 1;
+                self.page_length = set(self.page_length, arg, 66, min, HUGE);
+                self.bottom =
+                    self.page_length - self.margins[2] - self.margins[3];
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, line: &str) -> Result<(), SfwError> {
+        let mut line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() || line.starts_with([' ', '\t']) {
+            self.brk()?;
+            let body = line.trim_start_matches([' ', '\t']);
+            if !body.is_empty() {
+                let lead = &line[..line.len() - body.len()];
+                self.temp_indent = self.indent + lead_width(lead);
+                line = body.to_string();
+            }
+        }
+        if self.underline_count > 0 {
+            line = underline(&line);
+            self.underline_count -= 1;
+        }
+        if self.center_count > 0 {
+            let width = display_width(&line);
+            self.temp_indent = (self.right_margin + self.temp_indent)
+                .saturating_sub(width)
+                / 2;
+            self.put(&line)?;
+            self.center_count -= 1;
+        } else if line.is_empty() || !self.fill {
+            self.put(&line)?;
+        } else {
+            line.split([' ', '\t'])
+                .filter(|word| !word.is_empty())
+                .try_for_each(|word| self.put_word(word))?;
+        }
+        Ok(())
+    }
+
+    /// Adds a word to the output line, first writing the line out,
+    /// justified, if the word won't fit.
+    fn put_word(&mut self, word: &str) -> Result<(), SfwError> {
+        let word_width = display_width(word);
+        let line_width = self.right_margin.saturating_sub(self.temp_indent);
+        if !self.words.is_empty() && self.width + 1 + word_width > line_width {
+            let line = self.spread(line_width.saturating_sub(self.width));
+            self.words.clear();
+            self.width = 0;
+            self.put(&line)?;
+        }
+        if !self.words.is_empty() {
+            self.width += 1;
+        }
+        self.width += word_width;
+        self.words.push(word.to_string());
+        Ok(())
+    }
+
+    /// The output line with `extra` more spaces spread between its
+    /// words, the wider gaps alternating between the right and left.
+    fn spread(&mut self, extra: usize) -> String {
+        let holes = self.words.len().saturating_sub(1);
+        let mut gaps = vec![1; holes];
+        if extra > 0 && holes > 0 {
+            self.spread_left = !self.spread_left;
+            let mut left = extra;
+            (0..holes).rev().for_each(|gap| {
+                let remaining = gap + 1;
+                let spaces = if self.spread_left {
+                    left / remaining
+                } else {
+                    left.div_ceil(remaining)
+                };
+                gaps[gap] += spaces;
+                left -= spaces;
+            });
+        }
+        let mut line = String::new();
+        self.words.iter().enumerate().for_each(|(ix, word)| {
+            if ix > 0 {
+                line.push_str(&" ".repeat(gaps[ix - 1]));
+            }
+            line.push_str(word);
+        });
+        line
+    }
+
+    /// Writes out the output line being filled, unjustified.
+    fn brk(&mut self) -> Result<(), SfwError> {
+        if !self.words.is_empty() {
+            let line = self.words.join(" ");
+            self.words.clear();
+            self.width = 0;
+            self.put(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an output line at the current indent, followed by the
+    /// blank lines of the line spacing.
+    fn put(&mut self, line: &str) -> Result<(), SfwError> {
+        if self.line_num == 0 || self.line_num > self.bottom {
+            self.head()?;
+        }
+        write!(self.f_out, "{}", " ".repeat(self.temp_indent))?;
+        writeln!(self.f_out, "{}", line)?;
+        self.temp_indent = self.indent;
+        let spacing = self.line_spacing - 1;
+        self.skip(spacing.min(self.bottom.saturating_sub(self.line_num)))?;
+        self.line_num += self.line_spacing;
+        if self.line_num > self.bottom {
+            self.foot()?;
+        }
+        Ok(())
+    }
+
+    /// Leaves `lines` blank lines, or up to the end of the page.
+    fn space(&mut self, lines: usize) -> Result<(), SfwError> {
+        self.brk()?;
+        if self.line_num > self.bottom {
+            return Ok(());
+        }
+        if self.line_num == 0 {
+            self.head()?;
+        }
+        self.skip(lines.min(self.bottom + 1 - self.line_num))?;
+        self.line_num += lines;
+        if self.line_num > self.bottom {
+            self.foot()?;
+        }
+        Ok(())
+    }
+
+    fn head(&mut self) -> Result<(), SfwError> {
+        self.page = self.next_page;
+        self.next_page += 1;
+        let [above, below, _, _] = self.margins;
+        if above > 0 {
+            self.skip(above - 1)?;
+            self.title(&self.header.clone())?;
+        }
+        self.skip(below)?;
+        self.line_num = above + below + 1;
+        Ok(())
+    }
+
+    fn foot(&mut self) -> Result<(), SfwError> {
+        let [_, _, above, below] = self.margins;
+        self.skip(above)?;
+        if below > 0 {
+            self.title(&self.footer.clone())?;
+            self.skip(below - 1)?;
+        }
+        self.line_num = 0;
+        Ok(())
+    }
+
+    fn title(&mut self, title: &str) -> Result<(), SfwError> {
+        let page = self.page.to_string();
+        writeln!(self.f_out, "{}", title.replace(PAGE_NUM, &page))?;
+        Ok(())
+    }
+
+    fn skip(&mut self, lines: usize) -> Result<(), SfwError> {
+        (0..lines).try_for_each(|_| writeln!(self.f_out))?;
+        Ok(())
+    }
+}
+
+/// The width of leading blanks, each tab reaching the next tab stop.
+fn lead_width(lead: &str) -> usize {
+    lead.bytes().fold(0, |col, bt| match bt {
+        b'\t' => col + TAB_WIDTH - col % TAB_WIDTH,
+        _ => col + 1,
+    })
+}
+
+/// The text of a header or footer command; a leading quote may be used
+/// to keep leading blanks.
+fn title(args: &str) -> String {
+    let text = args.trim_start_matches([' ', '\t']);
+    text.strip_prefix('"').unwrap_or(text).to_string()
+}
+
+/// Underlines each visible character of `line` by overstriking.
+fn underline(line: &str) -> String {
+    line.chars()
+        .flat_map(|ch| match ch {
+            ' ' | '\t' | '\u{8}' => vec![ch],
+            ch => vec!['_', '\u{8}', ch],
+        })
+        .collect()
+}
+
+pub fn format<R: Read, W: Write>(
+    conf: &FormatConf,
+    f_in: R,
+    f_out: W,
+) -> Result<(), SfwError> {
+    let mut formatter = Formatter::new(conf, f_out);
+    formatter.format_input(f_in)?;
+    formatter.finish().map(|_| ())
+}
+
+const FORMAT_USAGE: &str = r#"
+format [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Formats the text of SOURCE_FILE (or STDIN) into filled, justified pages,
+following the commands found in it, one per line:
+
+.fi  .nf      fill (the default) or don't fill lines
+.br           start a new output line
+.ce [N]       center the next N lines
+.ul [N]       underline the next N lines
+.in [±N]      indent lines by N
+.ti [±N]      indent the next output line by N
+.rm [±N]      set the right margin to N
+.pl [±N]      set the page length to N
+.ls [±N]      set the line spacing to N
+.sp [N]       leave N blank lines
+.bp [±N]      begin a new page, numbered N
+.he TEXT      set the page header; # stands for the page number
+.fo TEXT      set the page footer; # stands for the page number
+
+Valid options are:
+-l N          the initial page length (default 66)
+-w N          the initial right margin (default 60)
+
+"#;
+
+pub fn run_format_seahorse_cmd() -> Command {
+    Command::new("format")
+        .description(
+            "format: fill, justify and paginate text\
+            ; output to STDOUT is the default",
+        )
+        .usage(FORMAT_USAGE)
+        .action(run_format_seahorse_action)
+        .flag(
+            Flag::new("page-length", FlagType::Int)
+                .alias("l")
+                .description("format -l 60 some_file"),
+        )
+        .flag(
+            Flag::new("width", FlagType::Int)
+                .alias("w")
+                .description("format -w 72 some_file"),
+        )
+}
+
+pub fn run_format_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let f_in =
+        open_input(args.next().map(String::as_str)).user_err("Error in format");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in format");
+    let defaults = FormatConf::default();
+    let conf = FormatConf {
+        page_length: opt_usize_flag(ctxt, "page-length")
+            .user_err("Error in format")
+            .unwrap_or(defaults.page_length),
+        right_margin: opt_usize_flag(ctxt, "width")
+            .user_err("Error in format")
+            .unwrap_or(defaults.right_margin),
+    };
+    run_format(&conf, f_in, f_out);
+}
+
+/// Convenience function for running format in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_format(
+    conf: &FormatConf,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    format(conf, f_in, f_out).user_err("Error in format");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_str(conf: &FormatConf, input: &str) -> Vec<String> {
+        let mut out = vec![];
+        format(conf, input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    const CONF: FormatConf = FormatConf {
+        page_length: 14,
+        right_margin: 20,
+    };
+
+    #[test]
+    fn test_format_fill_and_justify() {
+        let out = format_str(
+            &CONF,
+            ".he Title\n.fo - # -\nthe quick brown fox jumps over \
+             the lazy dog\n\n.ce\ncentre\n",
+        );
+        assert_eq!(
+            out,
+            [
+                "",
+                "Title",
+                "",
+                "",
+                "the  quick brown fox",
+                "jumps over the  lazy",
+                "dog",
+                "",
+                "       centre",
+                "",
+                "",
+                "",
+                "- 1 -",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_commands() {
+        let input = ".nf\n  a  b\n.fi\n.in 2\n.ls 2\nx y\n.ti -2\nz\n\
+                     .bp 5\n.he #\n.ul\nab c\n";
+        let out = format_str(&CONF, input);
+        assert_eq!(out.len(), 28);
+        assert_eq!(&out[4..9], ["  a  b", "  x y", "", "z", ""]);
+        assert_eq!(&out[15..19], ["5", "", "", "  _\u{8}a_\u{8}b _\u{8}c"]);
+    }
+
+    #[test]
+    fn test_format_extreme_arguments_and_tabs() {
+        let input = ".in 5\n.in +9223372036854775807\nx\n\
+                     .in -9223372036854775807\n.nf\n \ty\n";
+        let out = format_str(&CONF, input);
+        assert_eq!(&out[4..6], [" ".repeat(19) + "x", " ".repeat(8) + "y"]);
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("naïve"), 5);
+        assert_eq!(display_width("e\u{301}t\u{e9}"), 3);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("_\u{8}a"), 1);
+        // Wide words are justified by their width, not their length.
+        let conf = FormatConf {
+            right_margin: 10,
+            ..CONF
+        };
+        assert_eq!(format_str(&conf, "日本 語 x y")[4], "日本  語 x");
+    }
+}
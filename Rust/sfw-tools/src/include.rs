@@ -0,0 +1,528 @@
+/*
+The `include` filter from chapter 3 of Software Tools copies its input to
+its output, except that a line of the form
+
+  include "file"
+
+is replaced by the contents of that file, which may in turn include
+others. Names are looked up relative to the including file, then in
+each search directory in turn. The directive word may be changed (to
+`#include`, say), and `#line` markers may be written wherever the
+source file or line changes, so that tools further down the pipeline
+can report positions in the original files.
+ */
+
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::{BytesIter, FlatBytes};
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, STD_STREAM_NAME};
+
+const STDIN_NAME: &str = "<stdin>";
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct IncludeConf {
+    /// The word introducing an include line.
+    pub directive: String,
+    /// Directories searched, in order, after that of the including file.
+    pub search_paths: Vec<PathBuf>,
+    /// Write `#line N "file"` markers when the source position jumps.
+    pub line_markers: bool,
+}
+
+impl Default for IncludeConf {
+    fn default() -> Self {
+        IncludeConf {
+            directive: String::from("include"),
+            search_paths: vec![],
+            line_markers: false,
+        }
+    }
+}
+
+const INCLUDE_USAGE: &str = r#"
+include [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Copies SOURCE_FILE (or STDIN), replacing each line of the form
+    include "file"
+with the contents of that file, recursively.
+
+Valid options are:
+-I DIRS          directories to search for included files, after that
+                 of the including file; separate several with ':'
+-d DIRECTIVE     the word introducing an include line (default include)
+--line-markers   write #line markers wherever the source position jumps
+
+"#;
+
+pub fn run_include_seahorse_cmd() -> Command {
+    Command::new("include")
+        .description(
+            "include: expand include lines with file contents\
+            ; output to STDOUT is the default",
+        )
+        .usage(INCLUDE_USAGE)
+        .action(run_include_seahorse_action)
+        .flag(
+            Flag::new("search", FlagType::String)
+                .alias("I")
+                .description("include -I lib:../lib some_file"),
+        )
+        .flag(
+            Flag::new("directive", FlagType::String)
+                .alias("d")
+                .description("include -d '#include' some_file"),
+        )
+        .flag(
+            Flag::new("line-markers", FlagType::Bool)
+                .description("include --line-markers some_file"),
+        )
+}
+
+pub fn run_include_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let src = args.next().map(String::as_str);
+    let f_in = open_input(src).user_err("Error in include");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in include");
+    let defaults = IncludeConf::default();
+    let conf = IncludeConf {
+        directive: ctxt.string_flag("directive").unwrap_or(defaults.directive),
+        search_paths: ctxt
+            .string_flag("search")
+            .map(|dirs| env::split_paths(&dirs).collect())
+            .unwrap_or(defaults.search_paths),
+        line_markers: ctxt.bool_flag("line-markers"),
+    };
+    if conf.directive.is_empty() {
+        user_exit(SfwError::usage("include: DIRECTIVE must not be empty"));
+    }
+    run_include(&conf, src, f_in, f_out);
+}
+
+/// Convenience function for running include in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_include(
+    conf: &IncludeConf,
+    src: Option<&str>,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    include(conf, src, f_in, f_out).user_err("Error in include");
+}
+
+/// Expands the include lines of `f_in`, which was read from the file
+/// `src` (or if `None`, from STDIN).
+pub fn include<R: Read, W: Write>(
+    conf: &IncludeConf,
+    src: Option<&str>,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let mut chain = vec![IncludeFrame::top(src)];
+    include_go(conf, f_in, &mut f_out, &mut chain)?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// A file being read, with the line reached so far. Other tools that
+/// include files (`macro` and `ratfor`) keep a chain of these too, so
+/// that files are found, and cycles caught, the same way everywhere.
+pub(crate) struct IncludeFrame {
+    /// The canonical path, used to detect cycles.
+    pub(crate) path: Option<PathBuf>,
+    /// The name as written, for messages and markers.
+    pub(crate) name: String,
+    /// The directory searched first for files included from this one.
+    pub(crate) dir: Option<PathBuf>,
+    pub(crate) line: u64,
+}
+
+impl IncludeFrame {
+    /// The frame of the file read first, `src`, or STDIN if `None`.
+    pub(crate) fn top(src: Option<&str>) -> Self {
+        let src = src.filter(|src| *src != STD_STREAM_NAME);
+        IncludeFrame {
+            path: src.and_then(|src| Path::new(src).canonicalize().ok()),
+            name: src.unwrap_or(STDIN_NAME).to_string(),
+            dir: src
+                .and_then(|src| Path::new(src).parent())
+                .map(Path::to_path_buf),
+            line: 0,
+        }
+    }
+
+    /// The frame of a file found by `open_included`.
+    pub(crate) fn included(path: &Path) -> Self {
+        IncludeFrame {
+            path: path.canonicalize().ok(),
+            name: path.to_string_lossy().to_string(),
+            dir: path.parent().map(Path::to_path_buf),
+            line: 0,
+        }
+    }
+}
+
+/// Input read through a stack of files and pushed-back text, for the
+/// tools that expand text in place (`macro` and `ratfor`). Each entry
+/// remembers how deeply nested the expansion that produced it was.
+pub(crate) struct InputStack<'a> {
+    inputs: Vec<Input<'a>>,
+    /// The files being read, outermost first; the first is never popped.
+    frames: Vec<IncludeFrame>,
+    pending_len: usize,
+    max_pending: usize,
+}
+
+enum Input<'a> {
+    Text {
+        text: Vec<u8>,
+        pos: usize,
+        depth: usize,
+    },
+    File {
+        bytes: FlatBytes<Box<dyn Read + 'a>>,
+        depth: usize,
+        /// Whether the next byte starts a line.
+        line_start: bool,
+    },
+}
+
+impl<'a> InputStack<'a> {
+    /// Reads `f_in`, which came from the file `src` (or if `None`, from
+    /// STDIN), allowing at most `max_pending` bytes of pushed-back text.
+    pub(crate) fn new(
+        src: Option<&str>,
+        f_in: Box<dyn Read + 'a>,
+        max_pending: usize,
+    ) -> Self {
+        InputStack {
+            inputs: vec![Input::File {
+                bytes: BytesIter::new(f_in, DEFAULT_BUF_SIZE).flat_bytes(),
+                depth: 0,
+                line_start: true,
+            }],
+            frames: vec![IncludeFrame::top(src)],
+            pending_len: 0,
+            max_pending,
+        }
+    }
+
+    /// The file being read; its line is that of the last byte read.
+    pub(crate) fn frame(&self) -> &IncludeFrame {
+        &self.frames[self.frames.len() - 1]
+    }
+
+    /// An error at the current position.
+    pub(crate) fn err<S: Into<String>>(&self, msg: S) -> SfwError {
+        let frame = self.frame();
+        SfwError::data_at_line(msg, frame.line).with_path(&frame.name)
+    }
+
+    /// The next byte, with the depth of the entry it came from.
+    pub(crate) fn read(&mut self) -> Result<Option<(u8, usize)>, SfwError> {
+        loop {
+            match self.inputs.last_mut() {
+                None => return Ok(None),
+                Some(Input::Text { text, pos, depth }) => {
+                    if let Some(bt) = text.get(*pos) {
+                        *pos += 1;
+                        self.pending_len -= 1;
+                        return Ok(Some((*bt, *depth)));
+                    }
+                }
+                Some(Input::File {
+                    bytes,
+                    depth,
+                    line_start,
+                }) => {
+                    if let Some(bt) = bytes.next() {
+                        let frame =
+                            self.frames.last_mut().sfw_err("no file")?;
+                        let bt =
+                            bt.sfw_path_err("Couldn't read", &frame.name)?;
+                        if *line_start {
+                            frame.line += 1;
+                        }
+                        *line_start = bt == b'\n';
+                        return Ok(Some((bt, *depth)));
+                    }
+                    if self.frames.len() > 1 {
+                        self.frames.pop();
+                    }
+                }
+            }
+            self.inputs.pop();
+        }
+    }
+
+    /// Pushes back `text`, to be read before the rest of the input.
+    pub(crate) fn push_text(
+        &mut self,
+        text: Vec<u8>,
+        depth: usize,
+    ) -> Result<(), SfwError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.pending_len += text.len();
+        if self.pending_len > self.max_pending {
+            return Err(self.err(format!(
+                "pushback overflow: more than {} bytes of pending text",
+                self.max_pending
+            )));
+        }
+        self.inputs.push(Input::Text {
+            text,
+            pos: 0,
+            depth,
+        });
+        Ok(())
+    }
+
+    /// Starts reading the file `target` named in the current one, as
+    /// `include` would find it.
+    pub(crate) fn push_file(
+        &mut self,
+        search_paths: &[PathBuf],
+        target: &str,
+        depth: usize,
+    ) -> Result<(), SfwError> {
+        let (path, file) = open_included(search_paths, &self.frames, target)
+            .map_err(|err| err.with_path(&self.frame().name))?;
+        let f_in: Box<dyn Read + 'a> = Box::new(file);
+        self.frames.push(IncludeFrame::included(&path));
+        self.inputs.push(Input::File {
+            bytes: BytesIter::new(f_in, DEFAULT_BUF_SIZE).flat_bytes(),
+            depth,
+            line_start: true,
+        });
+        Ok(())
+    }
+}
+
+fn write_marker<W: Write>(
+    f_out: &mut W,
+    line: u64,
+    name: &str,
+) -> Result<(), SfwError> {
+    writeln!(f_out, "#line {} \"{}\"", line, name)?;
+    Ok(())
+}
+
+/// Copies the file at the top of `chain`, recursing for each include
+/// line. On return, the caller's frame is again at the top. Returns
+/// whether the file's last line lacked a newline.
+fn include_go<R: Read, W: Write>(
+    conf: &IncludeConf,
+    f_in: R,
+    f_out: &mut W,
+    chain: &mut Vec<IncludeFrame>,
+) -> Result<bool, SfwError> {
+    let name = chain
+        .last()
+        .map(|frame| frame.name.clone())
+        .unwrap_or_default();
+    if conf.line_markers {
+        write_marker(f_out, 1, &name)?;
+    }
+    let mut reader = BufReader::new(f_in);
+    let mut line = vec![];
+    let mut unterminated = false;
+    loop {
+        line.clear();
+        let len = reader
+            .read_until(b'\n', &mut line)
+            .sfw_path_err("Couldn't read", &name)?;
+        if len == 0 {
+            return Ok(unterminated); /* Finished */
+        }
+        let frame = chain.last_mut().sfw_err("include chain is empty")?;
+        frame.line += 1;
+        let line_num = frame.line;
+        match parse_directive(&line, conf.directive.as_bytes()) {
+            None => {
+                f_out.write_all(&line)?;
+                unterminated = line.last() != Some(&b'\n');
+            }
+            Some(target) => {
+                let target = String::from_utf8_lossy(target).to_string();
+                let (path, file) =
+                    open_included(&conf.search_paths, chain, &target)
+                        .map_err(|err| err.with_path(&name))?;
+                chain.push(IncludeFrame::included(&path));
+                // The includer's next line mustn't be glued onto the
+                // included file's last one.
+                if include_go(conf, file, f_out, chain)? {
+                    f_out.write_all(b"\n")?;
+                }
+                unterminated = false;
+                chain.pop();
+                if conf.line_markers {
+                    write_marker(f_out, line_num + 1, &name)?;
+                }
+            }
+        }
+    }
+}
+
+/// Finds and opens a file named in an include line of the file at the
+/// top of `chain`, checking that it isn't already being included. Names
+/// are looked up relative to the including file, then in `search_paths`.
+pub(crate) fn open_included(
+    search_paths: &[PathBuf],
+    chain: &[IncludeFrame],
+    target: &str,
+) -> Result<(PathBuf, File), SfwError> {
+    let frame = chain.last().sfw_err("include chain is empty")?;
+    let here = frame.dir.clone().unwrap_or_default();
+    let target_path = Path::new(target);
+    let candidates = if target_path.is_absolute() {
+        vec![target_path.to_path_buf()]
+    } else {
+        std::iter::once(&here)
+            .chain(search_paths.iter())
+            .map(|dir| dir.join(target_path))
+            .collect()
+    };
+    let path = candidates
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            SfwError::data_at_line(
+                format!("can't find included file \"{}\"", target),
+                frame.line,
+            )
+        })?;
+    let canonical = path.canonicalize().sfw_path_err("Couldn't open", &path)?;
+    if chain
+        .iter()
+        .any(|frame| frame.path.as_ref() == Some(&canonical))
+    {
+        let steps = chain
+            .iter()
+            .map(|frame| format!("{}:{}", frame.name, frame.line))
+            .collect::<Vec<String>>();
+        return Err(SfwError::data_at_line(
+            format!(
+                "include cycle: {} -> {}",
+                steps.join(" -> "),
+                path.display()
+            ),
+            frame.line,
+        ));
+    }
+    let file = File::open(&path).sfw_path_err("Couldn't open", &path)?;
+    Ok((path, file))
+}
+
+/// The file named by an include line, if `line` is one: the directive,
+/// then whitespace and the name, either quoted or as a single word.
+pub fn parse_directive<'a>(
+    line: &'a [u8],
+    directive: &[u8],
+) -> Option<&'a [u8]> {
+    let line = trim_ascii(line);
+    let rest = line.strip_prefix(directive)?;
+    match rest.first() {
+        Some(bt) if bt.is_ascii_whitespace() || *bt == b'"' => (),
+        _ => return None,
+    }
+    match trim_ascii(rest) {
+        [b'"', name @ .., b'"'] if !name.is_empty() => Some(name),
+        name if !name.is_empty()
+            && !name
+                .iter()
+                .any(|bt| bt.is_ascii_whitespace() || *bt == b'"') =>
+        {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+fn trim_ascii(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|bt| !bt.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|bt| !bt.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(name: &str) -> String {
+        format!("{}/test_data/include/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn include_file(
+        conf: &IncludeConf,
+        name: &str,
+    ) -> Result<String, SfwError> {
+        let src = test_data(name);
+        let mut out = vec![];
+        include(conf, Some(&src), File::open(&src).unwrap(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap().replace(&test_data(""), ""))
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive(b"include \"a b\"\n", b"include"),
+            Some(&b"a b"[..])
+        );
+        assert_eq!(
+            parse_directive(b"  #include x.h", b"#include"),
+            Some(&b"x.h"[..])
+        );
+        assert_eq!(parse_directive(b"includes x", b"include"), None);
+        assert_eq!(parse_directive(b"include x y", b"include"), None);
+    }
+
+    #[test]
+    fn test_include_with_search_path_and_markers() {
+        let conf = IncludeConf {
+            search_paths: vec![PathBuf::from(test_data("lib"))],
+            line_markers: true,
+            ..IncludeConf::default()
+        };
+        assert_eq!(
+            include_file(&conf, "main.txt").unwrap(),
+            "#line 1 \"main.txt\"\nstart\n\
+             #line 1 \"lib/part.txt\"\npart\n\
+             #line 3 \"main.txt\"\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_include_unterminated_file() {
+        assert_eq!(
+            include_file(&IncludeConf::default(), "unterminated.txt").unwrap(),
+            "inner\nafter\n"
+        );
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let err = include_file(&IncludeConf::default(), "cycle_a.txt")
+            .unwrap_err()
+            .to_string()
+            .replace(&test_data(""), "");
+        assert_eq!(
+            err,
+            "cycle_b.txt:2: include cycle: cycle_a.txt:1 -> \
+             cycle_b.txt:2 -> cycle_a.txt"
+        );
+    }
+}
@@ -1,12 +1,11 @@
-use std::io::Error;
-use std::io::ErrorKind::InvalidData;
+use crate::error::SfwError;
 
 pub trait IterExtra: Iterator {
-    fn safe_take(self, nn: usize) -> Result<Vec<Self::Item>, Error>;
+    fn safe_take(self, nn: usize) -> Result<Vec<Self::Item>, SfwError>;
 }
 
 impl<I: Iterator> IterExtra for I {
-    fn safe_take(self, nn: usize) -> Result<Vec<Self::Item>, Error>
+    fn safe_take(self, nn: usize) -> Result<Vec<Self::Item>, SfwError>
     where
         I: Sized,
     {
@@ -15,9 +14,10 @@ impl<I: Iterator> IterExtra for I {
         if vec_len == nn {
             Ok(result_vec)
         } else {
-            Err(Error::new(InvalidData
-                           , format!("safe_take could only take {} elements, needed at least {}."
-                                     , vec_len, nn)))
+            Err(SfwError::data(format!(
+                "safe_take could only take {} elements, needed at least {}.",
+                vec_len, nn
+            )))
         }
     }
 }
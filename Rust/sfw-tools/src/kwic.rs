@@ -0,0 +1,375 @@
+/*
+`kwic` and `unrotate` from chapter 4 of Software Tools together build a
+keyword-in-context (permuted) index:
+
+  kwic glossary.txt | sort -f | unrotate
+
+`kwic` writes each line once for every word in it, rotated so that the
+line starts with that word; a fold marker stands where the line's end
+was joined back to its start. Sorting these rotations brings lines
+together by keyword, and `unrotate` then lays each one out with its
+keyword in the middle of the page, after the text that came before it.
+
+Words found in a stop-word list (such as "the" and "a") aren't indexed.
+The fold marker shouldn't occur in the text itself.
+ */
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, opt_usize_flag};
+
+pub const DEFAULT_FOLD: u8 = b'$';
+/// Spaces between the text before a keyword and the keyword itself.
+const GAP: usize = 2;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct KwicConf {
+    pub fold: u8,
+    /// Words that aren't indexed, in lower case.
+    pub stop_words: HashSet<Vec<u8>>,
+}
+
+impl Default for KwicConf {
+    fn default() -> Self {
+        KwicConf {
+            fold: DEFAULT_FOLD,
+            stop_words: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct UnrotateConf {
+    pub fold: u8,
+    /// The width of the output lines; keywords start in the middle.
+    pub width: usize,
+}
+
+impl Default for UnrotateConf {
+    fn default() -> Self {
+        UnrotateConf {
+            fold: DEFAULT_FOLD,
+            width: 80,
+        }
+    }
+}
+
+/// Reads the fold marker given with `-f`, if any.
+fn fold_flag(ctxt: &Context, cmd: &str) -> u8 {
+    match ctxt.string_flag("fold") {
+        Ok(fold) => match fold.as_bytes() {
+            [fold] => *fold,
+            _ => user_exit(SfwError::usage(format!(
+                "{}: the fold marker must be a single character",
+                cmd
+            ))),
+        },
+        Err(_) => DEFAULT_FOLD,
+    }
+}
+
+const KWIC_USAGE: &str = r#"
+kwic [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Writes every rotation of each line of SOURCE_FILE (or STDIN) that starts
+with a word, marking the original line end with the fold marker.
+Sort the output and pass it to unrotate for a permuted index.
+
+Valid options are:
+-f CHAR       the fold marker (default $)
+-s FILE       don't index the words listed in FILE, one per line;
+              case is ignored
+
+"#;
+
+pub fn run_kwic_seahorse_cmd() -> Command {
+    Command::new("kwic")
+        .description(
+            "kwic: rotate lines for a keyword-in-context index\
+            ; output to STDOUT is the default",
+        )
+        .usage(KWIC_USAGE)
+        .action(run_kwic_seahorse_action)
+        .flag(
+            Flag::new("fold", FlagType::String)
+                .alias("f")
+                .description("kwic -f '|' some_file"),
+        )
+        .flag(
+            Flag::new("stop-words", FlagType::String)
+                .alias("s")
+                .description("kwic -s stop_words.txt some_file"),
+        )
+}
+
+pub fn run_kwic_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let f_in =
+        open_input(args.next().map(String::as_str)).user_err("Error in kwic");
+    let f_out =
+        open_output(args.next().map(String::as_str)).user_err("Error in kwic");
+    let stop_words = match ctxt.string_flag("stop-words") {
+        Ok(path) => File::open(&path)
+            .sfw_path_err("Couldn't open stop-word file", &path)
+            .and_then(read_stop_words)
+            .user_err("Error in kwic"),
+        Err(_) => HashSet::new(),
+    };
+    let conf = KwicConf {
+        fold: fold_flag(ctxt, "kwic"),
+        stop_words,
+    };
+    run_kwic(&conf, f_in, f_out);
+}
+
+/// Convenience function for running kwic in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_kwic(conf: &KwicConf, f_in: Box<dyn Read>, f_out: Box<dyn Write>) {
+    kwic(conf, f_in, f_out).user_err("Error in kwic");
+}
+
+/// Reads a stop-word list: one word per line, lower-cased, with blank
+/// lines ignored.
+pub fn read_stop_words<R: Read>(f_in: R) -> Result<HashSet<Vec<u8>>, SfwError> {
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+        .lines()
+        .map(|line| {
+            line.map(|line| line.trim_ascii().to_ascii_lowercase())
+                .sfw_err("Couldn't read stop words")
+        })
+        .filter(|word| !matches!(word, Ok(word) if word.is_empty()))
+        .collect()
+}
+
+pub fn kwic<R: Read, W: Write>(
+    conf: &KwicConf,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+        .lines()
+        .try_for_each(|line| {
+            let line = line.sfw_err("Couldn't read input")?;
+            word_spans(&line)
+                .into_iter()
+                .filter(|(start, end)| {
+                    let word = line[*start..*end].to_ascii_lowercase();
+                    !conf.stop_words.contains(&word)
+                })
+                .try_for_each(|(start, _)| {
+                    f_out.write_all(&line[start..])?;
+                    f_out.write_all(&[conf.fold])?;
+                    f_out.write_all(&line[..start])?;
+                    f_out.write_all(b"\n")
+                })
+                .sfw_err("Couldn't write output")
+        })?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// Words are runs of letters and digits; bytes beyond ASCII count as
+/// letters, so that UTF-8 words are kept whole.
+fn is_word_byte(bt: u8) -> bool {
+    bt.is_ascii_alphanumeric() || !bt.is_ascii()
+}
+
+/// The (start, end) of each word of `line`.
+fn word_spans(line: &[u8]) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = None;
+    line.iter().enumerate().for_each(|(ix, bt)| {
+        match (start, is_word_byte(*bt)) {
+            (None, true) => start = Some(ix),
+            (Some(st), false) => {
+                spans.push((st, ix));
+                start = None;
+            }
+            _ => (),
+        }
+    });
+    if let Some(st) = start {
+        spans.push((st, line.len()));
+    }
+    spans
+}
+
+const UNROTATE_USAGE: &str = r#"
+unrotate [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Lays out the rotated lines written by kwic (usually after sorting them)
+with each keyword starting in the middle of the line, preceded by the
+text that came before it.
+
+Valid options are:
+-f CHAR       the fold marker (default $)
+-w N          the width of output lines (default 80)
+
+"#;
+
+pub fn run_unrotate_seahorse_cmd() -> Command {
+    Command::new("unrotate")
+        .description(
+            "unrotate: lay out a keyword-in-context index\
+            ; output to STDOUT is the default",
+        )
+        .usage(UNROTATE_USAGE)
+        .action(run_unrotate_seahorse_action)
+        .flag(
+            Flag::new("fold", FlagType::String)
+                .alias("f")
+                .description("unrotate -f '|' some_file"),
+        )
+        .flag(
+            Flag::new("width", FlagType::Int)
+                .alias("w")
+                .description("unrotate -w 72 some_file"),
+        )
+}
+
+pub fn run_unrotate_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let f_in = open_input(args.next().map(String::as_str))
+        .user_err("Error in unrotate");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in unrotate");
+    let defaults = UnrotateConf::default();
+    let conf = UnrotateConf {
+        fold: fold_flag(ctxt, "unrotate"),
+        width: opt_usize_flag(ctxt, "width")
+            .user_err("Error in unrotate")
+            .unwrap_or(defaults.width),
+    };
+    run_unrotate(&conf, f_in, f_out);
+}
+
+/// Convenience function for running unrotate in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_unrotate(
+    conf: &UnrotateConf,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    unrotate(conf, f_in, f_out).user_err("Error in unrotate");
+}
+
+/// Writes each rotated line as the text before its keyword, ending just
+/// before the middle of the line, then the keyword and the rest of the
+/// text; either side is cut short if it doesn't fit.
+pub fn unrotate<R: Read, W: Write>(
+    conf: &UnrotateConf,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let middle = conf.width / 2;
+    let before_width = middle.saturating_sub(GAP);
+    let after_width = conf.width - middle;
+    BytesIter::new(f_in, DEFAULT_BUF_SIZE)
+        .lines()
+        .enumerate()
+        .try_for_each(|(ix, line)| {
+            let line = line.sfw_err("Couldn't read input")?;
+            let fold = line
+                .iter()
+                .rposition(|bt| *bt == conf.fold)
+                .ok_or_else(|| {
+                    SfwError::data_at_line("missing fold marker", ix as u64 + 1)
+                })?;
+            let after = line[..fold].trim_ascii_end();
+            let after = &after[..char_offset(after, after_width)];
+            let before = line[fold + 1..].trim_ascii_end();
+            let before_len = char_count(before);
+            let cut = before_len.saturating_sub(before_width);
+            let before = &before[char_offset(before, cut)..];
+            let mut out = vec![b' '; before_width.saturating_sub(before_len)];
+            out.extend_from_slice(before);
+            out.resize(out.len() + middle - before_width, b' ');
+            out.extend_from_slice(after);
+            out.truncate(out.trim_ascii_end().len());
+            out.push(b'\n');
+            f_out.write_all(&out).sfw_err("Couldn't write output")
+        })?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// Widths are counted in characters, so that lines are only ever cut
+/// between the UTF-8 encodings of two characters.
+fn is_char_start(bt: &u8) -> bool {
+    bt & 0xC0 != 0x80
+}
+
+fn char_count(text: &[u8]) -> usize {
+    text.iter().filter(|bt| is_char_start(bt)).count()
+}
+
+/// The byte offset just past the first `count` characters of `text`.
+fn char_offset(text: &[u8], count: usize) -> usize {
+    text.iter()
+        .enumerate()
+        .filter(|(_, bt)| is_char_start(bt))
+        .nth(count)
+        .map_or(text.len(), |(ix, _)| ix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_str<F>(filter: F, input: &str) -> String
+    where
+        F: FnOnce(&[u8], &mut Vec<u8>) -> Result<(), SfwError>,
+    {
+        let mut out = vec![];
+        filter(input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_kwic_with_stop_words() {
+        let conf = KwicConf {
+            stop_words: read_stop_words(&b"The\n\na\n"[..]).unwrap(),
+            ..KwicConf::default()
+        };
+        assert_eq!(
+            run_str(|f_in, f_out| kwic(&conf, f_in, f_out), "the cat, a hat\n"),
+            "cat, a hat$the \nhat$the cat, a \n"
+        );
+    }
+
+    #[test]
+    fn test_unrotate() {
+        let conf = UnrotateConf {
+            width: 20,
+            ..UnrotateConf::default()
+        };
+        assert_eq!(
+            run_str(
+                |f_in, f_out| unrotate(&conf, f_in, f_out),
+                "cat sat on the mat$the \nhat$sat in a red \n"
+            ),
+            "     the  cat sat on\n\
+             in a red  hat\n"
+        );
+        let conf = UnrotateConf {
+            width: 10,
+            ..UnrotateConf::default()
+        };
+        assert_eq!(
+            run_str(
+                |f_in, f_out| unrotate(&conf, f_in, f_out),
+                "\u{e9}t\u{e9} \u{e0} ma\u{ef}s$\u{e7}a \u{e9}t\u{e9} \n\
+                 x$\u{e9} \n"
+            ),
+            "\u{e9}t\u{e9}  \u{e9}t\u{e9} \u{e0}\n\
+             \x20 \u{e9}  x\n"
+        );
+        let err = unrotate(&conf, &b"a\nb\n"[..], vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: missing fold marker");
+    }
+}
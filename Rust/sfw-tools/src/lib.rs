@@ -49,6 +49,26 @@
 //! - [x] `echo`
 //! - [x] `compress`
 //! - [ ] `expand`
+//! - [x] `translit`
+//! - [x] `overstrike`
+//! - [x] `crypt`
+//! - [x] `compare`
+//! - [x] `diff`
+//! - [x] `patch`
+//! - [x] `include`
+//! - [x] `concat`
+//! - [x] `print`
+//! - [x] `sort`
+//! - [x] `unique`
+//! - [x] `kwic`
+//! - [x] `unrotate`
+//! - [x] `shuffle`
+//! - [x] `find`
+//! - [x] `change`
+//! - [x] `edit`
+//! - [x] `format`
+//! - [x] `macro`
+//! - [x] `ratfor`
 //!
 //! ## Dependencies
 //!
@@ -66,6 +86,9 @@
 //!   but will stick with idiomatic Rust where that is obviously simpler.
 //!   An interesting note is that filters are the subject of chapter 2 and much of
 //!   the rest of the book, which are just a particular class of HOFs.
+//! - [func-iter](https://docs.rs/func-iter)
+//!   Our own small companion crate in this repository, with helpers such
+//!   as `sorted_by` for collections; `sort` uses it for its in-memory runs.
 //! - [peeking_take_while](https://docs.rs/peeking_take_while/)
 //!   A small library that provides the `peeking_take_while` function for
 //!   `Peekable` iterators. This behaves more of how would would expect for
@@ -105,6 +128,11 @@
 //!
 //! ## Build
 //!
+//! Besides one binary per tool, `sfwtools` is a busybox-style multicall
+//! binary: when invoked through a link whose name matches a subcommand
+//! (e.g. `wc`), it runs that subcommand directly. The links can be
+//! created with `sfwtools --install DIR`.
+//!
 //! ## Misc Notes
 //!
 //! ### Using todo!() to
@@ -203,13 +231,18 @@
 #![deny(unused_must_use)]
 
 use std::env;
-use std::io::Error;
+use std::fs;
+use std::io::{Error, ErrorKind::AlreadyExists};
+use std::path::{Path, PathBuf};
 
 use seahorse::{App, Command, Context};
 
 pub mod bytes_iter;
 pub use bytes_iter::BytesIter;
 
+pub mod checksum;
+pub use checksum::*;
+
 pub mod constants;
 pub use constants::*;
 
@@ -236,7 +269,67 @@ pub use tabs::*;
 pub mod compression;
 pub use compression::*;
 
-pub fn get_args() -> Result<(String, Vec<String>), Error> {
+pub mod translit;
+pub use translit::*;
+
+pub mod overstrike;
+pub use overstrike::*;
+
+pub mod crypt;
+pub use crypt::*;
+
+pub mod comparing;
+pub use comparing::*;
+
+pub mod diff;
+pub use diff::*;
+
+pub mod patch;
+pub use patch::*;
+
+pub mod include;
+pub use include::*;
+
+pub mod concat;
+pub use concat::*;
+
+pub mod print;
+pub use print::*;
+
+pub mod sorting;
+pub use sorting::*;
+
+pub mod unique;
+pub use unique::*;
+
+pub mod kwic;
+pub use kwic::*;
+
+pub mod pattern;
+pub use pattern::*;
+
+pub mod finding;
+pub use finding::*;
+
+pub mod regex;
+pub use regex::*;
+
+pub mod changing;
+pub use changing::*;
+
+pub mod editing;
+pub use editing::*;
+
+pub mod formatting;
+pub use formatting::*;
+
+pub mod macros;
+pub use macros::*;
+
+pub mod ratfor;
+pub use ratfor::*;
+
+pub fn get_args() -> Result<(String, Vec<String>), SfwError> {
     let mut args_in = env::args();
     let cmd = args_in.next().sfw_err("Impossible: no first arg!")?;
     let args_out: Vec<String> = args_in.collect::<Vec<String>>();
@@ -247,13 +340,83 @@ pub fn get_args() -> Result<(String, Vec<String>), Error> {
 /// a nicer user error message if there are no arguments provided.
 pub fn run_app(app: App, args: Vec<String>, arg_err: &str) {
     match args.len() {
-        0 => user_exit(&format!("{}: Zero arguments in run_app", arg_err)),
+        0 => user_exit(SfwError::usage(format!(
+            "{}: Zero arguments in run_app",
+            arg_err
+        ))),
         _ => app.run(args),
     }
 }
 
-pub fn echo(args: &[String]) {
-    println!("{}", args.join(" "))
+/// The name of the multicall binary; any other `argv[0]` basename is
+/// treated as the name of the subcommand to run.
+pub const MULTICALL_NAME: &str = "sfwtools";
+
+/// Busybox-style dispatch: when the binary is invoked through a link named
+/// after one of the `app`'s commands (e.g. `wc`), the command name is
+/// inserted as the first argument, just as the standalone binaries do.
+pub fn multicall_args(app: &App, mut args: Vec<String>) -> Vec<String> {
+    let invoked_as = args
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned());
+    match invoked_as {
+        Some(name) if name != MULTICALL_NAME && has_command(app, &name) => {
+            args.insert(1, name);
+            args
+        }
+        _ => args,
+    }
+}
+
+fn has_command(app: &App, name: &str) -> bool {
+    app.commands.iter().flatten().any(|cmd| {
+        cmd.name == name
+            || cmd.alias.iter().flatten().any(|alias| alias == name)
+    })
+}
+
+/// Creates a symbolic link in `dir` for each of the `app`'s commands,
+/// pointing at the currently running executable. Aliases (such as `cat`
+/// for `concat`) aren't linked, so as not to shadow the system's tools,
+/// but links made to them by hand work too. Links that already point
+/// there are left alone; any other existing file is an error.
+pub fn install_links(app: &App, dir: &str) -> Result<Vec<PathBuf>, SfwError> {
+    let exe = env::current_exe().sfw_err("Couldn't locate sfwtools binary")?;
+    fs::create_dir_all(dir).sfw_path_err("Couldn't create directory", dir)?;
+    app.commands
+        .iter()
+        .flatten()
+        .map(|cmd| Path::new(dir).join(&cmd.name))
+        .filter_map(|link| match fs::read_link(&link) {
+            Ok(target) if target == exe => None,
+            Ok(_) => Some(Err(SfwError::from(Error::new(
+                AlreadyExists,
+                format!("{} already links elsewhere", link.display()),
+            )))),
+            Err(_) if link.exists() => Some(Err(SfwError::from(Error::new(
+                AlreadyExists,
+                format!("{} already exists", link.display()),
+            )))),
+            Err(_) => Some(symlink(&exe, &link).map(|_| link)),
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> Result<(), SfwError> {
+    std::os::unix::fs::symlink(target, link)
+        .sfw_path_err("Couldn't create link", link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> Result<(), SfwError> {
+    std::os::windows::fs::symlink_file(target, link)
+        .sfw_path_err("Couldn't create link", link)
+}
+
+pub fn echo(args: &[String]) -> Result<(), SfwError> {
+    print_line(&args.join(" ")).sfw_err("Couldn't write to STDOUT")
 }
 
 pub fn echo_app() -> App {
@@ -272,5 +435,60 @@ pub fn run_echo_seahorse_cmd() -> Command {
 }
 
 pub fn run_echo_seahorse_action(ctxt: &Context) {
-    echo(&ctxt.args);
+    echo(&ctxt.args).user_err("Error in echo");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn test_app() -> App {
+        App::new(MULTICALL_NAME)
+            .command(run_wc_seahorse_cmd())
+            .command(run_concat_seahorse_cmd())
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_multicall_args() {
+        let app = test_app();
+        let dispatch = |argv: &[&str]| multicall_args(&app, args(argv));
+        assert_eq!(
+            dispatch(&["/usr/bin/wc", "-l"]),
+            args(&["/usr/bin/wc", "wc", "-l"])
+        );
+        assert_eq!(dispatch(&["cat", "f"]), args(&["cat", "cat", "f"]));
+        assert_eq!(
+            dispatch(&["./sfwtools", "wc", "f"]),
+            args(&["./sfwtools", "wc", "f"])
+        );
+        assert_eq!(dispatch(&["frobnicate", "f"]), args(&["frobnicate", "f"]));
+        assert_eq!(dispatch(&[]), args(&[]));
+    }
+
+    #[test]
+    fn test_install_links() {
+        let dir =
+            env::temp_dir().join(format!("sfwtools-links.{}", process::id()));
+        let dir_str = dir.to_str().unwrap();
+        let app = test_app();
+        let first = install_links(&app, dir_str);
+        let second = install_links(&app, dir_str);
+        let exe = env::current_exe().unwrap();
+        let wc_target = fs::read_link(dir.join("wc"));
+        fs::remove_file(dir.join("concat")).unwrap();
+        fs::write(dir.join("concat"), "not a link").unwrap();
+        let foreign = install_links(&app, dir_str);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first.unwrap(), vec![dir.join("wc"), dir.join("concat")]);
+        assert_eq!(second.unwrap(), Vec::<PathBuf>::new());
+        assert_eq!(wc_target.unwrap(), exe);
+        let err = foreign.unwrap_err();
+        assert_eq!(err.io_kind(), Some(AlreadyExists));
+        assert!(err.to_string().contains("already exists"));
+    }
 }
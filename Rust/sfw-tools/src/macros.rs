@@ -0,0 +1,636 @@
+/*
+`macro` from chapter 8 of Software Tools is a macro processor, much like
+m4. Its input is copied to its output, except that names (a letter or
+`_`, then letters, digits and `_`) that have been defined are replaced
+by their definitions, and the result is read again, so that definitions
+may use other macros. A name may be followed by arguments in
+parentheses, separated by commas, which are themselves expanded before
+the call; `$1` to `$9` in a definition stand for the arguments and `$0`
+for the name. Text in quotes, `like this', is copied without expansion,
+less one level of quotes. The built-in macros are:
+
+  define(name, text)      define name as text
+  ifelse(a, b, c, d)      c if a and b are the same, otherwise d; more
+                          arguments continue the chain, as in m4
+  incr(n)                 n + 1
+  substr(s, m, n)         n characters of s from the m'th (counting
+                          from 1), or the rest of s if n is missing
+  len(s)                  the number of characters in s
+  changeq(l, r)           quote with l and r instead; no arguments
+                          restore ` and '
+  include(file)           the contents of file, read as input
+
+Expansions, like included files, are pushed back onto the input as a
+stack of pending text, each entry remembering how deeply nested the
+expansion that produced it was; going beyond the limit is an error
+rather than a hang, as is a pushback that grows too large.
+ */
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::error::*;
+use crate::include::InputStack;
+use crate::util::{open_input, open_output};
+
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+/// The most bytes of pending text.
+const MAX_PUSHBACK: usize = 1 << 24;
+const LQUOTE: u8 = b'`';
+const RQUOTE: u8 = b'\'';
+const ARG_FLAG: u8 = b'$';
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MacroConf {
+    /// Definitions made before the input is read.
+    pub defines: Vec<(String, String)>,
+    /// Directories searched, in order, after that of the including file.
+    pub search_paths: Vec<PathBuf>,
+    /// How deeply expansions (and included files) may nest.
+    pub max_depth: usize,
+}
+
+impl Default for MacroConf {
+    fn default() -> Self {
+        MacroConf {
+            defines: vec![],
+            search_paths: vec![],
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Builtin {
+    Define,
+    Ifelse,
+    Incr,
+    Substr,
+    Len,
+    Changeq,
+    Include,
+}
+
+const BUILTINS: [(&str, Builtin); 7] = [
+    ("define", Builtin::Define),
+    ("ifelse", Builtin::Ifelse),
+    ("incr", Builtin::Incr),
+    ("substr", Builtin::Substr),
+    ("len", Builtin::Len),
+    ("changeq", Builtin::Changeq),
+    ("include", Builtin::Include),
+];
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Def {
+    Builtin(Builtin),
+    Text(Vec<u8>),
+}
+
+/// A call whose arguments are being collected.
+struct Call {
+    name: Vec<u8>,
+    depth: usize,
+    args: Vec<Vec<u8>>,
+    /// Unmatched parentheses within the current argument.
+    parens: usize,
+}
+
+enum Token {
+    /// A name, and the depth of expansion it came from.
+    Name(Vec<u8>, usize),
+    Byte(u8),
+}
+
+fn is_name_start(bt: u8) -> bool {
+    bt.is_ascii_alphabetic() || bt == b'_'
+}
+
+fn is_name_byte(bt: u8) -> bool {
+    bt.is_ascii_alphanumeric() || bt == b'_'
+}
+
+/// Expands the macros in the input it reads.
+pub struct MacroProcessor<'c, 'a> {
+    conf: &'c MacroConf,
+    input: InputStack<'a>,
+    defs: HashMap<Vec<u8>, Def>,
+    calls: Vec<Call>,
+    quotes: (u8, u8),
+}
+
+impl<'c, 'a> MacroProcessor<'c, 'a> {
+    /// Reads `f_in`, which came from the file `src` (or if `None`, from
+    /// STDIN); files it includes are looked up relative to `src`.
+    pub fn new<R: Read + 'a>(
+        conf: &'c MacroConf,
+        src: Option<&str>,
+        f_in: R,
+    ) -> Self {
+        let mut defs = BUILTINS
+            .iter()
+            .map(|(name, builtin)| {
+                (name.as_bytes().to_vec(), Def::Builtin(*builtin))
+            })
+            .collect::<HashMap<_, _>>();
+        conf.defines.iter().for_each(|(name, text)| {
+            defs.insert(
+                name.as_bytes().to_vec(),
+                Def::Text(text.as_bytes().to_vec()),
+            );
+        });
+        MacroProcessor {
+            conf,
+            input: InputStack::new(src, Box::new(f_in), MAX_PUSHBACK),
+            defs,
+            calls: vec![],
+            quotes: (LQUOTE, RQUOTE),
+        }
+    }
+
+    /// The next byte of input, pending text first, with its depth.
+    fn read(&mut self) -> Result<Option<(u8, usize)>, SfwError> {
+        self.input.read()
+    }
+
+    fn push_back(
+        &mut self,
+        text: Vec<u8>,
+        depth: usize,
+    ) -> Result<(), SfwError> {
+        self.input.push_text(text, depth)
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, SfwError> {
+        let (first, depth) = match self.read()? {
+            Some(read) => read,
+            None => return Ok(None),
+        };
+        if !is_name_start(first) {
+            return Ok(Some(Token::Byte(first)));
+        }
+        let mut name = vec![first];
+        while let Some((bt, bt_depth)) = self.read()? {
+            if is_name_byte(bt) {
+                name.push(bt);
+            } else {
+                self.push_back(vec![bt], bt_depth)?;
+                break;
+            }
+        }
+        Ok(Some(Token::Name(name, depth)))
+    }
+
+    /// Where expanded text goes: the argument being collected, if any.
+    fn emit<W: Write>(
+        &mut self,
+        text: &[u8],
+        f_out: &mut W,
+    ) -> Result<(), SfwError> {
+        match self.calls.last_mut().and_then(|call| call.args.last_mut()) {
+            Some(arg) => arg.extend_from_slice(text),
+            None => f_out.write_all(text)?,
+        }
+        Ok(())
+    }
+
+    fn skip_blanks(&mut self) -> Result<(), SfwError> {
+        while let Some((bt, depth)) = self.read()? {
+            if !bt.is_ascii_whitespace() {
+                return self.push_back(vec![bt], depth);
+            }
+        }
+        Ok(())
+    }
+
+    /// The text up to the matching close quote, after an open quote.
+    fn quoted(&mut self) -> Result<Vec<u8>, SfwError> {
+        let (lquote, rquote) = self.quotes;
+        let mut text = vec![];
+        let mut level = 1;
+        loop {
+            let bt = match self.read()? {
+                Some((bt, _)) => bt,
+                None => {
+                    return Err(SfwError::data("unterminated quoted string"))
+                }
+            };
+            if bt == rquote {
+                level -= 1;
+                if level == 0 {
+                    return Ok(text);
+                }
+            } else if bt == lquote {
+                level += 1;
+            }
+            text.push(bt);
+        }
+    }
+
+    /// Expands all of the input into `f_out`.
+    pub fn process<W: Write>(&mut self, f_out: &mut W) -> Result<(), SfwError> {
+        while let Some(token) = self.token()? {
+            match token {
+                Token::Name(name, depth) if self.defs.contains_key(&name) => {
+                    match self.read()? {
+                        Some((b'(', _)) => {
+                            if self.calls.len() >= self.conf.max_depth {
+                                return Err(too_deep(&name, self.conf));
+                            }
+                            self.calls.push(Call {
+                                name,
+                                depth,
+                                args: vec![vec![]],
+                                parens: 0,
+                            });
+                            self.skip_blanks()?;
+                        }
+                        next => {
+                            if let Some((bt, bt_depth)) = next {
+                                self.push_back(vec![bt], bt_depth)?;
+                            }
+                            self.expand(&name, vec![], depth)?;
+                        }
+                    }
+                }
+                Token::Name(name, _) => self.emit(&name, f_out)?,
+                Token::Byte(bt) if bt == self.quotes.0 => {
+                    let text = self.quoted()?;
+                    self.emit(&text, f_out)?;
+                }
+                Token::Byte(bt) => {
+                    let call = match self.calls.last_mut() {
+                        Some(call) => call,
+                        None => {
+                            f_out.write_all(&[bt])?;
+                            continue;
+                        }
+                    };
+                    match bt {
+                        b'(' => call.parens += 1,
+                        b')' if call.parens > 0 => call.parens -= 1,
+                        b')' => {
+                            let call = self.calls.pop().sfw_err("no call")?;
+                            self.expand(&call.name, call.args, call.depth)?;
+                            continue;
+                        }
+                        b',' if call.parens == 0 => {
+                            call.args.push(vec![]);
+                            self.skip_blanks()?;
+                            continue;
+                        }
+                        _ => (),
+                    }
+                    self.emit(&[bt], f_out)?;
+                }
+            }
+        }
+        match self.calls.last() {
+            Some(call) => Err(SfwError::data(format!(
+                "end of input in the arguments of {}",
+                String::from_utf8_lossy(&call.name)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Pushes back the expansion of a call of `name` made at `depth`.
+    fn expand(
+        &mut self,
+        name: &[u8],
+        args: Vec<Vec<u8>>,
+        depth: usize,
+    ) -> Result<(), SfwError> {
+        if depth >= self.conf.max_depth {
+            return Err(too_deep(name, self.conf));
+        }
+        let def = self.defs.get(name).cloned().sfw_err("undefined macro")?;
+        let text = match def {
+            Def::Text(text) => substitute_args(&text, name, &args),
+            Def::Builtin(builtin) => self.builtin(builtin, args, depth)?,
+        };
+        self.push_back(text, depth + 1)
+    }
+
+    /// The text of a call of `builtin` made at `depth`.
+    fn builtin(
+        &mut self,
+        builtin: Builtin,
+        mut args: Vec<Vec<u8>>,
+        depth: usize,
+    ) -> Result<Vec<u8>, SfwError> {
+        let arg = |ix: usize| args.get(ix).cloned().unwrap_or_default();
+        let text = |ix: usize| String::from_utf8_lossy(&arg(ix)).to_string();
+        match builtin {
+            Builtin::Define => {
+                let name = arg(0);
+                if !name.first().is_some_and(|bt| is_name_start(*bt))
+                    || !name.iter().all(|bt| is_name_byte(*bt))
+                {
+                    return Err(SfwError::data(format!(
+                        "define: invalid name \"{}\"",
+                        String::from_utf8_lossy(&name)
+                    )));
+                }
+                self.defs.insert(name, Def::Text(arg(1)));
+                Ok(vec![])
+            }
+            Builtin::Ifelse => {
+                // Each pair of arguments not alike moves along the chain.
+                while args.len() > 4 && args[0] != args[1] {
+                    args.drain(..3);
+                }
+                match args.len() {
+                    0..=2 => Ok(vec![]),
+                    _ if args[0] == args[1] => Ok(args.swap_remove(2)),
+                    _ => Ok(args.get(3).cloned().unwrap_or_default()),
+                }
+            }
+            Builtin::Incr => {
+                let num = text(0).trim().parse::<i64>().map_err(|_| {
+                    SfwError::data(format!(
+                        "incr: \"{}\" is not a number",
+                        text(0)
+                    ))
+                })?;
+                Ok((num + 1).to_string().into_bytes())
+            }
+            Builtin::Substr => {
+                let number = |ix: usize| {
+                    text(ix).trim().parse::<usize>().map_err(|_| {
+                        SfwError::data(format!(
+                            "substr: \"{}\" is not a position",
+                            text(ix)
+                        ))
+                    })
+                };
+                let start = number(1)?.saturating_sub(1);
+                let count = match args.len() {
+                    0..=2 => usize::MAX,
+                    _ => number(2)?,
+                };
+                let sub =
+                    text(0).chars().skip(start).take(count).collect::<String>();
+                Ok(sub.into_bytes())
+            }
+            Builtin::Len => {
+                Ok(text(0).chars().count().to_string().into_bytes())
+            }
+            Builtin::Changeq => {
+                self.quotes = match (arg(0).as_slice(), arg(1).as_slice()) {
+                    ([], []) => (LQUOTE, RQUOTE),
+                    ([lquote], [rquote]) => (*lquote, *rquote),
+                    _ => {
+                        return Err(SfwError::data(
+                            "changeq: quotes must be single characters",
+                        ))
+                    }
+                };
+                Ok(vec![])
+            }
+            Builtin::Include => {
+                // The file is read in place of the call, like an expansion.
+                let name = text(0);
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(self.input.err("include: missing file name"));
+                }
+                self.input.push_file(
+                    &self.conf.search_paths,
+                    name,
+                    depth + 1,
+                )?;
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+fn too_deep(name: &[u8], conf: &MacroConf) -> SfwError {
+    SfwError::data(format!(
+        "expansions nested more than {} deep, expanding {}",
+        conf.max_depth,
+        String::from_utf8_lossy(name)
+    ))
+}
+
+/// The definition with `$0` replaced by the name and `$1` to `$9` by
+/// the arguments, missing ones being empty.
+fn substitute_args(text: &[u8], name: &[u8], args: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut ix = 0;
+    while ix < text.len() {
+        match (text[ix], text.get(ix + 1)) {
+            (ARG_FLAG, Some(digit)) if digit.is_ascii_digit() => {
+                match (digit - b'0') as usize {
+                    0 => out.extend_from_slice(name),
+                    num => out.extend_from_slice(
+                        args.get(num - 1).map_or(&[][..], Vec::as_slice),
+                    ),
+                }
+                ix += 2;
+            }
+            (bt, _) => {
+                out.push(bt);
+                ix += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Expands the macros in `f_in`, which was read from the file `src` (or
+/// if `None`, from STDIN).
+pub fn macro_process<R: Read, W: Write>(
+    conf: &MacroConf,
+    src: Option<&str>,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    MacroProcessor::new(conf, src, f_in).process(&mut f_out)?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// Splits a `-D` definition, `NAME=VALUE` or just `NAME` (defined as
+/// empty).
+pub fn parse_define(define: &str) -> Result<(String, String), SfwError> {
+    let (name, value) = define.split_once('=').unwrap_or((define, ""));
+    let valid = name.bytes().next().is_some_and(is_name_start)
+        && name.bytes().all(is_name_byte);
+    if valid {
+        Ok((name.to_string(), value.to_string()))
+    } else {
+        Err(SfwError::usage(format!(
+            "macro: invalid definition \"{}\"",
+            define
+        )))
+    }
+}
+
+const MACRO_USAGE: &str = r#"
+macro [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Copies SOURCE_FILE (or STDIN), expanding macros: names defined with
+define(name, text), where $1 to $9 in text stand for the arguments
+of a call, name(arg1, ...), and $0 for the name. Text in `quotes' isn't
+expanded. The other built-in macros are ifelse(a, b, c, d), incr(n),
+substr(s, m, n), len(s), changeq(l, r) and include(file).
+
+Valid options are:
+-D NAME=VALUE   define NAME as VALUE; may be given more than once
+-I DIRS         directories to search for included files, after that
+                of the including file; separate several with ':'
+--depth N       how deeply expansions may nest (default 256)
+
+"#;
+
+pub fn run_macro_seahorse_cmd() -> Command {
+    Command::new("macro")
+        .description(
+            "macro: expand macros with arguments\
+            ; output to STDOUT is the default",
+        )
+        .usage(MACRO_USAGE)
+        .action(run_macro_seahorse_action)
+        .flag(
+            Flag::new("define", FlagType::String)
+                .alias("D")
+                .description("macro -D NAME=VALUE some_file"),
+        )
+        .flag(
+            Flag::new("search", FlagType::String)
+                .alias("I")
+                .description("macro -I templates:../templates some_file"),
+        )
+        .flag(
+            Flag::new("depth", FlagType::Int)
+                .description("macro --depth 64 some_file"),
+        )
+}
+
+pub fn run_macro_seahorse_action(ctxt: &Context) {
+    // Seahorse takes a flag's first occurrence only; the rest are left
+    // among the arguments.
+    let mut defines =
+        ctxt.string_flag("define").into_iter().collect::<Vec<_>>();
+    let mut args = vec![];
+    let mut rest = ctxt.args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-D" | "--define" => match rest.next() {
+                Some(define) => defines.push(define.clone()),
+                None => {
+                    user_exit(SfwError::usage("macro: -D needs NAME=VALUE"))
+                }
+            },
+            _ => args.push(arg.as_str()),
+        }
+    }
+    let defaults = MacroConf::default();
+    let conf = MacroConf {
+        defines: defines
+            .iter()
+            .map(|define| parse_define(define))
+            .collect::<Result<_, _>>()
+            .user_err("Error in macro"),
+        search_paths: ctxt
+            .string_flag("search")
+            .map(|dirs| std::env::split_paths(&dirs).collect())
+            .unwrap_or(defaults.search_paths),
+        max_depth: ctxt
+            .int_flag("depth")
+            .map(|depth| depth.max(1) as usize)
+            .unwrap_or(defaults.max_depth),
+    };
+    let args = &mut args.into_iter();
+    let src = args.next();
+    let f_in = open_input(src).user_err("Error in macro");
+    let f_out = open_output(args.next()).user_err("Error in macro");
+    run_macro(&conf, src, f_in, f_out);
+}
+
+/// Convenience function for running macro in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_macro(
+    conf: &MacroConf,
+    src: Option<&str>,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    macro_process(conf, src, f_in, f_out).user_err("Error in macro");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(conf: &MacroConf, input: &str) -> Result<String, SfwError> {
+        let mut out = vec![];
+        macro_process(conf, None, input.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_macro_definitions_and_arguments() {
+        let conf = MacroConf {
+            defines: vec![parse_define("HOST=example.org").unwrap()],
+            ..MacroConf::default()
+        };
+        let input = "define(url, `https://$1/$2')define(sq, `($1*$1)')\
+                     url(HOST, index) sq(sq(2)) `url(x)' $0\n";
+        assert_eq!(
+            expand(&conf, input).unwrap(),
+            "https://example.org/index ((2*2)*(2*2)) url(x) $0\n"
+        );
+    }
+
+    #[test]
+    fn test_macro_builtins() {
+        let conf = MacroConf::default();
+        let input = "ifelse(a, b, no, a, a, yes, no) ifelse(x, y, z) \
+                     incr(41) substr(héllo, 2, 3) substr(abc, 2) len(héllo) \
+                     changeq([, ])[define(n, 1)]n changeq()`n'\n";
+        assert_eq!(
+            expand(&conf, input).unwrap(),
+            "yes  42 éll bc 5 define(n, 1)n n\n"
+        );
+    }
+
+    #[test]
+    fn test_macro_include() {
+        let dir = format!("{}/test_data/macro/", env!("CARGO_MANIFEST_DIR"));
+        let process = |name: &str| {
+            let src = format!("{}{}", dir, name);
+            let mut out = vec![];
+            let f_in = std::fs::File::open(&src).unwrap();
+            macro_process(&MacroConf::default(), Some(&src), f_in, &mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+                .map_err(|err| err.to_string().replace(&dir, ""))
+        };
+        assert_eq!(process("main.m").unwrap(), "hello world\ndone\n");
+        assert_eq!(
+            process("self.m").unwrap_err(),
+            "self.m:2: include cycle: self.m:2 -> self.m"
+        );
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let conf = MacroConf {
+            max_depth: 16,
+            ..MacroConf::default()
+        };
+        let err = expand(&conf, "define(x, `x')x").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expansions nested more than 16 deep, expanding x"
+        );
+        assert!(expand(&conf, "define(x, `1").is_err());
+        assert!(expand(&conf, "incr(one)").is_err());
+        assert!(expand(&conf, "include(no/such/file)").is_err());
+        assert!(parse_define("1X=2").is_err());
+    }
+}
@@ -1,26 +1,83 @@
 #![deny(unused_must_use)]
 
+use sfwtools::changing::run_change_seahorse_cmd;
+use sfwtools::comparing::run_compare_seahorse_cmd;
 use sfwtools::compression::{
     run_compress_seahorse_cmd, run_expand_seahorse_cmd,
 };
+use sfwtools::concat::run_concat_seahorse_cmd;
 use sfwtools::copying::run_cp_seahorse_cmd;
 use sfwtools::counting::run_wc_seahorse_cmd;
+use sfwtools::crypt::run_crypt_seahorse_cmd;
+use sfwtools::diff::run_diff_seahorse_cmd;
+use sfwtools::editing::run_edit_seahorse_cmd;
+use sfwtools::error::*;
+use sfwtools::finding::run_find_seahorse_cmd;
+use sfwtools::formatting::run_format_seahorse_cmd;
+use sfwtools::include::run_include_seahorse_cmd;
+use sfwtools::kwic::{run_kwic_seahorse_cmd, run_unrotate_seahorse_cmd};
+use sfwtools::macros::run_macro_seahorse_cmd;
+use sfwtools::overstrike::run_overstrike_seahorse_cmd;
+use sfwtools::patch::run_patch_seahorse_cmd;
+use sfwtools::print::run_print_seahorse_cmd;
+use sfwtools::ratfor::run_ratfor_seahorse_cmd;
+use sfwtools::sorting::{run_shuffle_seahorse_cmd, run_sort_seahorse_cmd};
 use sfwtools::tabs::{run_detab_seahorse_cmd, run_entab_seahorse_cmd};
-use sfwtools::{run_app, run_echo_seahorse_cmd};
+use sfwtools::translit::run_translit_seahorse_cmd;
+use sfwtools::unique::run_unique_seahorse_cmd;
+use sfwtools::{install_links, multicall_args, MULTICALL_NAME};
+use sfwtools::{print_line, run_app, run_echo_seahorse_cmd};
 use std::env;
 
 use seahorse::App;
 
+const INSTALL_FLAG: &str = "--install";
+
 fn main() {
-    let app_name: String = String::from("sfwtools");
+    let app_name: String = String::from(MULTICALL_NAME);
     let app = App::new(app_name.clone())
         .author("Brandon Elam Barker")
+        .usage(format!(
+            "{0} COMMAND [ARGS...]\n\t{0} {1} DIR",
+            MULTICALL_NAME, INSTALL_FLAG
+        ))
         .command(run_cp_seahorse_cmd())
         .command(run_wc_seahorse_cmd())
         .command(run_detab_seahorse_cmd())
         .command(run_entab_seahorse_cmd())
         .command(run_echo_seahorse_cmd())
         .command(run_compress_seahorse_cmd())
-        .command(run_expand_seahorse_cmd());
-    run_app(app, env::args().collect(), &app_name)
+        .command(run_expand_seahorse_cmd())
+        .command(run_translit_seahorse_cmd())
+        .command(run_overstrike_seahorse_cmd())
+        .command(run_crypt_seahorse_cmd())
+        .command(run_compare_seahorse_cmd())
+        .command(run_diff_seahorse_cmd())
+        .command(run_patch_seahorse_cmd())
+        .command(run_include_seahorse_cmd())
+        .command(run_concat_seahorse_cmd())
+        .command(run_print_seahorse_cmd())
+        .command(run_sort_seahorse_cmd())
+        .command(run_unique_seahorse_cmd())
+        .command(run_kwic_seahorse_cmd())
+        .command(run_unrotate_seahorse_cmd())
+        .command(run_shuffle_seahorse_cmd())
+        .command(run_find_seahorse_cmd())
+        .command(run_change_seahorse_cmd())
+        .command(run_edit_seahorse_cmd())
+        .command(run_format_seahorse_cmd())
+        .command(run_macro_seahorse_cmd())
+        .command(run_ratfor_seahorse_cmd());
+    let args = multicall_args(&app, env::args().collect());
+    match args.get(1).map(String::as_str) {
+        Some(INSTALL_FLAG) => {
+            let dir = args.get(2).user_err("--install: missing directory");
+            install_links(&app, dir)
+                .user_err("Error in --install")
+                .iter()
+                .try_for_each(|link| print_line(&link.display().to_string()))
+                .user_err("Error in --install");
+        }
+        _ => run_app(app, args, &app_name),
+    }
 }
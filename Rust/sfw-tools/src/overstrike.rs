@@ -0,0 +1,297 @@
+/*
+The overstrike filter from chapter 2 of Software Tools converts backspace
+sequences, such as `x\bx` (bold) or `_\bx` (underline), into line-printer
+carriage control: each output line begins with ' ' to advance the paper
+as usual, or with '+' to print over the previous line.
+
+The reverse direction, for displaying such text on a terminal, resolves
+the characters struck at each column into plain text or ANSI bold and
+underline.
+ */
+
+use std::io::{Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+use tailcall::tailcall;
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, write_u8};
+
+const BACKSPACE: u8 = 0x08;
+const SKIP: u8 = b' ';
+const NOSKIP: u8 = b'+';
+const UNDERSCORE: u8 = b'_';
+
+const ANSI_RESET: &[u8] = b"\x1b[0m";
+const ANSI_BOLD: &[u8] = b"\x1b[1m";
+const ANSI_UNDERLINE: &[u8] = b"\x1b[4m";
+
+/// How overstruck text is rendered by `unoverstrike`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StrikeRender {
+    /// Strip the overstrikes, keeping only the visible character.
+    Plain,
+    /// Use ANSI escape sequences for bold and underlined characters.
+    Ansi,
+}
+
+/// The appearance of a single column once its overstrikes are resolved.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StrikeStyle {
+    Normal,
+    Bold,
+    Underline,
+}
+
+const OVERSTRIKE_USAGE: &str = r#"
+overstrike [OPTION] [SOURCE_FILE [DEST_FILE]]
+
+By default, backspaces are converted to line-printer carriage control:
+lines starting with '+' are printed over the preceding line.
+
+Valid options are:
+--plain       instead, strip backspace overstrikes down to plain text
+--ansi        instead, show overstrikes as ANSI bold and underline
+
+"#;
+
+pub fn run_overstrike_seahorse_cmd() -> Command {
+    Command::new("overstrike")
+        .description(
+            "overstrike: convert backspaces to carriage control\
+            ; output to STDOUT is the default",
+        )
+        .usage(OVERSTRIKE_USAGE)
+        .action(run_overstrike_seahorse_action)
+        .flag(
+            Flag::new("plain", FlagType::Bool)
+                .description("overstrike --plain some_file"),
+        )
+        .flag(
+            Flag::new("ansi", FlagType::Bool)
+                .description("overstrike --ansi some_file"),
+        )
+}
+
+pub fn run_overstrike_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let f_in = open_input(args.next().map(String::as_str))
+        .user_err("Error in overstrike");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in overstrike");
+    let render = match (ctxt.bool_flag("plain"), ctxt.bool_flag("ansi")) {
+        (true, true) => user_exit(SfwError::usage(
+            "overstrike: --plain and --ansi are mutually exclusive",
+        )),
+        (true, false) => Some(StrikeRender::Plain),
+        (false, true) => Some(StrikeRender::Ansi),
+        (false, false) => None,
+    };
+    match render {
+        Some(render) => run_unoverstrike(f_in, f_out, render),
+        None => run_overstrike(f_in, f_out),
+    }
+}
+
+/// Convenience function for running overstrike in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_overstrike(src: Box<dyn Read>, dst: Box<dyn Write>) {
+    overstrike(src, dst).user_err("Error in overstrike");
+}
+
+/// Convenience function for running unoverstrike in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_unoverstrike(
+    src: Box<dyn Read>,
+    dst: Box<dyn Write>,
+    render: StrikeRender,
+) {
+    unoverstrike(src, dst, render).user_err("Error in overstrike");
+}
+
+pub fn overstrike<R: Read, W: Write>(
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
+    overstrike_go(&mut f_out, f_in_iter, vec![].into_iter(), 0, 0)?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// `col` is the column the printer is at, and `new_col` the column
+/// that the pending backspaces (if any) have moved it back to.
+#[tailcall]
+fn overstrike_go<'a, R, W>(
+    f_out: &mut W,
+    mut bytes_iter: BytesIter<R>,
+    mut buf_iter: std::vec::IntoIter<u8>,
+    col: usize,
+    new_col: usize,
+) -> Result<(), SfwError>
+where
+    R: Read,
+    W: Write,
+{
+    match buf_iter.next() {
+        Some(BACKSPACE) => overstrike_go(
+            f_out,
+            bytes_iter,
+            buf_iter,
+            col,
+            new_col.saturating_sub(1),
+        ),
+        Some(byte) => {
+            if new_col < col {
+                write_u8(f_out, b'\n')?;
+                write_u8(f_out, NOSKIP)?;
+                f_out.write_all(&vec![b' '; new_col])?;
+            } else if col == 0 {
+                write_u8(f_out, SKIP)?;
+            }
+            write_u8(f_out, byte)?;
+            let col = match byte {
+                b'\n' => 0,
+                _ => new_col + 1,
+            };
+            overstrike_go(f_out, bytes_iter, buf_iter, col, col)
+        }
+        None => {
+            match bytes_iter.next() {
+                Some(buf_new) => {
+                    let buf_iter = buf_new?.into_iter(); //shadow
+                    overstrike_go(f_out, bytes_iter, buf_iter, col, new_col)
+                }
+                None => Ok(()), /* Finished */
+            }
+        }
+    }
+}
+
+/// Resolves every column struck in a line (as tracked by `unoverstrike`)
+/// to the character that should be shown, and its style.
+/// Spaces don't obscure anything, so they are ignored unless alone.
+pub fn resolve_strikes(strikes: &[u8]) -> (u8, StrikeStyle) {
+    let visible = strikes
+        .iter()
+        .copied()
+        .filter(|bt| *bt != b' ')
+        .collect::<Vec<u8>>();
+    let underlined = visible.contains(&UNDERSCORE);
+    let marks = visible
+        .iter()
+        .copied()
+        .filter(|bt| *bt != UNDERSCORE)
+        .collect::<Vec<u8>>();
+    match (marks.last(), underlined) {
+        (None, _) if visible.len() > 1 => (UNDERSCORE, StrikeStyle::Bold),
+        (None, _) => {
+            (visible.last().copied().unwrap_or(b' '), StrikeStyle::Normal)
+        }
+        (Some(mark), true) => (*mark, StrikeStyle::Underline),
+        (Some(mark), false) if marks.len() > 1 => (*mark, StrikeStyle::Bold),
+        (Some(mark), false) => (*mark, StrikeStyle::Normal),
+    }
+}
+
+pub fn unoverstrike<R: Read, W: Write>(
+    f_in: R,
+    mut f_out: W,
+    render: StrikeRender,
+) -> Result<(), SfwError> {
+    let line = BytesIter::new(f_in, DEFAULT_BUF_SIZE).try_fold(
+        StruckLine::default(),
+        |line, b_slice| {
+            b_slice?.iter().try_fold(line, |mut line, byte| {
+                match *byte {
+                    b'\n' => {
+                        line.write(&mut f_out, render)?;
+                        write_u8(&mut f_out, b'\n')?;
+                        return Ok(StruckLine::default());
+                    }
+                    BACKSPACE => line.col = line.col.saturating_sub(1),
+                    b'\r' => line.col = 0,
+                    byte => line.strike(byte),
+                }
+                Ok::<_, SfwError>(line)
+            })
+        },
+    )?;
+    line.write(&mut f_out, render)?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// The characters struck at each column of the current line.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+struct StruckLine {
+    columns: Vec<Vec<u8>>,
+    col: usize,
+}
+
+impl StruckLine {
+    fn strike(&mut self, byte: u8) {
+        if self.columns.len() <= self.col {
+            self.columns.resize(self.col + 1, vec![]);
+        }
+        self.columns[self.col].push(byte);
+        self.col += 1;
+    }
+
+    fn write<W: Write>(
+        &self,
+        f_out: &mut W,
+        render: StrikeRender,
+    ) -> Result<(), SfwError> {
+        let last_style = self.columns.iter().try_fold(
+            StrikeStyle::Normal,
+            |prev_style, strikes| {
+                let (byte, style) = resolve_strikes(strikes);
+                if render == StrikeRender::Ansi && style != prev_style {
+                    if prev_style != StrikeStyle::Normal {
+                        f_out.write_all(ANSI_RESET)?;
+                    }
+                    match style {
+                        StrikeStyle::Bold => f_out.write_all(ANSI_BOLD)?,
+                        StrikeStyle::Underline => {
+                            f_out.write_all(ANSI_UNDERLINE)?
+                        }
+                        StrikeStyle::Normal => (),
+                    }
+                }
+                write_u8(f_out, byte)?;
+                Ok::<_, SfwError>(style)
+            },
+        )?;
+        if render == StrikeRender::Ansi && last_style != StrikeStyle::Normal {
+            f_out.write_all(ANSI_RESET)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overstrike_carriage_control() {
+        let mut out = vec![];
+        overstrike(&b"ab\x08\x08__ c\n"[..], &mut out).unwrap();
+        assert_eq!(out, b" ab\n+__ c\n");
+    }
+
+    #[test]
+    fn test_unoverstrike() {
+        let input = &b"x\x08x _\x08y abc\x08\x08\x08___\n"[..];
+        let mut plain = vec![];
+        unoverstrike(input, &mut plain, StrikeRender::Plain).unwrap();
+        assert_eq!(plain, b"x y abc\n");
+        let mut ansi = vec![];
+        unoverstrike(input, &mut ansi, StrikeRender::Ansi).unwrap();
+        assert_eq!(
+            String::from_utf8(ansi).unwrap(),
+            "\x1b[1mx\x1b[0m \x1b[4my\x1b[0m \x1b[4mabc\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,963 @@
+/*
+`patch` closes the loop with `diff`: it reads a unified diff and applies
+each hunk to the file it names. As the file may have changed since the
+diff was made, a hunk that no longer matches at its stated line is
+looked for nearby (an offset), and failing that, with up to `fuzz` lines
+of its leading and trailing context ignored. Hunks that still can't be
+placed are saved to a `.rej` file next to the target, in unified form,
+so they can be applied by hand.
+
+Hunks are applied in order, each after the previous one, as is the case
+for any patch produced by `diff`. Files are rewritten atomically, so an
+interrupted `patch` leaves every file either as it was or fully patched.
+ */
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::diff::DiffText;
+use crate::error::*;
+use crate::util::{open_input, opt_usize_flag, write_atomically};
+
+/// The name used in place of a file, for diffs that create or delete one.
+pub const DEV_NULL: &str = "/dev/null";
+const STDIN_NAME: &str = "<stdin>";
+const NO_NEWLINE_MARK: u8 = b'\\';
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PatchConf {
+    /// Leading path components to strip from the names in the patch.
+    pub strip: usize,
+    /// Context lines that may be ignored at each end of a hunk.
+    pub fuzz: usize,
+    pub reverse: bool,
+    /// Report what would happen without changing any files.
+    pub dry_run: bool,
+}
+
+impl Default for PatchConf {
+    fn default() -> Self {
+        PatchConf {
+            strip: 0,
+            fuzz: 2,
+            reverse: false,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum HunkLine {
+    Context(Vec<u8>),
+    Delete(Vec<u8>),
+    Insert(Vec<u8>),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PatchHunk {
+    /// The old range, as given in the `@@` header.
+    pub old_start: usize,
+    pub old_len: usize,
+    /// The new range, as given in the `@@` header.
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<HunkLine>,
+    /// Set if the last old (or new) line has no newline.
+    pub old_unterminated: bool,
+    pub new_unterminated: bool,
+    /// The line of the patch where the hunk starts.
+    pub line: u64,
+    /// The hunk as it appeared in the patch, for rejects.
+    raw: Vec<Vec<u8>>,
+}
+
+impl PatchHunk {
+    fn old_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Delete(text) => {
+                    Some(text.as_slice())
+                }
+                HunkLine::Insert(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Insert(text) => {
+                    Some(text.as_slice())
+                }
+                HunkLine::Delete(_) => None,
+            })
+            .collect()
+    }
+
+    /// The hunk that undoes this one.
+    pub fn reversed(&self) -> Self {
+        PatchHunk {
+            old_start: self.new_start,
+            old_len: self.new_len,
+            new_start: self.old_start,
+            new_len: self.old_len,
+            lines: self
+                .lines
+                .iter()
+                .map(|line| match line {
+                    HunkLine::Context(text) => HunkLine::Context(text.clone()),
+                    HunkLine::Delete(text) => HunkLine::Insert(text.clone()),
+                    HunkLine::Insert(text) => HunkLine::Delete(text.clone()),
+                })
+                .collect(),
+            old_unterminated: self.new_unterminated,
+            new_unterminated: self.old_unterminated,
+            line: self.line,
+            raw: reversed_raw(&self.raw),
+        }
+    }
+
+    /// The leading and trailing context lines, which fuzz may ignore.
+    fn context_ends(&self) -> (usize, usize) {
+        let is_context =
+            |line: &&HunkLine| matches!(line, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+        (leading, trailing)
+    }
+}
+
+/// The changes to a single file.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FilePatch {
+    pub old_path: String,
+    pub new_path: String,
+    pub hunks: Vec<PatchHunk>,
+    /// The `---` and `+++` lines, for rejects.
+    header: Vec<Vec<u8>>,
+}
+
+impl FilePatch {
+    pub fn reversed(&self) -> Self {
+        FilePatch {
+            old_path: self.new_path.clone(),
+            new_path: self.old_path.clone(),
+            hunks: self.hunks.iter().map(PatchHunk::reversed).collect(),
+            header: match self.header.as_slice() {
+                [old, new] => vec![
+                    [&b"--- "[..], &new[4..]].concat(),
+                    [&b"+++ "[..], &old[4..]].concat(),
+                ],
+                header => header.to_vec(),
+            },
+        }
+    }
+}
+
+/// Reverses the text of a hunk as it appeared in the patch: the ranges in
+/// the `@@` header and the `-` and `+` lines trade places, with deletions
+/// still ahead of insertions in each change.
+fn reversed_raw(raw: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut reversed = raw
+        .first()
+        .map(|header| reversed_header(header))
+        .into_iter()
+        .collect::<Vec<_>>();
+    let (mut deletes, mut inserts) = (vec![], vec![]);
+    // Where the previous line went, as a marker line goes along with it.
+    let mut prev = b' ';
+    raw.iter().skip(1).for_each(|line| {
+        let sign = line.first().copied().unwrap_or(b' ');
+        match (sign, prev) {
+            (NO_NEWLINE_MARK, b'-') => deletes.push(line.clone()),
+            (NO_NEWLINE_MARK, b'+') => inserts.push(line.clone()),
+            (b'-', _) => inserts.push([&b"+"[..], &line[1..]].concat()),
+            (b'+', _) => deletes.push([&b"-"[..], &line[1..]].concat()),
+            _ => {
+                reversed.append(&mut deletes);
+                reversed.append(&mut inserts);
+                reversed.push(line.clone());
+            }
+        }
+        prev = match sign {
+            b'-' => b'+',
+            b'+' => b'-',
+            NO_NEWLINE_MARK => prev,
+            _ => b' ',
+        };
+    });
+    reversed.append(&mut deletes);
+    reversed.append(&mut inserts);
+    reversed
+}
+
+/// Swaps the ranges in a `@@ -OLD +NEW @@` header, keeping any text
+/// that follows it.
+fn reversed_header(header: &[u8]) -> Vec<u8> {
+    let header = String::from_utf8_lossy(header);
+    let mut parts = header.splitn(4, ' ');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(at), Some(old), Some(new), Some(rest)) => {
+            format!("{} -{} +{} {}", at, &new[1..], &old[1..], rest)
+                .into_bytes()
+        }
+        _ => header.as_bytes().to_vec(),
+    }
+}
+
+/// Parses the unified diffs in a patch. Lines outside of the diffs (such
+/// as a commit message) are ignored, but within a hunk, anything
+/// unexpected is an error, reported with its line in the patch.
+pub fn parse_patch<R: Read>(
+    name: &str,
+    f_in: R,
+) -> Result<Vec<FilePatch>, SfwError> {
+    let text = DiffText::read(name, f_in)?;
+    parse_patch_lines(&text.lines).map_err(|err| err.with_path(name))
+}
+
+fn parse_patch_lines(lines: &[Vec<u8>]) -> Result<Vec<FilePatch>, SfwError> {
+    let mut patches: Vec<FilePatch> = vec![];
+    let mut ii = 0;
+    while ii < lines.len() {
+        let line = &lines[ii];
+        let line_num = ii as u64 + 1;
+        if line.starts_with(b"GIT binary patch")
+            || (line.starts_with(b"Binary files ")
+                && line.ends_with(b" differ"))
+        {
+            return Err(SfwError::data_at_line(
+                "binary patches are not supported",
+                line_num,
+            ));
+        }
+        match lines.get(ii + 1) {
+            Some(next)
+                if line.starts_with(b"--- ") && next.starts_with(b"+++ ") =>
+            {
+                patches.push(FilePatch {
+                    old_path: header_path(line),
+                    new_path: header_path(next),
+                    hunks: vec![],
+                    header: vec![line.clone(), next.clone()],
+                });
+                ii += 2;
+                continue;
+            }
+            _ => (),
+        }
+        if line.starts_with(b"@@ ") {
+            let file_patch = patches.last_mut().ok_or_else(|| {
+                SfwError::data_at_line(
+                    "hunk found before any '---' and '+++' file header",
+                    line_num,
+                )
+            })?;
+            let (hunk, next_ii) = parse_hunk(lines, ii)?;
+            file_patch.hunks.push(hunk);
+            ii = next_ii;
+        } else {
+            ii += 1;
+        }
+    }
+    Ok(patches)
+}
+
+/// The path in a `---` or `+++` line, without any timestamp.
+fn header_path(line: &[u8]) -> String {
+    let path = &line[4..];
+    let path = path.split(|bt| *bt == b'\t').next().unwrap_or(path);
+    String::from_utf8_lossy(path).trim_end().to_string()
+}
+
+/// Parses a hunk starting at its `@@` line (`lines[start]`), returning
+/// it along with the index of the line following it.
+fn parse_hunk(
+    lines: &[Vec<u8>],
+    start: usize,
+) -> Result<(PatchHunk, usize), SfwError> {
+    let header_line = start as u64 + 1;
+    let header = String::from_utf8_lossy(&lines[start]).to_string();
+    let ((old_start, old_len), (new_start, new_len)) =
+        parse_hunk_header(&header).ok_or_else(|| {
+            SfwError::data_at_line(
+                format!("malformed hunk header '{}'", header),
+                header_line,
+            )
+        })?;
+    let mut hunk = PatchHunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: vec![],
+        old_unterminated: false,
+        new_unterminated: false,
+        line: header_line,
+        raw: vec![lines[start].clone()],
+    };
+    let (mut old_left, mut new_left) = (old_len, new_len);
+    let mut ii = start + 1;
+    loop {
+        let line = match lines.get(ii) {
+            Some(line) if line.first() == Some(&NO_NEWLINE_MARK) => {
+                match hunk.lines.last() {
+                    Some(HunkLine::Context(_)) => {
+                        hunk.old_unterminated = true;
+                        hunk.new_unterminated = true;
+                    }
+                    Some(HunkLine::Delete(_)) => hunk.old_unterminated = true,
+                    Some(HunkLine::Insert(_)) => hunk.new_unterminated = true,
+                    None => {
+                        return Err(SfwError::data_at_line(
+                            "'\\ No newline' marker doesn't follow a line",
+                            ii as u64 + 1,
+                        ))
+                    }
+                }
+                hunk.raw.push(line.clone());
+                ii += 1;
+                continue;
+            }
+            _ if old_left == 0 && new_left == 0 => break,
+            Some(line) => line,
+            None => {
+                return Err(SfwError::data_at_line(
+                    format!(
+                        "patch ends in the middle of the hunk starting at \
+                         line {}",
+                        header_line
+                    ),
+                    ii as u64 + 1,
+                ))
+            }
+        };
+        let (hunk_line, old_used, new_used) = match line.split_first() {
+            // Some editors strip the trailing space of an empty context line
+            None => (HunkLine::Context(vec![]), 1, 1),
+            Some((b' ', text)) => (HunkLine::Context(text.to_vec()), 1, 1),
+            Some((b'-', text)) => (HunkLine::Delete(text.to_vec()), 1, 0),
+            Some((b'+', text)) => (HunkLine::Insert(text.to_vec()), 0, 1),
+            Some(_) => {
+                return Err(SfwError::data_at_line(
+                    "malformed hunk line: expected ' ', '-' or '+'",
+                    ii as u64 + 1,
+                ))
+            }
+        };
+        if old_left < old_used || new_left < new_used {
+            return Err(SfwError::data_at_line(
+                format!(
+                    "hunk has more lines than its header (at line {}) says",
+                    header_line
+                ),
+                ii as u64 + 1,
+            ));
+        }
+        old_left -= old_used;
+        new_left -= new_used;
+        hunk.lines.push(hunk_line);
+        hunk.raw.push(line.clone());
+        ii += 1;
+    }
+    Ok((hunk, ii))
+}
+
+/// Parses `@@ -OLD_START[,OLD_LEN] +NEW_START[,NEW_LEN] @@`, where an
+/// omitted length is 1.
+fn parse_hunk_header(header: &str) -> Option<((usize, usize), (usize, usize))> {
+    let mut parts = header.split(' ');
+    let parse_range = |range: Option<&str>, sign: char| {
+        let range = range?.strip_prefix(sign)?;
+        let mut nums = range.splitn(2, ',');
+        let start = nums.next()?.parse().ok()?;
+        let len = match nums.next() {
+            Some(len) => len.parse().ok()?,
+            None => 1,
+        };
+        Some((start, len))
+    };
+    match (parts.next(), parse_range(parts.next(), '-')) {
+        (Some("@@"), Some(old)) => {
+            let new = parse_range(parts.next(), '+')?;
+            match parts.next() {
+                Some("@@") => Some((old, new)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// What became of a hunk.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HunkOutcome {
+    /// Applied at the given (1-based) line of the original file, which is
+    /// `offset` lines from where the hunk said, ignoring `fuzz` lines of
+    /// context at either end.
+    Applied {
+        line: usize,
+        offset: isize,
+        fuzz: usize,
+    },
+    Failed,
+}
+
+/// A line of a file being patched, and whether it lacks a newline.
+type PatchLine = (Vec<u8>, bool);
+
+/// Applies the hunks in order, returning the patched lines and the
+/// outcome of each hunk. Failed hunks leave the lines unchanged.
+pub fn apply_hunks(
+    text: &DiffText,
+    hunks: &[PatchHunk],
+    max_fuzz: usize,
+) -> (Vec<PatchLine>, Vec<HunkOutcome>) {
+    let lines = text
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(ii, line)| {
+            (
+                line.clone(),
+                text.unterminated && ii + 1 == text.lines.len(),
+            )
+        })
+        .collect::<Vec<PatchLine>>();
+    let mut out = Vec::with_capacity(lines.len());
+    let mut cursor = 0;
+    let mut last_offset: isize = 0;
+    let outcomes = hunks
+        .iter()
+        .map(|hunk| {
+            let old_lines = hunk.old_lines();
+            let new_lines = hunk.new_lines();
+            let (leading, trailing) = hunk.context_ends();
+            let stated = match hunk.old_len {
+                0 => hunk.old_start,
+                _ => hunk.old_start.saturating_sub(1),
+            };
+            let found = (0..=max_fuzz).find_map(|fuzz| {
+                let front = fuzz.min(leading);
+                let back = fuzz.min(trailing);
+                if front + back > old_lines.len() {
+                    return None;
+                }
+                let pattern = &old_lines[front..old_lines.len() - back];
+                let expected = stated as isize + last_offset + front as isize;
+                find_nearest(&lines, cursor, expected, pattern)
+                    .map(|pos| (pos, fuzz, front, back))
+            });
+            match found {
+                Some((pos, fuzz, front, back)) => {
+                    out.extend_from_slice(&lines[cursor..pos]);
+                    let replacement = &new_lines[front..new_lines.len() - back];
+                    out.extend(replacement.iter().enumerate().map(
+                        |(ii, line)| {
+                            let last = front + ii + 1 == new_lines.len();
+                            (line.to_vec(), last && hunk.new_unterminated)
+                        },
+                    ));
+                    cursor = pos + old_lines.len() - front - back;
+                    let line = pos - front;
+                    last_offset = line as isize - stated as isize;
+                    HunkOutcome::Applied {
+                        line: line + 1,
+                        offset: last_offset,
+                        fuzz,
+                    }
+                }
+                None => HunkOutcome::Failed,
+            }
+        })
+        .collect();
+    out.extend_from_slice(&lines[cursor..]);
+    (out, outcomes)
+}
+
+/// The position at or after `cursor` where `pattern` matches `lines`,
+/// closest to `expected`.
+fn find_nearest(
+    lines: &[PatchLine],
+    cursor: usize,
+    expected: isize,
+    pattern: &[&[u8]],
+) -> Option<usize> {
+    if pattern.len() > lines.len() - cursor {
+        return None;
+    }
+    let last = (lines.len() - pattern.len()) as isize;
+    let expected = expected.clamp(cursor as isize, last);
+    let matches_at = |pos: isize| {
+        (cursor as isize..=last).contains(&pos)
+            && pattern
+                .iter()
+                .zip(&lines[pos as usize..])
+                .all(|(want, (have, _))| *want == have.as_slice())
+    };
+    (0..=(last - cursor as isize)).find_map(|dist| {
+        [expected - dist, expected + dist]
+            .iter()
+            .copied()
+            .find(|pos| matches_at(*pos))
+            .map(|pos| pos as usize)
+    })
+}
+
+const PATCH_USAGE: &str = r#"
+patch [OPTION]... [PATCH_FILE]
+
+Applies the unified diffs in PATCH_FILE (or STDIN) to the files they
+name. Hunks that can't be applied are saved to FILE.rej.
+Exits with status 0 if every hunk was applied, 1 if some failed, and 2
+if there was trouble.
+
+Valid options are:
+-p N          strip the first N components from file names (default 0)
+-F N          ignore up to N lines of context when placing a hunk
+              (default 2)
+-R            reverse the patch, undoing its changes
+--dry-run     only report what would happen; change no files
+
+"#;
+
+pub fn run_patch_seahorse_cmd() -> Command {
+    Command::new("patch")
+        .description(
+            "patch: apply a unified diff to files\
+            ; report to STDOUT",
+        )
+        .usage(PATCH_USAGE)
+        .action(run_patch_seahorse_action)
+        .flag(
+            Flag::new("strip", FlagType::Int)
+                .alias("p")
+                .description("patch -p 1 changes.diff"),
+        )
+        .flag(
+            Flag::new("fuzz", FlagType::Int)
+                .alias("F")
+                .description("patch -F 0 changes.diff"),
+        )
+        .flag(
+            Flag::new("reverse", FlagType::Bool)
+                .alias("R")
+                .description("patch -R changes.diff"),
+        )
+        .flag(
+            Flag::new("dry-run", FlagType::Bool)
+                .description("patch --dry-run changes.diff"),
+        )
+}
+
+pub fn run_patch_seahorse_action(ctxt: &Context) {
+    let defaults = PatchConf::default();
+    let conf = PatchConf {
+        strip: opt_usize_flag(ctxt, "strip")
+            .user_err("Error in patch")
+            .unwrap_or(defaults.strip),
+        fuzz: opt_usize_flag(ctxt, "fuzz")
+            .user_err("Error in patch")
+            .unwrap_or(defaults.fuzz),
+        reverse: ctxt.bool_flag("reverse"),
+        dry_run: ctxt.bool_flag("dry-run"),
+    };
+    run_patch(ctxt.args.first().map(String::as_str), &conf);
+}
+
+/// Convenience function for running patch in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+/// Otherwise, the program exits with status 0 if every hunk was
+/// applied, and 1 if some failed.
+pub fn run_patch(patch_file: Option<&str>, conf: &PatchConf) {
+    let f_in =
+        open_input(patch_file).user_err_status("Error in patch", EXIT_TROUBLE);
+    let name = patch_file.unwrap_or(STDIN_NAME);
+    let all_applied = patch(name, f_in, conf, io::stdout())
+        .user_err_status("Error in patch", EXIT_TROUBLE);
+    process::exit(if all_applied {
+        EXIT_SUCCESS
+    } else {
+        EXIT_FAILURE
+    })
+}
+
+/// Applies the named patch, writing a report of each file and failed or
+/// displaced hunk to `f_out`. Returns `true` if every hunk was applied.
+pub fn patch<R: Read, W: Write>(
+    name: &str,
+    f_in: R,
+    conf: &PatchConf,
+    mut f_out: W,
+) -> Result<bool, SfwError> {
+    let patches = parse_patch(name, f_in)?;
+    if patches.is_empty() {
+        return Err(SfwError::data("no unified diffs found").with_path(name));
+    }
+    let all_applied =
+        patches.iter().try_fold(true, |all_applied, file_patch| {
+            let file_patch = if conf.reverse {
+                file_patch.reversed()
+            } else {
+                file_patch.clone()
+            };
+            Ok::<_, SfwError>(
+                patch_file(&file_patch, conf, &mut f_out)? && all_applied,
+            )
+        })?;
+    f_out.flush().sfw_err("Couldn't flush output")?;
+    Ok(all_applied)
+}
+
+/// Removes the first `strip` components of a path from a patch. Paths
+/// that are still absolute, or that climb out through `..`, are refused,
+/// so that a patch can't reach outside the working tree.
+pub fn strip_path(path: &str, strip: usize) -> Option<PathBuf> {
+    let components = Path::new(path).components().collect::<Vec<_>>();
+    match components.get(strip..) {
+        Some(rest)
+            if !rest.is_empty()
+                && rest.iter().all(|component| {
+                    matches!(
+                        component,
+                        Component::Normal(_) | Component::CurDir
+                    )
+                }) =>
+        {
+            Some(rest.iter().collect())
+        }
+        _ => None,
+    }
+}
+
+/// The file a patch applies to: the new name, unless only the old one
+/// exists (as when the file is being renamed, or for a patch made with
+/// `diff file.orig file`). Files being created or deleted are named
+/// `/dev/null`.
+fn target_path(file_patch: &FilePatch, strip: usize) -> Option<PathBuf> {
+    let old = strip_path(&file_patch.old_path, strip);
+    let new = strip_path(&file_patch.new_path, strip);
+    match (file_patch.old_path.as_str(), file_patch.new_path.as_str()) {
+        (_, DEV_NULL) => old,
+        (DEV_NULL, _) => new,
+        _ => match (old, new) {
+            (_, Some(new)) if new.exists() => Some(new),
+            (Some(old), _) if old.exists() => Some(old),
+            (_, new) => new,
+        },
+    }
+}
+
+fn patch_file<W: Write>(
+    file_patch: &FilePatch,
+    conf: &PatchConf,
+    f_out: &mut W,
+) -> Result<bool, SfwError> {
+    let first_line = file_patch.hunks.first().map_or(0, |hunk| hunk.line);
+    let path = target_path(file_patch, conf.strip).ok_or_else(|| {
+        SfwError::data_at_line(
+            format!(
+                "can't strip {} components from '{}', or the rest is \
+                 absolute or contains '..'",
+                conf.strip, file_patch.new_path
+            ),
+            first_line,
+        )
+    })?;
+    // An insert-only hunk has an empty old range, and a delete-only one
+    // an empty new range, so only `/dev/null` marks a file as created or
+    // deleted; a missing file patched by a single `-0,0` hunk (as `diff
+    // -N` would make) starts out empty.
+    let creating = file_patch.old_path == DEV_NULL;
+    let deleting = file_patch.new_path == DEV_NULL;
+    let from_empty = creating
+        || matches!(
+            file_patch.hunks.as_slice(),
+            [hunk] if hunk.old_start == 0 && hunk.old_len == 0
+        );
+    let verb = if conf.dry_run { "checking" } else { "patching" };
+    writeln!(f_out, "{} file {}", verb, path.display())?;
+    let text = match fs::File::open(&path) {
+        Ok(file) => Some(DiffText::read(&path.to_string_lossy(), file)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            return Err(SfwError::from(err).with_path(&path))
+                .sfw_err("Couldn't open file to patch")
+        }
+    };
+    let (lines, outcomes) = match (text, from_empty) {
+        (Some(text), true) if creating && !text.lines.is_empty() => {
+            writeln!(f_out, "file to be created already exists")?;
+            (vec![], vec![HunkOutcome::Failed; file_patch.hunks.len()])
+        }
+        (Some(text), _) => apply_hunks(&text, &file_patch.hunks, conf.fuzz),
+        (None, true) => {
+            let empty = DiffText {
+                name: path.to_string_lossy().to_string(),
+                lines: vec![],
+                unterminated: false,
+            };
+            apply_hunks(&empty, &file_patch.hunks, conf.fuzz)
+        }
+        (None, false) => {
+            writeln!(f_out, "can't find file to patch; skipping it")?;
+            return Ok(false);
+        }
+    };
+    outcomes.iter().enumerate().try_for_each(
+        |(ii, outcome)| match outcome {
+            HunkOutcome::Applied { line, offset, fuzz }
+                if *offset != 0 || *fuzz != 0 =>
+            {
+                write!(f_out, "Hunk #{} succeeded at {}", ii + 1, line)?;
+                if *fuzz != 0 {
+                    write!(f_out, " with fuzz {}", fuzz)?;
+                }
+                if *offset != 0 {
+                    let plural = if offset.abs() == 1 { "" } else { "s" };
+                    write!(f_out, " (offset {} line{})", offset, plural)?;
+                }
+                writeln!(f_out, ".")
+            }
+            HunkOutcome::Applied { .. } => Ok(()),
+            HunkOutcome::Failed => writeln!(
+                f_out,
+                "Hunk #{} FAILED at {}.",
+                ii + 1,
+                file_patch.hunks[ii].old_start
+            ),
+        },
+    )?;
+    let failed = file_patch
+        .hunks
+        .iter()
+        .zip(outcomes.iter())
+        .filter(|(_, outcome)| **outcome == HunkOutcome::Failed)
+        .map(|(hunk, _)| hunk)
+        .collect::<Vec<&PatchHunk>>();
+    let applied = outcomes.len() - failed.len();
+    if !failed.is_empty() {
+        let plural = if outcomes.len() == 1 { "" } else { "s" };
+        write!(
+            f_out,
+            "{} out of {} hunk{} FAILED",
+            failed.len(),
+            outcomes.len(),
+            plural
+        )?;
+        if conf.dry_run {
+            writeln!(f_out)?;
+        } else {
+            let rej_path = PathBuf::from(format!("{}.rej", path.display()));
+            writeln!(
+                f_out,
+                " -- saving rejects to file {}",
+                rej_path.display()
+            )?;
+            write_atomically(&rej_path, |rej_out| {
+                file_patch
+                    .header
+                    .iter()
+                    .chain(failed.iter().flat_map(|hunk| hunk.raw.iter()))
+                    .try_for_each(|line| {
+                        rej_out.write_all(line)?;
+                        rej_out.write_all(b"\n")
+                    })?;
+                Ok(())
+            })?;
+        }
+    }
+    if !conf.dry_run && applied > 0 {
+        if deleting && lines.is_empty() {
+            fs::remove_file(&path).sfw_path_err("Couldn't delete", &path)?;
+        } else {
+            write_atomically(&path, |file_out| {
+                let last = lines.len();
+                lines.iter().enumerate().try_for_each(
+                    |(ii, (line, unterm))| {
+                        file_out.write_all(line)?;
+                        if !(*unterm && ii + 1 == last) {
+                            file_out.write_all(b"\n")?;
+                        }
+                        Ok::<_, io::Error>(())
+                    },
+                )?;
+                Ok(())
+            })?;
+        }
+    }
+    Ok(failed.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::*;
+
+    fn text(lines: &str) -> DiffText {
+        DiffText::read("t", lines.as_bytes()).unwrap()
+    }
+
+    fn joined(lines: &[PatchLine]) -> String {
+        lines
+            .iter()
+            .map(|(line, _)| String::from_utf8_lossy(line) + "\n")
+            .collect()
+    }
+
+    fn unified(old: &str, new: &str) -> Vec<u8> {
+        let mut out = vec![];
+        diff(
+            ("a", old.as_bytes()),
+            ("b", new.as_bytes()),
+            &DiffConf::default(),
+            &mut out,
+        )
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_apply_with_offset_and_fuzz() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n";
+        let patches = parse_patch("p", &unified(old, new)[..]).unwrap();
+        let hunks = &patches[0].hunks;
+
+        let (lines, outcomes) = apply_hunks(&text(old), hunks, 0);
+        assert_eq!(joined(&lines), new);
+        assert!(matches!(
+            outcomes[0],
+            HunkOutcome::Applied { offset: 0, .. }
+        ));
+
+        let shifted = format!("0\n0\n{}", old);
+        let (lines, outcomes) = apply_hunks(&text(&shifted), hunks, 0);
+        assert_eq!(joined(&lines), format!("0\n0\n{}", new));
+        assert!(matches!(
+            outcomes[0],
+            HunkOutcome::Applied { offset: 2, .. }
+        ));
+
+        let fuzzy = "1\nTWO\n3\n4\n5\n6\n7\n8\n";
+        let (_, outcomes) = apply_hunks(&text(fuzzy), hunks, 0);
+        assert_eq!(outcomes[0], HunkOutcome::Failed);
+        let (lines, outcomes) = apply_hunks(&text(fuzzy), hunks, 1);
+        assert_eq!(joined(&lines), "1\nTWO\n3\n4\nfive\n6\n7\n8\n");
+        assert!(matches!(outcomes[0], HunkOutcome::Applied { fuzz: 1, .. }));
+
+        let reversed =
+            hunks.iter().map(PatchHunk::reversed).collect::<Vec<_>>();
+        let (lines, _) = apply_hunks(&text(new), &reversed, 0);
+        assert_eq!(joined(&lines), old);
+    }
+
+    #[test]
+    fn test_malformed_patch_errors() {
+        let bad_line = b"--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n*b\n";
+        let err = parse_patch("p", &bad_line[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "p:5: malformed hunk line: expected ' ', '-' or '+'"
+        );
+        let truncated = b"--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n";
+        let err = parse_patch("p", &truncated[..]).unwrap_err();
+        assert!(err.to_string().starts_with("p:5: patch ends"));
+        let binary = b"diff a b\nBinary files a and b differ\n";
+        let err = parse_patch("p", &binary[..]).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_DATAERR);
+        assert!(err.to_string().starts_with("p:2: binary"));
+    }
+
+    #[test]
+    fn test_strip_path() {
+        assert_eq!(strip_path("a/src/lib.rs", 1), Some("src/lib.rs".into()));
+        assert_eq!(strip_path("lib.rs", 1), None);
+        assert_eq!(strip_path("/etc/passwd", 0), None);
+        assert_eq!(strip_path("/etc/passwd", 1), Some("etc/passwd".into()));
+        assert_eq!(strip_path("a/../../b", 1), None);
+        let escape = b"--- ../x\n+++ ../x\n@@ -0,0 +1 @@\n+x\n";
+        let err = patch("p", &escape[..], &PatchConf::default(), vec![]);
+        assert!(err.unwrap_err().to_string().contains("'..'"));
+    }
+
+    #[test]
+    fn test_reversed_rejects() {
+        let dir = format!("patch-rej-test.{}", process::id());
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/f", dir);
+        fs::write(&path, "x\ny\nz\n").unwrap();
+        let unified = format!(
+            "--- {0}\t(old)\n+++ {0}\t(new)\n@@ -1,3 +1,4 @@ fn\n 1\n-2\n\
+             -3\n\\ No newline at end of file\n+two\n+three\n+four\n",
+            path
+        );
+        let conf = PatchConf {
+            reverse: true,
+            ..PatchConf::default()
+        };
+        let applied = patch("p", unified.as_bytes(), &conf, vec![]);
+        let rejects = fs::read_to_string(format!("{}.rej", path));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!applied.unwrap());
+        assert_eq!(
+            rejects.unwrap(),
+            format!(
+                "--- {0}\t(new)\n+++ {0}\t(old)\n@@ -1,4 +1,3 @@ fn\n 1\n\
+                 -two\n-three\n-four\n+2\n+3\n\\ No newline at end of file\n",
+                path
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_patch_roundtrip_without_context() {
+        // Patches name files relative to the working directory, which is
+        // the crate's while testing.
+        let dir = format!("patch-test.{}", process::id());
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/f", dir);
+        let conf = DiffConf {
+            context: 0,
+            ..DiffConf::default()
+        };
+        let cases = [
+            ("1\n2\n3\n", "1\n2\nx\n3\n", "@@ -2,0 +3 @@"),
+            ("1\n", "0\n1\n", "@@ -0,0 +1 @@"),
+            ("1\n2\n", "", "@@ -1,2 +0,0 @@"),
+        ];
+        let results = cases
+            .iter()
+            .map(|(old, new, _)| {
+                fs::write(&path, old).unwrap();
+                let mut unified = vec![];
+                let names = (path.as_str(), path.as_str());
+                diff(
+                    (names.0, old.as_bytes()),
+                    (names.1, new.as_bytes()),
+                    &conf,
+                    &mut unified,
+                )
+                .unwrap();
+                let conf = PatchConf::default();
+                let applied = patch("p", &unified[..], &conf, vec![]);
+                (unified, applied.unwrap(), fs::read_to_string(&path))
+            })
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        cases.iter().zip(results).for_each(
+            |((_, new, range), (unified, applied, patched))| {
+                let unified = String::from_utf8(unified).unwrap();
+                assert!(unified.contains(range), "{}", unified);
+                assert!(applied, "{}", unified);
+                assert_eq!(patched.unwrap(), *new);
+            },
+        );
+    }
+}
@@ -0,0 +1,259 @@
+/*
+The pattern language of chapter 5 of Software Tools, as used by `find`
+and `change`. A pattern is compiled once into a list of elements, which
+are then matched against each line by backtracking, as the book's
+`amatch` does. Patterns and lines are bytes, so any text can be
+searched.
+
+  c         the byte c, unless it is special
+  .         any byte
+  ^ or %    the beginning of the line, at the start of the pattern
+  $         the end of the line, at the end of the pattern
+  [...]     any byte in the class; a-z denotes a range
+  [^...]    any byte not in the class
+  *         any number (including none) of the preceding element
+  @c        c, literally; @t is a tab and @n a newline
+
+Special bytes in any other position stand for themselves.
+ */
+
+use crate::error::*;
+
+const ESCAPE: u8 = b'@';
+const ANY: u8 = b'.';
+const BOL: u8 = b'^';
+const BOL_ALT: u8 = b'%';
+const EOL: u8 = b'$';
+const CLOSURE: u8 = b'*';
+const CCL: u8 = b'[';
+const CCL_END: u8 = b']';
+const NEGATE: u8 = b'^';
+const DASH: u8 = b'-';
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum PatElem {
+    Byte(u8),
+    Any,
+    Bol,
+    Eol,
+    /// Which bytes are in the class.
+    Class(Box<[bool; 256]>),
+    Closure(Box<PatElem>),
+}
+
+impl PatElem {
+    /// Whether the element matches `bt`, for those that match one byte.
+    fn matches(&self, bt: u8) -> bool {
+        match self {
+            PatElem::Byte(pat_bt) => *pat_bt == bt,
+            PatElem::Any => true,
+            PatElem::Class(members) => members[bt as usize],
+            PatElem::Bol | PatElem::Eol | PatElem::Closure(_) => false,
+        }
+    }
+}
+
+/// A compiled pattern.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Pattern {
+    elems: Vec<PatElem>,
+}
+
+impl Pattern {
+    pub fn compile(pat: &[u8]) -> Result<Pattern, SfwError> {
+        let mut elems: Vec<PatElem> = vec![];
+        let mut ix = 0;
+        while ix < pat.len() {
+            let bt = pat[ix];
+            ix += 1;
+            let elem = match bt {
+                ANY => PatElem::Any,
+                BOL | BOL_ALT if ix == 1 => PatElem::Bol,
+                EOL if ix == pat.len() => PatElem::Eol,
+                CCL => {
+                    let (members, end) = compile_class(pat, ix)?;
+                    ix = end;
+                    PatElem::Class(members)
+                }
+                CLOSURE if closable(elems.last()) => {
+                    let last = elems.pop().map(Box::new);
+                    match last {
+                        Some(last) => PatElem::Closure(last),
+                        None => PatElem::Byte(CLOSURE),
+                    }
+                }
+                ESCAPE => {
+                    let (bt, end) = escaped(pat, ix);
+                    ix = end;
+                    PatElem::Byte(bt)
+                }
+                bt => PatElem::Byte(bt),
+            };
+            elems.push(elem);
+        }
+        Ok(Pattern { elems })
+    }
+
+    /// The pattern anchored at both ends, so that it only matches whole
+    /// lines.
+    pub fn anchored(&self) -> Pattern {
+        let mut elems = self.elems.clone();
+        if elems.first() != Some(&PatElem::Bol) {
+            elems.insert(0, PatElem::Bol);
+        }
+        if elems.last() != Some(&PatElem::Eol) {
+            elems.push(PatElem::Eol);
+        }
+        Pattern { elems }
+    }
+
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.find(line).is_some()
+    }
+
+    /// The (start, end) of the leftmost match in `line`; of the matches
+    /// starting there, closures make it the longest.
+    pub fn find(&self, line: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(line, 0)
+    }
+
+    /// As `find`, but for a match starting at or after `start`, which
+    /// can only match `^` if it is 0.
+    pub fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        let last_start = match self.elems.first() {
+            Some(PatElem::Bol) => 0,
+            _ => line.len(),
+        };
+        (start..=last_start).find_map(|pos| {
+            match_here(&self.elems, line, pos).map(|end| (pos, end))
+        })
+    }
+}
+
+/// Only elements matching a single byte may be repeated.
+fn closable(elem: Option<&PatElem>) -> bool {
+    matches!(
+        elem,
+        Some(PatElem::Byte(_)) | Some(PatElem::Any) | Some(PatElem::Class(_))
+    )
+}
+
+/// The byte escaped by the `@` just before `pat[ix]`, and the index after
+/// it. A trailing `@` stands for itself.
+fn escaped(pat: &[u8], ix: usize) -> (u8, usize) {
+    match pat.get(ix) {
+        Some(b't') => (b'\t', ix + 1),
+        Some(b'n') => (b'\n', ix + 1),
+        Some(bt) => (*bt, ix + 1),
+        None => (ESCAPE, ix),
+    }
+}
+
+/// Compiles the class starting at `pat[ix]`, just after its `[`,
+/// returning its members and the index after its `]`.
+fn compile_class(
+    pat: &[u8],
+    ix: usize,
+) -> Result<(Box<[bool; 256]>, usize), SfwError> {
+    let negated = pat.get(ix) == Some(&NEGATE);
+    let mut ix = if negated { ix + 1 } else { ix };
+    let mut members = Box::new([false; 256]);
+    let mut prev: Option<u8> = None;
+    loop {
+        let (bt, next) = match pat.get(ix) {
+            None => {
+                return Err(SfwError::usage(format!(
+                    "pattern \"{}\": missing ]",
+                    String::from_utf8_lossy(pat)
+                )))
+            }
+            Some(&CCL_END) => break,
+            Some(&ESCAPE) => escaped(pat, ix + 1),
+            Some(bt) => (*bt, ix + 1),
+        };
+        let range_end = match pat.get(next) {
+            Some(&CCL_END) | None => None,
+            Some(&ESCAPE) => Some(escaped(pat, next + 1)),
+            Some(last) => Some((*last, next + 1)),
+        };
+        match (pat[ix], prev, range_end) {
+            (DASH, Some(first), Some((last, after))) => {
+                (first..=last).for_each(|bt| members[bt as usize] = true);
+                ix = after;
+                prev = None;
+            }
+            _ => {
+                members[bt as usize] = true;
+                ix = next;
+                prev = Some(bt);
+            }
+        }
+    }
+    if negated {
+        members.iter_mut().for_each(|member| *member = !*member);
+    }
+    Ok((members, ix + 1))
+}
+
+/// The end of a match of `elems` starting at `line[pos]`, if any.
+fn match_here(elems: &[PatElem], line: &[u8], pos: usize) -> Option<usize> {
+    let (elem, rest) = match elems.split_first() {
+        None => return Some(pos),
+        Some(split) => split,
+    };
+    match elem {
+        PatElem::Bol => match pos {
+            0 => match_here(rest, line, pos),
+            _ => None,
+        },
+        PatElem::Eol if pos == line.len() => match_here(rest, line, pos),
+        PatElem::Eol => None,
+        PatElem::Closure(elem) => {
+            let longest = line[pos..]
+                .iter()
+                .take_while(|bt| elem.matches(**bt))
+                .count();
+            (pos..=pos + longest)
+                .rev()
+                .find_map(|end| match_here(rest, line, end))
+        }
+        elem => match line.get(pos) {
+            Some(bt) if elem.matches(*bt) => match_here(rest, line, pos + 1),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pat: &str, line: &str) -> Option<(usize, usize)> {
+        Pattern::compile(pat.as_bytes())
+            .unwrap()
+            .find(line.as_bytes())
+    }
+
+    #[test]
+    fn test_pattern_anchors_and_closures() {
+        assert_eq!(find("b*c", "abbbcd"), Some((1, 5)));
+        assert_eq!(find("^ab", "xab"), None);
+        assert_eq!(find("%ab", "abx"), Some((0, 2)));
+        assert_eq!(find("d$", "abcd"), Some((3, 4)));
+        assert_eq!(find("a.*c", "xaxcyc"), Some((1, 6)));
+        assert_eq!(find("x*", "abc"), Some((0, 0)));
+        // Out of place, special characters are literal.
+        assert_eq!(find("a^$b", "xa^$b"), Some((1, 5)));
+        assert_eq!(find("*a", "b*a"), Some((1, 3)));
+    }
+
+    #[test]
+    fn test_pattern_classes_and_escapes() {
+        assert_eq!(find("[a-c]*", "cabd"), Some((0, 3)));
+        assert_eq!(find("[^a-c]", "cabd"), Some((3, 4)));
+        assert_eq!(find("[x-]", "a-"), Some((1, 2)));
+        assert_eq!(find("@.@t", "a.\tb"), Some((1, 3)));
+        assert_eq!(find("[@]]", "a]"), Some((1, 2)));
+        assert!(Pattern::compile(b"[abc").is_err());
+    }
+}
@@ -0,0 +1,627 @@
+/*
+`print` from chapter 3 of Software Tools lays files out as pages, each
+with a header giving the file's name, its date and the page number, and
+padded to a fixed length so that the pages line up on a printer.
+
+Here, a page may also hold several columns, filled down and then across
+(the last page of a file being balanced), or, with `-m`, several files
+side by side, one per column. Tabs are expanded as `detab` does so that
+the columns line up, and a form feed in the input starts a new page.
+ */
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::error::*;
+use crate::tabs::{detab_line, TabConf};
+use crate::util::{open_input, opt_usize_flag, STD_STREAM_NAME};
+
+const FORM_FEED: u8 = 0x0c;
+/// Blank lines, header line, blank lines.
+const HEADER_LINES: usize = 5;
+const TRAILER_LINES: usize = 5;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PrintConf {
+    /// Lines per page, including the header and trailer; pages too short
+    /// to hold both are printed without them.
+    pub page_length: usize,
+    /// Characters per line, across all columns.
+    pub page_width: usize,
+    /// Columns per page, filled down and then across.
+    pub columns: usize,
+    /// Print the inputs side by side, one per column.
+    pub merge: bool,
+    /// Replaces the file name in headers.
+    pub header: Option<String>,
+    /// How tabs are expanded.
+    pub tabs: TabConf,
+}
+
+impl Default for PrintConf {
+    fn default() -> Self {
+        PrintConf {
+            page_length: 66,
+            page_width: 72,
+            columns: 1,
+            merge: false,
+            header: None,
+            tabs: TabConf::TabConstant(8),
+        }
+    }
+}
+
+/// An input to `print`, with the name and date shown in its headers.
+pub struct PrintSource {
+    pub name: String,
+    pub date: String,
+    pub f_in: Box<dyn Read>,
+}
+
+const PRINT_USAGE: &str = r#"
+print [OPTION]... [FILE]...
+
+Writes each FILE (or STDIN) to STDOUT as numbered pages with headers.
+A form feed in the input starts a new page.
+
+Valid options are:
+-l N            lines per page, including 10 of header and trailer
+                (default 66); shorter pages get neither
+-w N            characters per line (default 72)
+-c N            print N columns per page
+-m              print all FILEs side by side, one per column
+-t N            tab stops every N columns (default 8)
+--header TEXT   use TEXT in headers instead of the file name
+
+"#;
+
+pub fn run_print_seahorse_cmd() -> Command {
+    Command::new("print")
+        .description("print: paginate files; output to STDOUT")
+        .usage(PRINT_USAGE)
+        .action(run_print_seahorse_action)
+        .flag(
+            Flag::new("length", FlagType::Int)
+                .alias("l")
+                .description("print -l 60 some_file"),
+        )
+        .flag(
+            Flag::new("width", FlagType::Int)
+                .alias("w")
+                .description("print -w 80 some_file"),
+        )
+        .flag(
+            Flag::new("columns", FlagType::Int)
+                .alias("c")
+                .description("print -c 2 some_file"),
+        )
+        .flag(
+            Flag::new("merge", FlagType::Bool)
+                .alias("m")
+                .description("print -m file1 file2"),
+        )
+        .flag(
+            Flag::new("tabs", FlagType::Int)
+                .alias("t")
+                .description("print -t 4 some_file"),
+        )
+        .flag(
+            Flag::new("header", FlagType::String)
+                .description("print --header 'Draft' some_file"),
+        )
+}
+
+pub fn run_print_seahorse_action(ctxt: &Context) {
+    let defaults = PrintConf::default();
+    let int_flag = |name, default| {
+        opt_usize_flag(ctxt, name)
+            .map(|value| value.unwrap_or(default))
+            .user_err("Error in print")
+    };
+    let tabs = opt_usize_flag(ctxt, "tabs")
+        .user_err("Error in print")
+        .map_or(defaults.tabs, TabConf::TabConstant);
+    let conf = PrintConf {
+        page_length: int_flag("length", defaults.page_length),
+        page_width: int_flag("width", defaults.page_width),
+        columns: int_flag("columns", defaults.columns),
+        merge: ctxt.bool_flag("merge"),
+        header: ctxt.string_flag("header").ok(),
+        tabs,
+    };
+    run_print(&conf, &ctxt.args);
+}
+
+/// Convenience function for running print in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_print(conf: &PrintConf, srcs: &[String]) {
+    let default_src = [String::from(STD_STREAM_NAME)];
+    let srcs = if srcs.is_empty() { &default_src } else { srcs };
+    let srcs = srcs
+        .iter()
+        .map(|src| open_source(src))
+        .collect::<Result<Vec<PrintSource>, SfwError>>()
+        .user_err("Error in print");
+    let f_out = io::BufWriter::new(io::stdout());
+    print(conf, srcs, f_out).user_err("Error in print");
+}
+
+/// Opens the file `src` (or STDIN, for `-`), dated by its modification
+/// time, or by the current time for STDIN.
+fn open_source(src: &str) -> Result<PrintSource, SfwError> {
+    let f_in = open_input(Some(src))?;
+    let (name, time) = if src == STD_STREAM_NAME {
+        (String::new(), SystemTime::now())
+    } else {
+        let time = fs::metadata(src)
+            .and_then(|meta| meta.modified())
+            .sfw_path_err("Couldn't read modification time", src)?;
+        (src.to_string(), time)
+    };
+    Ok(PrintSource {
+        name,
+        date: format_time(time),
+        f_in,
+    })
+}
+
+/// Writes `srcs` as pages: one after another, each numbered from 1, or
+/// side by side when merging.
+pub fn print<W: Write>(
+    conf: &PrintConf,
+    srcs: Vec<PrintSource>,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let layout = Layout::new(conf, srcs.len())?;
+    if conf.merge {
+        let date = srcs.first().map(|src| src.date.clone()).unwrap_or_default();
+        let title = conf.header.clone().unwrap_or_default();
+        let mut inputs =
+            srcs.into_iter().map(PageLines::new).collect::<Vec<_>>();
+        print_merged(&layout, &mut inputs, &date, &title, &mut f_out)?;
+    } else {
+        srcs.into_iter().try_for_each(|src| {
+            let title = conf.header.clone().unwrap_or(src.name.clone());
+            let date = src.date.clone();
+            let mut input = PageLines::new(src);
+            print_columns(&layout, &mut input, &date, &title, &mut f_out)
+        })?;
+    }
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// The dimensions of a page, worked out from a `PrintConf`.
+struct Layout<'a> {
+    conf: &'a PrintConf,
+    headed: bool,
+    body_length: usize,
+    columns: usize,
+    /// The width of each column, or `None` if lines aren't cut to fit.
+    column_width: Option<usize>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(conf: &'a PrintConf, src_count: usize) -> Result<Self, SfwError> {
+        if conf.merge && conf.columns > 1 {
+            return Err(SfwError::usage("print: -m and -c can't be combined"));
+        }
+        if conf.page_length == 0 || conf.columns == 0 {
+            return Err(SfwError::usage(
+                "print: page length and columns must be positive",
+            ));
+        }
+        let headed = conf.page_length > HEADER_LINES + TRAILER_LINES;
+        let columns = if conf.merge { src_count } else { conf.columns };
+        let column_width = if columns > 1 {
+            let width = conf.page_width.saturating_sub(columns - 1) / columns;
+            if width == 0 {
+                return Err(SfwError::usage(format!(
+                    "print: page width {} is too narrow for {} columns",
+                    conf.page_width, columns
+                )));
+            }
+            Some(width)
+        } else {
+            None
+        };
+        Ok(Layout {
+            conf,
+            headed,
+            body_length: if headed {
+                conf.page_length - HEADER_LINES - TRAILER_LINES
+            } else {
+                conf.page_length
+            },
+            columns,
+            column_width,
+        })
+    }
+
+    fn write_header<W: Write>(
+        &self,
+        f_out: &mut W,
+        date: &str,
+        title: &str,
+        page: usize,
+    ) -> Result<(), SfwError> {
+        if self.headed {
+            let line = header_line(self.conf.page_width, date, title, page);
+            write!(f_out, "\n\n{}\n\n\n", line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rows of a page's body, followed by the blank lines
+    /// that fill out the page.
+    fn write_body<W: Write>(
+        &self,
+        f_out: &mut W,
+        rows: &[Vec<Option<Vec<u8>>>],
+    ) -> Result<(), SfwError> {
+        rows.iter().try_for_each(|row| self.write_row(f_out, row))?;
+        let trailer = if self.headed { TRAILER_LINES } else { 0 };
+        let padding = self.body_length.saturating_sub(rows.len()) + trailer;
+        f_out.write_all(&vec![b'\n'; padding])?;
+        Ok(())
+    }
+
+    fn write_row<W: Write>(
+        &self,
+        f_out: &mut W,
+        row: &[Option<Vec<u8>>],
+    ) -> Result<(), SfwError> {
+        let mut out = vec![];
+        row.iter().enumerate().try_for_each(|(ix, cell)| {
+            let cell = cell.as_deref().unwrap_or_default();
+            match self.column_width {
+                None => detab_line(&self.conf.tabs, cell, &mut out),
+                Some(width) => {
+                    if ix > 0 {
+                        out.push(b' ');
+                    }
+                    let mut expanded = vec![];
+                    detab_line(&self.conf.tabs, cell, &mut expanded)?;
+                    push_fitted(&mut out, &expanded, width);
+                    Ok(())
+                }
+            }
+        })?;
+        let len = out
+            .iter()
+            .rposition(|bt| *bt != b' ')
+            .map_or(0, |ix| ix + 1);
+        out.truncate(len);
+        out.push(b'\n');
+        f_out.write_all(&out)?;
+        Ok(())
+    }
+}
+
+/// Prints one input as pages of `layout.columns` columns each.
+fn print_columns<W: Write>(
+    layout: &Layout,
+    input: &mut PageLines,
+    date: &str,
+    title: &str,
+    f_out: &mut W,
+) -> Result<(), SfwError> {
+    let page_lines = layout.body_length * layout.columns;
+    let mut page = 1;
+    loop {
+        let mut lines = vec![];
+        let mut form_feed = false;
+        while lines.len() < page_lines && !form_feed {
+            match input.next_item()? {
+                Some(PageItem::Line(line)) => lines.push(line),
+                Some(PageItem::FormFeed) => form_feed = true,
+                None => break,
+            }
+        }
+        if lines.is_empty() && !form_feed {
+            return Ok(()); /* Finished */
+        }
+        if lines.len() == page_lines {
+            input.skip_form_feed()?;
+        }
+        let row_count = lines.len().div_ceil(layout.columns);
+        let mut rows = vec![vec![None; layout.columns]; row_count];
+        lines.into_iter().enumerate().for_each(|(ix, line)| {
+            rows[ix % row_count][ix / row_count] = Some(line);
+        });
+        layout.write_header(f_out, date, title, page)?;
+        layout.write_body(f_out, &rows)?;
+        page += 1;
+    }
+}
+
+/// Prints `inputs` side by side, a line of each per row. An input
+/// reaching a form feed is left blank for the rest of the page.
+fn print_merged<W: Write>(
+    layout: &Layout,
+    inputs: &mut [PageLines],
+    date: &str,
+    title: &str,
+    f_out: &mut W,
+) -> Result<(), SfwError> {
+    let mut page = 1;
+    loop {
+        let mut rows = vec![];
+        let mut stopped = vec![false; inputs.len()];
+        let mut form_feed = false;
+        while rows.len() < layout.body_length {
+            let row = inputs
+                .iter_mut()
+                .zip(stopped.iter_mut())
+                .map(|(input, stopped)| {
+                    if *stopped {
+                        return Ok(None);
+                    }
+                    match input.next_item()? {
+                        Some(PageItem::Line(line)) => return Ok(Some(line)),
+                        Some(PageItem::FormFeed) => form_feed = true,
+                        None => (),
+                    }
+                    *stopped = true;
+                    Ok(None)
+                })
+                .collect::<Result<Vec<_>, SfwError>>()?;
+            if row.iter().all(Option::is_none) {
+                break;
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() && !form_feed {
+            return Ok(()); /* Finished */
+        }
+        if rows.len() == layout.body_length {
+            inputs.iter_mut().try_for_each(PageLines::skip_form_feed)?;
+        }
+        layout.write_header(f_out, date, title, page)?;
+        layout.write_body(f_out, &rows)?;
+        page += 1;
+    }
+}
+
+/// The date on the left, the title centred and the page number on the
+/// right, or just spaced apart if they don't fit in `width`.
+fn header_line(width: usize, date: &str, title: &str, page: usize) -> String {
+    let page = format!("Page {}", page);
+    let title_len = title.chars().count();
+    let room = width.saturating_sub(date.chars().count() + page.len());
+    if title_len + 2 > room {
+        return format!("{}  {}  {}", date, title, page);
+    }
+    let left = (room - title_len) / 2;
+    let right = room - title_len - left;
+    format!("{}{:left$}{}{:right$}{}", date, "", title, "", page)
+}
+
+/// Formats `time` as `YYYY-MM-DD HH:MM`, in UTC.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days(secs / SECONDS_PER_DAY);
+    let day_secs = secs % SECONDS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60
+    )
+}
+
+/// The (year, month, day) of a count of days since 1970-01-01, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let doe = days % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+enum PageItem {
+    Line(Vec<u8>),
+    FormFeed,
+}
+
+/// Appends `cell` cut or padded to `width` columns. As in `detab`, each
+/// UTF-8 character takes one column, and a cut never splits one.
+fn push_fitted(out: &mut Vec<u8>, cell: &[u8], width: usize) {
+    let is_char_start = |bt: &u8| bt & 0xC0 != 0x80;
+    let end = cell
+        .iter()
+        .enumerate()
+        .filter(|(_, bt)| is_char_start(bt))
+        .nth(width)
+        .map_or(cell.len(), |(ix, _)| ix);
+    let cols = cell[..end].iter().filter(|bt| is_char_start(bt)).count();
+    out.extend_from_slice(&cell[..end]);
+    out.resize(out.len() + width - cols, b' ');
+}
+
+/// The lines of an input, with form feeds split out of them.
+struct PageLines {
+    name: String,
+    reader: BufReader<Box<dyn Read>>,
+    pending: VecDeque<PageItem>,
+}
+
+impl PageLines {
+    fn new(src: PrintSource) -> Self {
+        PageLines {
+            name: src.name,
+            reader: BufReader::new(src.f_in),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn next_item(&mut self) -> Result<Option<PageItem>, SfwError> {
+        if self.pending.is_empty() {
+            self.read_line()?;
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /// Drops a form feed that comes just as a page fills up, which would
+    /// otherwise leave a blank page.
+    fn skip_form_feed(&mut self) -> Result<(), SfwError> {
+        if self.pending.is_empty() {
+            self.read_line()?;
+        }
+        if let Some(PageItem::FormFeed) = self.pending.front() {
+            self.pending.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Reads a line into `pending`, as the text between its form feeds:
+    /// empty text is kept only for a line without any form feeds.
+    fn read_line(&mut self) -> Result<(), SfwError> {
+        let mut line = vec![];
+        let len = self
+            .reader
+            .read_until(b'\n', &mut line)
+            .sfw_path_err("Couldn't read", &self.name)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        let mut parts = line.split(|bt| *bt == FORM_FEED).peekable();
+        let plain = line.iter().all(|bt| *bt != FORM_FEED);
+        while let Some(part) = parts.next() {
+            if !part.is_empty() || plain {
+                self.pending.push_back(PageItem::Line(part.to_vec()));
+            }
+            if parts.peek().is_some() {
+                self.pending.push_back(PageItem::FormFeed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print_str(conf: &PrintConf, inputs: &[&'static str]) -> String {
+        let srcs = inputs
+            .iter()
+            .enumerate()
+            .map(|(ix, input)| PrintSource {
+                name: format!("f{}", ix),
+                date: String::from("2020-02-29 12:00"),
+                f_in: Box::new(input.as_bytes()),
+            })
+            .collect();
+        let mut out = vec![];
+        print(conf, srcs, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_pages_with_headers() {
+        let conf = PrintConf {
+            page_length: 12,
+            page_width: 30,
+            ..PrintConf::default()
+        };
+        assert_eq!(
+            print_str(&conf, &["a\n\tb\nc\n"]),
+            "\n\n2020-02-29 12:00   f0   Page 1\n\n\n\
+             a\n        b\n\n\n\n\n\n\
+             \n\n2020-02-29 12:00   f0   Page 2\n\n\n\
+             c\n\n\n\n\n\n\n"
+        );
+    }
+
+    #[test]
+    fn test_print_tabs_align() {
+        let conf = PrintConf {
+            page_length: 3,
+            page_width: 30,
+            ..PrintConf::default()
+        };
+        assert_eq!(
+            print_str(&conf, &["abc\tX\nabcdef\tY\n12345678\tZ\ta\tb\n"]),
+            "abc     X\nabcdef  Y\n12345678        Z       a       b\n"
+        );
+        let wide = PrintConf {
+            tabs: TabConf::TabConstant(600),
+            ..conf
+        };
+        assert_eq!(
+            print_str(&wide, &["a\tb\n"]),
+            format!("a{}b\n\n\n", " ".repeat(599))
+        );
+    }
+
+    #[test]
+    fn test_print_columns_and_form_feeds() {
+        let conf = PrintConf {
+            page_length: 3,
+            page_width: 9,
+            columns: 2,
+            ..PrintConf::default()
+        };
+        // A full page, then one cut short by a form feed and balanced.
+        assert_eq!(
+            print_str(&conf, &["1\n2\n3\n4\n5\nsixsixsix\n7\x0c8\n9\n"]),
+            "1    4\n2    5\n3    sixs\n\
+             7\n\n\n\
+             8    9\n\n\n"
+        );
+    }
+
+    #[test]
+    fn test_print_columns_utf8() {
+        let conf = PrintConf {
+            page_length: 2,
+            page_width: 9,
+            columns: 2,
+            ..PrintConf::default()
+        };
+        // Cut after four characters, not bytes, and padded to match.
+        assert_eq!(
+            print_str(&conf, &["h\u{e9}h\u{e9}h\u{e9}\n\u{e9}\n\u{4e2d}\nx\n"]),
+            "h\u{e9}h\u{e9} \u{4e2d}\n\u{e9}    x\n"
+        );
+    }
+
+    #[test]
+    fn test_print_merge() {
+        let conf = PrintConf {
+            page_length: 2,
+            page_width: 7,
+            merge: true,
+            ..PrintConf::default()
+        };
+        assert_eq!(
+            print_str(&conf, &["a\nb\nc\n", "x\x0cy\n"]),
+            "a   x\nb\nc   y\n\n"
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(951_825_600);
+        assert_eq!(format_time(time), "2000-02-29 12:00");
+    }
+}
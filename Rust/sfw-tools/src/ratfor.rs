@@ -0,0 +1,1131 @@
+/*
+`ratfor` from chapter 9 of Software Tools translates Rational Fortran
+into Fortran 77. Ratfor adds the control flow that Fortran lacks:
+
+  if (cond) stmt [else stmt]
+  while (cond) stmt
+  for (init; cond; reinit) stmt
+  repeat stmt [until (cond)]
+  do limits stmt
+  break [n], next [n]      leave, or go round again, the n'th enclosing
+                           loop (the innermost by default)
+  { stmt ... }             a compound statement
+
+along with `define name value` (or `define(name, value)`) to replace a
+name throughout the rest of the input, `include file` to read another
+file in place, and operators such as `==`, `!=`, `<=`, `&` and `|` for
+`.eq.`, `.ne.`, `.le.`, `.and.` and `.or.`. Statements end at a newline
+or `;`, unless a parenthesis is open, the line ends with a comma or an
+operator, or the line ends with `_`. Comments run from `#` to the end of
+the line, and strings in double quotes become Fortran's single-quoted
+ones.
+
+As in the book, the lexer reads through a stack of input files, with
+pushback for the text of definitions (the same stack `macro` uses, so
+files are found as `include` finds them), and the parser works by
+recursive descent, writing each statement as soon as it is recognized;
+the control structures become `if`, `goto` and `continue` with
+generated labels, starting at 23000. Any error is reported with the
+file and line of the offending token.
+ */
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::error::*;
+use crate::include::InputStack;
+use crate::util::{open_input, open_output};
+
+const FIRST_LABEL: u64 = 23000;
+const MAX_LABEL: u64 = 99999;
+/// How deeply definitions may expand to further definitions.
+const MAX_DEFINE_DEPTH: usize = 64;
+/// The most bytes of pending text.
+const MAX_PUSHBACK: usize = 1 << 20;
+/// How deeply statements may nest.
+const MAX_NESTING: usize = 250;
+/// Fortran's fixed form: statements in columns 7 to 72, labels in 1 to 5
+/// and a mark in column 6 for a continuation line.
+const STMT_COL: usize = 6;
+const LAST_COL: usize = 72;
+const CONTINUATION: &str = "     *";
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct RatforConf {
+    /// Directories searched, in order, after that of the including file.
+    pub search_paths: Vec<PathBuf>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Tok {
+    Name(String),
+    Number(String),
+    /// A string, already in Fortran's form.
+    Str(String),
+    /// An operator or other punctuation, translated to Fortran.
+    Op(String),
+    LBrace,
+    RBrace,
+    Semi,
+    Newline,
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    /// Whether blanks came before it.
+    spaced: bool,
+    file: Rc<str>,
+    line: u64,
+}
+
+impl Token {
+    fn text(&self) -> &str {
+        match &self.tok {
+            Tok::Name(text)
+            | Tok::Number(text)
+            | Tok::Str(text)
+            | Tok::Op(text) => text,
+            Tok::LBrace => "{",
+            Tok::RBrace => "}",
+            Tok::Semi => ";",
+            Tok::Newline => "newline",
+            Tok::Eof => "end of input",
+        }
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        matches!(&self.tok, Tok::Name(text) if text == name)
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(&self.tok, Tok::Op(text) if text == op)
+    }
+
+    /// Whether a statement ending with this token continues on the next
+    /// line.
+    fn continues(&self) -> bool {
+        const OPS: [&str; 15] = [
+            ",", "+", "-", "*", "/", "=", ".and.", ".or.", ".not.", ".eq.",
+            ".ne.", ".lt.", ".le.", ".gt.", ".ge.",
+        ];
+        matches!(&self.tok, Tok::Op(text) if OPS.contains(&text.as_str()))
+    }
+
+    fn err<S: Into<String>>(&self, msg: S) -> SfwError {
+        SfwError::data_at_line(msg, self.line).with_path(&*self.file)
+    }
+}
+
+/// The Fortran text of some tokens, blanks kept where there were some.
+fn join(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    tokens.iter().for_each(|token| {
+        if token.spaced && !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(token.text());
+    });
+    text
+}
+
+struct Lexer<'c, 'a> {
+    conf: &'c RatforConf,
+    input: InputStack<'a>,
+    defs: HashMap<String, String>,
+}
+
+impl<'c, 'a> Lexer<'c, 'a> {
+    fn new(
+        conf: &'c RatforConf,
+        src: Option<&str>,
+        f_in: Box<dyn Read + 'a>,
+    ) -> Self {
+        Lexer {
+            conf,
+            input: InputStack::new(src, f_in, MAX_PUSHBACK),
+            defs: HashMap::new(),
+        }
+    }
+
+    fn file(&self) -> Rc<str> {
+        Rc::from(self.input.frame().name.as_str())
+    }
+
+    /// The line of the last byte read from a file.
+    fn line(&self) -> u64 {
+        self.input.frame().line
+    }
+
+    /// The next byte, pending text first, with its depth of definition.
+    fn getc(&mut self) -> Result<Option<(u8, usize)>, SfwError> {
+        self.input.read()
+    }
+
+    fn push_back(
+        &mut self,
+        text: Vec<u8>,
+        depth: usize,
+    ) -> Result<(), SfwError> {
+        self.input.push_text(text, depth)
+    }
+
+    fn ungetc(&mut self, read: Option<(u8, usize)>) -> Result<(), SfwError> {
+        match read {
+            Some((bt, depth)) => self.push_back(vec![bt], depth),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the next byte is `want`, which is then consumed.
+    fn next_is(&mut self, want: u8) -> Result<bool, SfwError> {
+        let read = self.getc()?;
+        if read.is_some_and(|(bt, _)| bt == want) {
+            return Ok(true);
+        }
+        self.ungetc(read)?;
+        Ok(false)
+    }
+
+    fn skip_blanks(&mut self) -> Result<(), SfwError> {
+        loop {
+            let read = self.getc()?;
+            if !read.is_some_and(|(bt, _)| bt == b' ' || bt == b'\t') {
+                return self.ungetc(read);
+            }
+        }
+    }
+
+    /// The rest of the line, leaving the newline to be read.
+    fn rest_of_line(&mut self) -> Result<Vec<u8>, SfwError> {
+        let mut text = vec![];
+        loop {
+            match self.getc()? {
+                Some((b'\n', depth)) => {
+                    self.push_back(vec![b'\n'], depth)?;
+                    return Ok(text);
+                }
+                Some((bt, _)) => text.push(bt),
+                None => return Ok(text),
+            }
+        }
+    }
+
+    /// Skips a comment, after its `#`, up to the end of the line or of
+    /// the definition it came from, whichever is first; a comment in a
+    /// definition mustn't swallow the rest of the line it's used on.
+    fn skip_comment(&mut self, depth: usize) -> Result<(), SfwError> {
+        loop {
+            let read = self.getc()?;
+            match read {
+                None => return Ok(()),
+                Some((bt, bt_depth)) if bt == b'\n' || bt_depth < depth => {
+                    return self.ungetc(read)
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    /// Appends the bytes satisfying `pred` that come next.
+    fn take_while(
+        &mut self,
+        text: &mut String,
+        pred: fn(u8) -> bool,
+    ) -> Result<(), SfwError> {
+        loop {
+            let read = self.getc()?;
+            match read {
+                Some((bt, _)) if pred(bt) => text.push(bt as char),
+                _ => return self.ungetc(read),
+            }
+        }
+    }
+
+    /// The next token, before definitions are applied, with the depth of
+    /// definition it came from.
+    fn lex(&mut self) -> Result<(Token, usize), SfwError> {
+        let mut spaced = false;
+        let (bt, depth) = loop {
+            match self.getc()? {
+                None => {
+                    let token = self.token_at(Tok::Eof, spaced, self.line());
+                    return Ok((token, 0));
+                }
+                Some((b' ' | b'\t' | b'\r', _)) => spaced = true,
+                Some((b'#', depth)) => self.skip_comment(depth)?,
+                Some((b'_', depth)) => {
+                    // A trailing `_` joins the line to the next.
+                    self.skip_blanks()?;
+                    if self.next_is(b'#')? {
+                        self.skip_comment(depth)?;
+                    }
+                    if self.next_is(b'\n')? {
+                        spaced = true;
+                    } else {
+                        break (b'_', depth);
+                    }
+                }
+                Some(read) => break read,
+            }
+        };
+        let line = self.line();
+        let tok = match bt {
+            b'\n' => Tok::Newline,
+            b'{' => Tok::LBrace,
+            b'}' => Tok::RBrace,
+            b';' => Tok::Semi,
+            b'"' | b'\'' => Tok::Str(self.string(bt, line)?),
+            bt if bt.is_ascii_alphabetic() => {
+                let mut name = String::from(bt as char);
+                self.take_while(&mut name, |bt| {
+                    bt.is_ascii_alphanumeric() || bt == b'_'
+                })?;
+                Tok::Name(name)
+            }
+            bt if bt.is_ascii_digit() => Tok::Number(self.number(bt)?),
+            b'=' if self.next_is(b'=')? => Tok::Op(".eq.".into()),
+            b'!' | b'^' | b'~' if self.next_is(b'=')? => Tok::Op(".ne.".into()),
+            b'!' | b'^' | b'~' => Tok::Op(".not.".into()),
+            b'<' if self.next_is(b'=')? => Tok::Op(".le.".into()),
+            b'<' => Tok::Op(".lt.".into()),
+            b'>' if self.next_is(b'=')? => Tok::Op(".ge.".into()),
+            b'>' => Tok::Op(".gt.".into()),
+            b'&' => {
+                self.next_is(b'&')?;
+                Tok::Op(".and.".into())
+            }
+            b'|' => {
+                self.next_is(b'|')?;
+                Tok::Op(".or.".into())
+            }
+            bt => Tok::Op(String::from(bt as char)),
+        };
+        Ok((self.token_at(tok, spaced, line), depth))
+    }
+
+    fn token_at(&self, tok: Tok, spaced: bool, line: u64) -> Token {
+        Token {
+            tok,
+            spaced,
+            file: self.file(),
+            line,
+        }
+    }
+
+    /// A number: digits, then perhaps a fraction and an exponent.
+    fn number(&mut self, first: u8) -> Result<String, SfwError> {
+        let mut text = String::from(first as char);
+        self.take_while(&mut text, |bt| bt.is_ascii_digit())?;
+        let point = self.getc()?;
+        if point.is_some_and(|(bt, _)| bt == b'.') {
+            let digit = self.getc()?;
+            let is_digit = digit.is_some_and(|(bt, _)| bt.is_ascii_digit());
+            self.ungetc(digit)?;
+            if is_digit {
+                text.push('.');
+                self.take_while(&mut text, |bt| bt.is_ascii_digit())?;
+            } else {
+                self.ungetc(point)?;
+            }
+        } else {
+            self.ungetc(point)?;
+        }
+        let exp = self.getc()?;
+        if !exp.is_some_and(|(bt, _)| b"eEdD".contains(&bt)) {
+            self.ungetc(exp)?;
+            return Ok(text);
+        }
+        let sign = self.getc()?;
+        let signed = sign.is_some_and(|(bt, _)| bt == b'+' || bt == b'-');
+        let digit = if signed { self.getc()? } else { sign };
+        let is_digit = digit.is_some_and(|(bt, _)| bt.is_ascii_digit());
+        self.ungetc(digit)?;
+        if is_digit {
+            exp.into_iter().for_each(|(bt, _)| text.push(bt as char));
+            if signed {
+                sign.into_iter().for_each(|(bt, _)| text.push(bt as char));
+            }
+            self.take_while(&mut text, |bt| bt.is_ascii_digit())?;
+        } else {
+            if signed {
+                self.ungetc(sign)?;
+            }
+            self.ungetc(exp)?;
+        }
+        Ok(text)
+    }
+
+    /// A string after its opening quote, as a Fortran string.
+    fn string(&mut self, quote: u8, line: u64) -> Result<String, SfwError> {
+        let mut text = vec![b'\''];
+        loop {
+            match self.getc()? {
+                None | Some((b'\n', _)) => {
+                    return Err(SfwError::data_at_line("missing quote", line)
+                        .with_path(&*self.file()))
+                }
+                Some((bt, _)) if bt == quote => {
+                    // Inside single quotes, two of them stand for one.
+                    if quote == b'\'' && self.next_is(b'\'')? {
+                        text.extend_from_slice(b"''");
+                    } else {
+                        break;
+                    }
+                }
+                Some((b'\'', _)) => text.extend_from_slice(b"''"),
+                Some((bt, _)) => text.push(bt),
+            }
+        }
+        text.push(b'\'');
+        Ok(String::from_utf8_lossy(&text).to_string())
+    }
+
+    /// The next token, after definitions and includes are dealt with.
+    fn token(&mut self) -> Result<Token, SfwError> {
+        loop {
+            let (token, depth) = self.lex()?;
+            let name = match &token.tok {
+                Tok::Name(name) => name.clone(),
+                _ => return Ok(token),
+            };
+            match name.as_str() {
+                "define" => self.define(&token)?,
+                "include" => self.include(&token)?,
+                _ => match self.defs.get(&name) {
+                    Some(text) => {
+                        if depth >= MAX_DEFINE_DEPTH {
+                            return Err(token.err(format!(
+                                "definition of {} nested too deeply",
+                                name
+                            )));
+                        }
+                        let mut text = text.clone().into_bytes();
+                        if token.spaced {
+                            text.insert(0, b' ');
+                        }
+                        self.push_back(text, depth + 1)?;
+                    }
+                    None => return Ok(token),
+                },
+            }
+        }
+    }
+
+    /// Reads a definition, `define name value` or `define(name, value)`.
+    fn define(&mut self, at: &Token) -> Result<(), SfwError> {
+        self.skip_blanks()?;
+        let parens = self.next_is(b'(')?;
+        self.skip_blanks()?;
+        let mut name = String::new();
+        self.take_while(&mut name, |bt| {
+            bt.is_ascii_alphanumeric() || bt == b'_'
+        })?;
+        if !name.starts_with(|ch: char| ch.is_ascii_alphabetic()) {
+            return Err(at.err("define: missing name"));
+        }
+        let value = if parens {
+            self.skip_blanks()?;
+            if self.next_is(b',')? {
+                self.define_value(at)?
+            } else if self.next_is(b')')? {
+                vec![]
+            } else {
+                return Err(at.err("define: missing comma"));
+            }
+        } else {
+            let line = self.rest_of_line()?;
+            strip_comment(&line).to_vec()
+        };
+        let value = String::from_utf8_lossy(&value).trim().to_string();
+        self.defs.insert(name, value);
+        Ok(())
+    }
+
+    /// The value of `define(name, value)`, up to the closing parenthesis.
+    fn define_value(&mut self, at: &Token) -> Result<Vec<u8>, SfwError> {
+        let mut value = vec![];
+        let mut level = 0;
+        loop {
+            match self.getc()? {
+                None => return Err(at.err("define: missing right paren")),
+                Some((b')', _)) if level == 0 => return Ok(value),
+                Some((bt, _)) => {
+                    match bt {
+                        b'(' => level += 1,
+                        b')' => level -= 1,
+                        _ => (),
+                    }
+                    value.push(bt);
+                }
+            }
+        }
+    }
+
+    /// Reads an include line, `include file` or `include "file"`, and
+    /// starts reading the file.
+    fn include(&mut self, at: &Token) -> Result<(), SfwError> {
+        let line = self.rest_of_line()?;
+        let name = String::from_utf8_lossy(strip_comment(&line))
+            .trim()
+            .to_string();
+        let name = match name.as_bytes() {
+            [b'"', .., b'"'] | [b'\'', .., b'\''] if name.len() > 1 => {
+                &name[1..name.len() - 1]
+            }
+            _ => name.as_str(),
+        };
+        if name.is_empty() {
+            return Err(at.err("include: missing file name"));
+        }
+        // The newline left by `rest_of_line` ends the file's last line.
+        self.input.push_file(&self.conf.search_paths, name, 0)
+    }
+}
+
+/// A line without its comment, if it has one outside a string.
+fn strip_comment(line: &[u8]) -> &[u8] {
+    let mut quote = None;
+    for (ix, bt) in line.iter().enumerate() {
+        match (quote, *bt) {
+            (None, b'#') => return &line[..ix],
+            (None, b'"' | b'\'') => quote = Some(*bt),
+            (Some(open), bt) if bt == open => quote = None,
+            _ => (),
+        }
+    }
+    line
+}
+
+/// Whether the token starts one of Ratfor's control statements.
+fn is_keyword(token: &Token) -> bool {
+    const KEYWORDS: [&str; 9] = [
+        "if", "else", "while", "for", "repeat", "until", "do", "break", "next",
+    ];
+    matches!(&token.tok, Tok::Name(name) if KEYWORDS.contains(&name.as_str()))
+}
+
+/// Parses statements and writes their Fortran.
+struct Translator<'c, 'a, W: Write> {
+    lexer: Lexer<'c, 'a>,
+    peeked: Option<Token>,
+    f_out: W,
+    next_label: u64,
+    /// The labels for `next` and `break` in each enclosing loop.
+    loops: Vec<(u64, u64)>,
+    nesting: usize,
+}
+
+impl<'c, 'a, W: Write> Translator<'c, 'a, W> {
+    fn next(&mut self) -> Result<Token, SfwError> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lexer.token(),
+        }
+    }
+
+    fn unget(&mut self, token: Token) {
+        self.peeked = Some(token);
+    }
+
+    /// The next token that isn't a newline or `;`.
+    fn next_significant(&mut self) -> Result<Token, SfwError> {
+        loop {
+            let token = self.next()?;
+            if !matches!(token.tok, Tok::Newline | Tok::Semi) {
+                return Ok(token);
+            }
+        }
+    }
+
+    fn labels(&mut self, count: u64, at: &Token) -> Result<u64, SfwError> {
+        let first = self.next_label;
+        self.next_label += count;
+        if self.next_label > MAX_LABEL + 1 {
+            return Err(at.err("too many generated labels"));
+        }
+        Ok(first)
+    }
+
+    /// Writes a statement in fixed form, continued over as many lines as
+    /// it takes.
+    fn emit(&mut self, label: Option<u64>, text: &str) -> Result<(), SfwError> {
+        let mut line = match label {
+            Some(label) => format!("{:<1$}", label, STMT_COL),
+            None => " ".repeat(STMT_COL),
+        };
+        let mut col = STMT_COL;
+        for ch in text.chars() {
+            if col == LAST_COL {
+                writeln!(self.f_out, "{}", line)?;
+                line = String::from(CONTINUATION);
+                col = STMT_COL;
+            }
+            line.push(ch);
+            col += 1;
+        }
+        writeln!(self.f_out, "{}", line)?;
+        Ok(())
+    }
+
+    fn emit_continue(&mut self, label: u64) -> Result<(), SfwError> {
+        self.emit(Some(label), "continue")
+    }
+
+    fn emit_goto(&mut self, label: u64) -> Result<(), SfwError> {
+        self.emit(None, &format!("goto {}", label))
+    }
+
+    fn emit_unless(&mut self, cond: &str, label: u64) -> Result<(), SfwError> {
+        self.emit(None, &format!("if (.not.({})) goto {}", cond, label))
+    }
+
+    fn statement(&mut self) -> Result<(), SfwError> {
+        let mut token = self.next()?;
+        while token.tok == Tok::Newline {
+            token = self.next()?;
+        }
+        if token.tok == Tok::Semi {
+            // A `;` alone is the null statement, as in `if (a) ;`.
+            return Ok(());
+        }
+        if self.nesting == MAX_NESTING {
+            return Err(token.err("statements nested too deeply"));
+        }
+        self.nesting += 1;
+        let result = self.statement_at(token);
+        self.nesting -= 1;
+        result
+    }
+
+    fn statement_at(&mut self, token: Token) -> Result<(), SfwError> {
+        match &token.tok {
+            Tok::Eof => Err(token.err("unexpected end of input")),
+            Tok::LBrace => loop {
+                let next = self.next_significant()?;
+                match next.tok {
+                    Tok::RBrace => return Ok(()),
+                    Tok::Eof => return Err(token.err("missing right brace")),
+                    _ => {
+                        self.unget(next);
+                        self.statement()?;
+                    }
+                }
+            },
+            Tok::RBrace => Err(token.err("unexpected right brace")),
+            Tok::Number(num) => {
+                // A label on a control statement goes on a `continue`
+                // ahead of its translation.
+                let next = self.next()?;
+                match num.parse::<u64>() {
+                    Ok(label) if label <= MAX_LABEL && is_keyword(&next) => {
+                        self.emit_continue(label)?;
+                        self.statement_at(next)
+                    }
+                    _ => {
+                        self.unget(next);
+                        self.other_stmt(token)
+                    }
+                }
+            }
+            Tok::Name(name) => match name.as_str() {
+                "if" => self.if_stmt(&token),
+                "else" => Err(token.err("else without if")),
+                "while" => self.while_stmt(&token),
+                "for" => self.for_stmt(&token),
+                "repeat" => self.repeat_stmt(&token),
+                "until" => Err(token.err("until without repeat")),
+                "do" => self.do_stmt(&token),
+                "break" => self.jump_stmt(&token, true),
+                "next" => self.jump_stmt(&token, false),
+                _ => self.other_stmt(token),
+            },
+            _ => self.other_stmt(token),
+        }
+    }
+
+    /// The tokens up to a `)` closing one already read, or with `semi`,
+    /// up to a `;`; the flag tells which ended them.
+    fn tokens_until(
+        &mut self,
+        at: &Token,
+        semi: bool,
+    ) -> Result<(Vec<Token>, bool), SfwError> {
+        let mut tokens = vec![];
+        let mut level = 0;
+        loop {
+            let token = self.next()?;
+            match &token.tok {
+                Tok::Newline => continue,
+                Tok::Semi if semi && level == 0 => return Ok((tokens, true)),
+                Tok::Semi | Tok::LBrace | Tok::RBrace | Tok::Eof => {
+                    return Err(
+                        at.err(format!("missing right paren in {}", at.text()))
+                    )
+                }
+                _ if token.is_op(")") && level == 0 => {
+                    return Ok((tokens, false))
+                }
+                _ if token.is_op(")") => level -= 1,
+                _ if token.is_op("(") => level += 1,
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn left_paren(&mut self, at: &Token) -> Result<(), SfwError> {
+        let mut token = self.next()?;
+        while token.tok == Tok::Newline {
+            token = self.next()?;
+        }
+        if token.is_op("(") {
+            Ok(())
+        } else {
+            Err(token.err(format!("missing left paren after {}", at.text())))
+        }
+    }
+
+    /// A parenthesized condition, as Fortran.
+    fn condition(&mut self, at: &Token) -> Result<String, SfwError> {
+        self.left_paren(at)?;
+        let (tokens, _) = self.tokens_until(at, false)?;
+        if tokens.is_empty() {
+            return Err(at.err(format!("missing condition in {}", at.text())));
+        }
+        Ok(join(&tokens))
+    }
+
+    fn if_stmt(&mut self, at: &Token) -> Result<(), SfwError> {
+        let cond = self.condition(at)?;
+        let label = self.labels(2, at)?;
+        self.emit_unless(&cond, label)?;
+        self.statement()?;
+        let next = self.next_significant()?;
+        if next.is_name("else") {
+            self.emit_goto(label + 1)?;
+            self.emit_continue(label)?;
+            self.statement()?;
+            self.emit_continue(label + 1)
+        } else {
+            self.unget(next);
+            self.emit_continue(label)
+        }
+    }
+
+    fn loop_body(&mut self, next: u64, brk: u64) -> Result<(), SfwError> {
+        self.loops.push((next, brk));
+        let result = self.statement();
+        self.loops.pop();
+        result
+    }
+
+    fn while_stmt(&mut self, at: &Token) -> Result<(), SfwError> {
+        let cond = self.condition(at)?;
+        let label = self.labels(2, at)?;
+        self.emit_continue(label)?;
+        self.emit_unless(&cond, label + 1)?;
+        self.loop_body(label, label + 1)?;
+        self.emit_goto(label)?;
+        self.emit_continue(label + 1)
+    }
+
+    fn for_stmt(&mut self, at: &Token) -> Result<(), SfwError> {
+        self.left_paren(at)?;
+        let (init, semi_1) = self.tokens_until(at, true)?;
+        let (cond, semi_2) = match semi_1 {
+            true => self.tokens_until(at, true)?,
+            false => (vec![], false),
+        };
+        if !semi_2 {
+            return Err(at.err("missing semicolon in for"));
+        }
+        let (reinit, _) = self.tokens_until(at, false)?;
+        let label = self.labels(3, at)?;
+        if !init.is_empty() {
+            self.emit(None, &join(&init))?;
+        }
+        self.emit_continue(label)?;
+        if !cond.is_empty() {
+            self.emit_unless(&join(&cond), label + 2)?;
+        }
+        self.loop_body(label + 1, label + 2)?;
+        self.emit_continue(label + 1)?;
+        if !reinit.is_empty() {
+            self.emit(None, &join(&reinit))?;
+        }
+        self.emit_goto(label)?;
+        self.emit_continue(label + 2)
+    }
+
+    fn repeat_stmt(&mut self, at: &Token) -> Result<(), SfwError> {
+        let label = self.labels(3, at)?;
+        self.emit_continue(label)?;
+        self.loop_body(label + 1, label + 2)?;
+        self.emit_continue(label + 1)?;
+        let next = self.next_significant()?;
+        if next.is_name("until") {
+            let cond = self.condition(&next)?;
+            self.emit_unless(&cond, label)?;
+        } else {
+            self.unget(next);
+            self.emit_goto(label)?;
+        }
+        self.emit_continue(label + 2)
+    }
+
+    fn do_stmt(&mut self, at: &Token) -> Result<(), SfwError> {
+        let mut limits = vec![];
+        loop {
+            let token = self.next()?;
+            match token.tok {
+                Tok::Newline | Tok::Semi => break,
+                Tok::LBrace | Tok::RBrace | Tok::Eof => {
+                    self.unget(token);
+                    break;
+                }
+                _ => limits.push(token),
+            }
+        }
+        if limits.is_empty() {
+            return Err(at.err("missing limits in do"));
+        }
+        let label = self.labels(2, at)?;
+        self.emit(None, &format!("do {} {}", label, join(&limits)))?;
+        self.loop_body(label, label + 1)?;
+        self.emit_continue(label)?;
+        self.emit_continue(label + 1)
+    }
+
+    /// `break` or `next`, with the number of loops out to go.
+    fn jump_stmt(&mut self, at: &Token, brk: bool) -> Result<(), SfwError> {
+        let mut token = self.next()?;
+        let levels = match token.tok.clone() {
+            Tok::Number(num) => {
+                token = self.next()?;
+                num.parse::<usize>()
+                    .ok()
+                    .filter(|num| *num > 0)
+                    .ok_or_else(|| {
+                        at.err(format!("{}: bad loop count {}", at.text(), num))
+                    })?
+            }
+            _ => 1,
+        };
+        match token.tok {
+            Tok::Newline | Tok::Semi => (),
+            Tok::RBrace | Tok::Eof => self.unget(token),
+            _ => {
+                return Err(token.err(format!(
+                    "unexpected {} after {}",
+                    token.text(),
+                    at.text()
+                )))
+            }
+        }
+        if levels > self.loops.len() {
+            return Err(at.err(match self.loops.len() {
+                0 => format!("{} not in loop", at.text()),
+                depth => format!(
+                    "{} {}: only {} enclosing loop(s)",
+                    at.text(),
+                    levels,
+                    depth
+                ),
+            }));
+        }
+        let (next, brk_label) = self.loops[self.loops.len() - levels];
+        self.emit_goto(if brk { brk_label } else { next })
+    }
+
+    /// Any other statement, copied through, with its label if it has
+    /// one.
+    fn other_stmt(&mut self, first: Token) -> Result<(), SfwError> {
+        let (label, mut token) = match &first.tok {
+            Tok::Number(num) => match num.parse::<u64>() {
+                Ok(label) if label <= MAX_LABEL => (Some(label), self.next()?),
+                _ => (None, first.clone()),
+            },
+            _ => (None, first.clone()),
+        };
+        let mut tokens: Vec<Token> = vec![];
+        let mut level = 0usize;
+        loop {
+            match &token.tok {
+                Tok::Newline
+                    if level > 0
+                        || tokens.last().is_some_and(Token::continues) => {}
+                Tok::Newline | Tok::Semi => break,
+                Tok::LBrace | Tok::RBrace | Tok::Eof => {
+                    self.unget(token);
+                    break;
+                }
+                _ => {
+                    if token.is_op("(") {
+                        level += 1;
+                    } else if token.is_op(")") {
+                        level = level
+                            .checked_sub(1)
+                            .ok_or_else(|| token.err("extra right paren"))?;
+                    }
+                    tokens.push(token);
+                }
+            }
+            token = self.next()?;
+        }
+        if level > 0 {
+            return Err(first.err("missing right paren"));
+        }
+        match (label, tokens.is_empty()) {
+            (Some(_), true) => self.emit(label, "continue"),
+            _ => self.emit(label, &join(&tokens)),
+        }
+    }
+}
+
+/// Translates the Ratfor of `f_in`, which was read from the file `src`
+/// (or if `None`, from STDIN), into Fortran.
+pub fn ratfor<'a, R: Read + 'a, W: Write>(
+    conf: &RatforConf,
+    src: Option<&str>,
+    f_in: R,
+    f_out: W,
+) -> Result<(), SfwError> {
+    let mut translator = Translator {
+        lexer: Lexer::new(conf, src, Box::new(f_in)),
+        peeked: None,
+        f_out,
+        next_label: FIRST_LABEL,
+        loops: vec![],
+        nesting: 0,
+    };
+    loop {
+        let token = translator.next_significant()?;
+        if token.tok == Tok::Eof {
+            break;
+        }
+        translator.unget(token);
+        translator.statement()?;
+    }
+    translator.f_out.flush().sfw_err("Couldn't flush output")
+}
+
+const RATFOR_USAGE: &str = r#"
+ratfor [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Translates the Ratfor in SOURCE_FILE (or STDIN) into Fortran 77.
+
+Valid options are:
+-I DIRS          directories to search for included files, after that
+                 of the including file; separate several with ':'
+
+"#;
+
+pub fn run_ratfor_seahorse_cmd() -> Command {
+    Command::new("ratfor")
+        .description(
+            "ratfor: translate Ratfor into Fortran\
+            ; output to STDOUT is the default",
+        )
+        .usage(RATFOR_USAGE)
+        .action(run_ratfor_seahorse_action)
+        .flag(
+            Flag::new("search", FlagType::String)
+                .alias("I")
+                .description("ratfor -I lib:../lib some_file"),
+        )
+}
+
+pub fn run_ratfor_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let src = args.next().map(String::as_str);
+    let f_in = open_input(src).user_err("Error in ratfor");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in ratfor");
+    let conf = RatforConf {
+        search_paths: ctxt
+            .string_flag("search")
+            .map(|dirs| env::split_paths(&dirs).collect())
+            .unwrap_or_default(),
+    };
+    run_ratfor(&conf, src, f_in, f_out);
+}
+
+/// Convenience function for running ratfor in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_ratfor(
+    conf: &RatforConf,
+    src: Option<&str>,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    ratfor(conf, src, f_in, f_out).user_err("Error in ratfor");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(input: &str) -> Result<String, SfwError> {
+        let mut out = vec![];
+        ratfor(&RatforConf::default(), None, input.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_ratfor_control_flow() {
+        let input = r#"
+define(MAXLINE, 81)   # the longest line
+if (n >= MAXLINE & ok) x = "it's"
+else {
+    while (i != 0) { if (i == 1) break; i = i - 1 }
+}
+repeat
+    next
+until (done)
+"#;
+        let expected = "      if (.not.(n .ge. 81 .and. ok)) goto 23000
+      x = 'it''s'
+      goto 23001
+23000 continue
+23002 continue
+      if (.not.(i .ne. 0)) goto 23003
+      if (.not.(i .eq. 1)) goto 23004
+      goto 23003
+23004 continue
+      i = i - 1
+      goto 23002
+23003 continue
+23001 continue
+23006 continue
+      goto 23007
+23007 continue
+      if (.not.(done)) goto 23006
+23008 continue
+";
+        assert_eq!(translate(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ratfor_loops_and_continuation() {
+        let input = "for (i = 1; i <= n; i = i + 1)
+    do j = 1, m
+        10 a(i, j) = b(i,
+                       j) +_
+                     c
+";
+        let expected = "      i = 1
+23000 continue
+      if (.not.(i .le. n)) goto 23002
+      do 23003 j = 1, m
+10    a(i, j) = b(i, j) + c
+23003 continue
+23004 continue
+23001 continue
+      i = i + 1
+      goto 23000
+23002 continue
+";
+        assert_eq!(translate(input).unwrap(), expected);
+        let long = format!("call f({})", "x, ".repeat(30) + "x");
+        let out = translate(&long).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), LAST_COL);
+        assert!(lines[1].starts_with(CONTINUATION));
+    }
+
+    #[test]
+    fn test_ratfor_labelled_control_statements() {
+        let input = "10 while (i > 0) i = i - 1
+20 if (x) y = 1
+30 z = 2
+";
+        let expected = "10    continue
+23000 continue
+      if (.not.(i .gt. 0)) goto 23001
+      i = i - 1
+      goto 23000
+23001 continue
+20    continue
+      if (.not.(x)) goto 23002
+      y = 1
+23002 continue
+30    z = 2
+";
+        assert_eq!(translate(input).unwrap(), expected);
+        assert_eq!(
+            translate("10 break").unwrap_err().to_string(),
+            "<stdin>:1: break not in loop"
+        );
+    }
+
+    #[test]
+    fn test_ratfor_comments_and_null_statements() {
+        assert_eq!(
+            translate("define N 10 # size\nx = N + 1\n").unwrap(),
+            "      x = 10 + 1\n"
+        );
+        assert_eq!(
+            translate("define(C, 1 # one)\ny = C + 2\n").unwrap(),
+            "      y = 1 + 2\n"
+        );
+        assert_eq!(
+            translate("if (a) ;\nelse b = 1\n").unwrap(),
+            "      if (.not.(a)) goto 23000
+      goto 23001
+23000 continue
+      b = 1
+23001 continue
+"
+        );
+    }
+
+    #[test]
+    fn test_ratfor_include() {
+        let dir = format!("{}/test_data/ratfor/", env!("CARGO_MANIFEST_DIR"));
+        let translate_file = |name: &str| {
+            let src = format!("{}{}", dir, name);
+            let mut out = vec![];
+            let f_in = std::fs::File::open(&src).unwrap();
+            ratfor(&RatforConf::default(), Some(&src), f_in, &mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+                .map_err(|err| err.to_string().replace(&dir, ""))
+        };
+        assert_eq!(
+            translate_file("main.r").unwrap(),
+            "      integer x\n      x = 10\n"
+        );
+        assert_eq!(
+            translate_file("self.r").unwrap_err(),
+            "self.r:2: include cycle: self.r:2 -> self.r"
+        );
+    }
+
+    #[test]
+    fn test_ratfor_errors() {
+        let err = |input: &str| translate(input).unwrap_err().to_string();
+        assert_eq!(err("x = 1\n\nelse y = 2"), "<stdin>:3: else without if");
+        assert_eq!(err("x = 1\nbreak"), "<stdin>:2: break not in loop");
+        assert_eq!(
+            err("while (x) {\n  y = 1\n"),
+            "<stdin>:1: missing right brace"
+        );
+        assert_eq!(
+            err("\nif (x y = 1"),
+            "<stdin>:2: missing right paren in if"
+        );
+        assert_eq!(
+            err("define A B\ndefine B A\nA"),
+            "<stdin>:3: definition of A nested too deeply"
+        );
+        assert_eq!(err("x = \"abc\n"), "<stdin>:1: missing quote");
+        assert!(err("include no/such/file").contains("can't find"));
+    }
+}
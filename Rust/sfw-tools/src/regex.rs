@@ -0,0 +1,912 @@
+/*
+Extended regular expressions, in the POSIX ERE syntax, matched in time
+linear in the length of the text, whatever the pattern; there is no
+backtracking for untrusted patterns or input to exploit.
+
+A pattern is parsed, then compiled to a Thompson NFA: a little program
+of byte tests, splits and jumps. Whether a text matches is decided by a
+DFA, built lazily from sets of NFA states as the text is read, and kept
+between searches; should it grow too large, it is simply thrown away
+and built again. Finding where a match lies, and what its groups
+matched, is done by a Pike VM, which runs the NFA's threads in lock
+step, so each byte of the text is looked at once per NFA state.
+
+Of the matches starting leftmost, the one preferred is that found first
+by taking the alternatives in order and repeating greedily, as in RE2
+and Rust's `regex`, rather than the longest, as in POSIX.
+
+  c          the byte c, unless it is special
+  \c         c, literally; \t is a tab and \n a newline
+  .          any byte
+  [...]      any byte in the class: a-z denotes a range, and [:alpha:]
+             and the like the POSIX character classes
+  [^...]     any byte not in the class
+  ^ $        the beginning and end of the text
+  (r)        r, as a group whose match is captured
+  r|s        r or s
+  r* r+ r?   r repeated any number of times, at least once, or at most
+             once
+  r{m,n}     r repeated m to n times; {m} and {m,} are also allowed
+ */
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::*;
+
+const MAX_REPEAT: u32 = 1000;
+const MAX_NESTING: usize = 250;
+/// The most instructions in a compiled pattern.
+const MAX_PROGRAM: usize = 1 << 16;
+/// The most DFA states cached before the cache is cleared.
+const MAX_DFA_STATES: usize = 4096;
+
+type ByteSet = Box<[bool; 256]>;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct RegexConf {
+    /// Letters match either case (ASCII only).
+    pub case_insensitive: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Set(ByteSet),
+    Bol,
+    Eol,
+    Group(Box<Node>, usize),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Byte(ByteSet),
+    /// Try the first, then the second.
+    Split(usize, usize),
+    Jmp(usize),
+    /// Record the position in a capture slot.
+    Save(usize),
+    Bol,
+    Eol,
+    Match,
+}
+
+/// The spans matched by a pattern (group 0) and its groups, in the
+/// order of their opening parentheses.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Captures {
+    spans: Vec<Option<(usize, usize)>>,
+}
+
+impl Captures {
+    /// The (start, end) of `group`, if it took part in the match.
+    pub fn get(&self, group: usize) -> Option<(usize, usize)> {
+        self.spans.get(group).copied().flatten()
+    }
+
+    /// The number of groups, including group 0.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    groups: usize,
+    dfa: Mutex<Dfa>,
+}
+
+impl Clone for Regex {
+    fn clone(&self) -> Self {
+        Regex {
+            prog: self.prog.clone(),
+            groups: self.groups,
+            dfa: Mutex::new(Dfa::default()),
+        }
+    }
+}
+
+impl Regex {
+    pub fn compile(pat: &[u8], conf: &RegexConf) -> Result<Regex, SfwError> {
+        let mut parser = Parser {
+            pat,
+            pos: 0,
+            groups: 0,
+            depth: 0,
+            conf,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < pat.len() {
+            return Err(parser.error("unmatched )"));
+        }
+        let mut prog = vec![Inst::Save(0)];
+        emit(&node, &mut prog)
+            .and_then(|_| {
+                prog.push(Inst::Save(1));
+                prog.push(Inst::Match);
+                check_size(&prog)
+            })
+            .map_err(|_| parser.error("pattern too large"))?;
+        Ok(Regex {
+            prog,
+            groups: parser.groups,
+            dfa: Mutex::new(Dfa::default()),
+        })
+    }
+
+    /// The number of groups, not counting the whole match.
+    pub fn group_count(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.is_match_at(text, 0)
+    }
+
+    /// Whether there is a match starting at or after `start`; `^` only
+    /// matches if `start` is 0.
+    pub fn is_match_at(&self, text: &[u8], start: usize) -> bool {
+        let mut dfa = self.dfa.lock().unwrap_or_else(|err| err.into_inner());
+        dfa.is_match(&self.prog, text, start)
+    }
+
+    /// The (start, end) of the preferred match in `text`.
+    pub fn find(&self, text: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// As `find`, for a match starting at or after `start`.
+    pub fn find_at(&self, text: &[u8], start: usize) -> Option<(usize, usize)> {
+        self.captures_at(text, start).and_then(|caps| caps.get(0))
+    }
+
+    pub fn captures(&self, text: &[u8]) -> Option<Captures> {
+        self.captures_at(text, 0)
+    }
+
+    /// As `captures`, for a match starting at or after `start`.
+    pub fn captures_at(&self, text: &[u8], start: usize) -> Option<Captures> {
+        if start > text.len() || !self.is_match_at(text, start) {
+            return None;
+        }
+        let slots = pike_vm(&self.prog, 2 * (self.groups + 1), text, start)?;
+        let spans = slots
+            .chunks(2)
+            .map(|span| match span {
+                [Some(start), Some(end)] => Some((*start, *end)),
+                _ => None,
+            })
+            .collect();
+        Some(Captures { spans })
+    }
+}
+
+struct Parser<'a> {
+    pat: &'a [u8],
+    pos: usize,
+    groups: usize,
+    depth: usize,
+    conf: &'a RegexConf,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> SfwError {
+        SfwError::usage(format!(
+            "regular expression \"{}\": {}",
+            String::from_utf8_lossy(self.pat),
+            msg
+        ))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.pat.get(self.pos).copied()
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, SfwError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap_or(Node::Empty),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, SfwError> {
+        let mut items = vec![];
+        while let Some(bt) = self.peek() {
+            if bt == b'|' || bt == b')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_repeats(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(items),
+        })
+    }
+
+    /// Applies the quantifiers following `node`. Stacked ones are
+    /// combined where that matches the same counts (`a**` is `a*`,
+    /// `a+?` is `a*`); the rest count toward the nesting limit.
+    fn parse_repeats(&mut self, mut node: Node) -> Result<Node, SfwError> {
+        let mut height = height(&node);
+        loop {
+            let (min, max) = match self.peek() {
+                Some(b'*') => (0, None),
+                Some(b'+') => (1, None),
+                Some(b'?') => (0, Some(1)),
+                Some(b'{') => match self.parse_bounds()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            node = match node {
+                // Each inner count reaches from at most one upward, so the
+                // totals have no gaps.
+                Node::Repeat {
+                    node: inner,
+                    min: inner_min,
+                    max: inner_max,
+                } if inner_min <= 1 => {
+                    let max = match (inner_max, max) {
+                        (Some(0), _) | (_, Some(0)) => Some(0),
+                        (Some(inner_max), Some(max)) => {
+                            if inner_max * max > MAX_REPEAT {
+                                return Err(
+                                    self.error("repetition count too large")
+                                );
+                            }
+                            Some(inner_max * max)
+                        }
+                        _ => None,
+                    };
+                    Node::Repeat {
+                        node: inner,
+                        min: inner_min * min,
+                        max,
+                    }
+                }
+                node => {
+                    height += 1;
+                    if height > MAX_NESTING {
+                        return Err(self.error("repetitions nested too deeply"));
+                    }
+                    Node::Repeat {
+                        node: Box::new(node),
+                        min,
+                        max,
+                    }
+                }
+            };
+        }
+    }
+
+    /// Parses `{m}`, `{m,}` or `{m,n}`, moving to its `}`; if what
+    /// follows the `{` isn't a bound, the `{` is taken literally.
+    fn parse_bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, SfwError> {
+        let rest = &self.pat[self.pos + 1..];
+        let close = match rest.iter().position(|bt| *bt == b'}') {
+            Some(close) => close,
+            None => return Ok(None),
+        };
+        let body = String::from_utf8_lossy(&rest[..close]).to_string();
+        let number = |num: &str| {
+            if !num.is_empty() && num.bytes().all(|bt| bt.is_ascii_digit()) {
+                num.parse::<u32>().ok().or(Some(u32::MAX))
+            } else {
+                None
+            }
+        };
+        let bounds = match body.split_once(',') {
+            None => number(&body).map(|num| (num, Some(num))),
+            Some((min, "")) => number(min).map(|min| (min, None)),
+            Some((min, max)) => number(min).zip(number(max).map(Some)),
+        };
+        let (min, max) = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        if min > MAX_REPEAT || max.unwrap_or(0) > MAX_REPEAT {
+            return Err(self.error("repetition count too large"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("bad repetition bounds"));
+        }
+        // Leave `pos` on the `}`, which `parse_repeats` skips.
+        self.pos += close + 1;
+        Ok(Some((min, max)))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, SfwError> {
+        let bt = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.pos += 1;
+        match bt {
+            b'(' => {
+                self.depth += 1;
+                if self.depth > MAX_NESTING {
+                    return Err(self.error("groups nested too deeply"));
+                }
+                self.groups += 1;
+                let group = self.groups;
+                let inner = self.parse_alt()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("missing )"));
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(Node::Group(Box::new(inner), group))
+            }
+            b'*' | b'+' | b'?' => {
+                Err(self.error("repetition operator without an operand"))
+            }
+            b'.' => Ok(Node::Set(Box::new([true; 256]))),
+            b'^' => Ok(Node::Bol),
+            b'$' => Ok(Node::Eol),
+            b'[' => self.parse_class(),
+            b'\\' => {
+                let bt = match self.peek() {
+                    Some(b't') => b'\t',
+                    Some(b'n') => b'\n',
+                    Some(bt) => bt,
+                    None => return Err(self.error("trailing \\")),
+                };
+                self.pos += 1;
+                Ok(self.literal(bt))
+            }
+            bt => Ok(self.literal(bt)),
+        }
+    }
+
+    fn literal(&self, bt: u8) -> Node {
+        let mut set = Box::new([false; 256]);
+        set[bt as usize] = true;
+        Node::Set(self.fold(set))
+    }
+
+    fn fold(&self, mut set: ByteSet) -> ByteSet {
+        if self.conf.case_insensitive {
+            (b'a'..=b'z').for_each(|lower| {
+                let upper = lower.to_ascii_uppercase();
+                let either = set[lower as usize] || set[upper as usize];
+                set[lower as usize] = either;
+                set[upper as usize] = either;
+            });
+        }
+        set
+    }
+
+    /// Parses a bracket expression, just after its `[`.
+    fn parse_class(&mut self) -> Result<Node, SfwError> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut set = Box::new([false; 256]);
+        let mut first = true;
+        loop {
+            let bt = self.peek().ok_or_else(|| self.error("missing ]"))?;
+            if bt == b']' && !first {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+            if bt == b'[' && self.pat.get(self.pos + 1) == Some(&b':') {
+                self.parse_named_class(&mut set)?;
+                continue;
+            }
+            self.pos += 1;
+            let range_end = match (self.peek(), self.pat.get(self.pos + 1)) {
+                (Some(b'-'), Some(end)) if *end != b']' => Some(*end),
+                _ => None,
+            };
+            match range_end {
+                Some(end) if end < bt => {
+                    return Err(self.error("bad range in [...]"));
+                }
+                Some(end) => {
+                    (bt..=end).for_each(|bt| set[bt as usize] = true);
+                    self.pos += 2;
+                }
+                None => set[bt as usize] = true,
+            }
+        }
+        let mut set = self.fold(set);
+        if negated {
+            set.iter_mut().for_each(|member| *member = !*member);
+        }
+        Ok(Node::Set(set))
+    }
+
+    /// Adds the members of a class like `[:alpha:]` to `set`.
+    fn parse_named_class(&mut self, set: &mut ByteSet) -> Result<(), SfwError> {
+        let rest = &self.pat[self.pos + 2..];
+        let end = rest
+            .windows(2)
+            .position(|pair| pair == b":]")
+            .ok_or_else(|| self.error("missing :]"))?;
+        let test: fn(&u8) -> bool = match &rest[..end] {
+            b"alpha" => u8::is_ascii_alphabetic,
+            b"digit" => u8::is_ascii_digit,
+            b"alnum" => u8::is_ascii_alphanumeric,
+            b"upper" => u8::is_ascii_uppercase,
+            b"lower" => u8::is_ascii_lowercase,
+            b"space" => |bt| bt.is_ascii_whitespace() || *bt == 0x0b,
+            b"blank" => |bt| *bt == b' ' || *bt == b'\t',
+            b"punct" => u8::is_ascii_punctuation,
+            b"print" => |bt| bt.is_ascii_graphic() || *bt == b' ',
+            b"graph" => u8::is_ascii_graphic,
+            b"cntrl" => u8::is_ascii_control,
+            b"xdigit" => u8::is_ascii_hexdigit,
+            _ => return Err(self.error("unknown character class")),
+        };
+        (0..=255u8)
+            .filter(test)
+            .for_each(|bt| set[bt as usize] = true);
+        self.pos += 2 + end + 2;
+        Ok(())
+    }
+}
+
+/// How many nodes deep `node` goes.
+fn height(node: &Node) -> usize {
+    let mut stack = vec![(node, 1)];
+    let mut height = 0;
+    while let Some((node, depth)) = stack.pop() {
+        height = height.max(depth);
+        match node {
+            Node::Group(node, _) | Node::Repeat { node, .. } => {
+                stack.push((node, depth + 1))
+            }
+            Node::Concat(nodes) | Node::Alt(nodes) => {
+                stack.extend(nodes.iter().map(|node| (node, depth + 1)))
+            }
+            Node::Empty | Node::Set(_) | Node::Bol | Node::Eol => (),
+        }
+    }
+    height
+}
+
+/// Appends the instructions for `node` to `prog`.
+fn emit(node: &Node, prog: &mut Vec<Inst>) -> Result<(), ()> {
+    check_size(prog)?;
+    match node {
+        Node::Empty => (),
+        Node::Set(set) => prog.push(Inst::Byte(set.clone())),
+        Node::Bol => prog.push(Inst::Bol),
+        Node::Eol => prog.push(Inst::Eol),
+        Node::Group(node, group) => {
+            prog.push(Inst::Save(2 * group));
+            emit(node, prog)?;
+            prog.push(Inst::Save(2 * group + 1));
+        }
+        Node::Concat(nodes) => {
+            nodes.iter().try_for_each(|node| emit(node, prog))?;
+        }
+        Node::Alt(branches) => {
+            let mut jumps = vec![];
+            let (last, rest) = branches.split_last().ok_or(())?;
+            for branch in rest {
+                let split = prog.len();
+                prog.push(Inst::Split(split + 1, 0));
+                emit(branch, prog)?;
+                jumps.push(prog.len());
+                prog.push(Inst::Jmp(0));
+                prog[split] = Inst::Split(split + 1, prog.len());
+            }
+            emit(last, prog)?;
+            let end = prog.len();
+            jumps
+                .into_iter()
+                .for_each(|jump| prog[jump] = Inst::Jmp(end));
+        }
+        Node::Repeat { node, min, max } => {
+            (0..*min).try_for_each(|_| emit(node, prog))?;
+            match max {
+                None => {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    emit(node, prog)?;
+                    prog.push(Inst::Jmp(split));
+                    prog[split] = Inst::Split(split + 1, prog.len());
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Split(prog.len() + 1, 0));
+                        emit(node, prog)?;
+                    }
+                    let end = prog.len();
+                    splits.into_iter().for_each(|split| {
+                        prog[split] = Inst::Split(split + 1, end);
+                    });
+                }
+            }
+        }
+    }
+    check_size(prog)
+}
+
+fn check_size(prog: &[Inst]) -> Result<(), ()> {
+    if prog.len() > MAX_PROGRAM {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// The NFA states reached from `seeds` without reading a byte: those
+/// that read one, `Match`, and (unless `at_end`) `Eol`, which can only
+/// be settled at the end of the text. Sorted, for use as a DFA state.
+fn closure(
+    prog: &[Inst],
+    seeds: &[usize],
+    at_start: bool,
+    at_end: bool,
+) -> Vec<usize> {
+    let mut seen = vec![false; prog.len()];
+    let mut stack = seeds.iter().rev().copied().collect::<Vec<_>>();
+    let mut states = vec![];
+    while let Some(pc) = stack.pop() {
+        if seen[pc] {
+            continue;
+        }
+        seen[pc] = true;
+        match &prog[pc] {
+            Inst::Jmp(target) => stack.push(*target),
+            Inst::Split(first, second) => {
+                stack.push(*second);
+                stack.push(*first);
+            }
+            Inst::Save(_) => stack.push(pc + 1),
+            Inst::Bol if at_start => stack.push(pc + 1),
+            Inst::Bol => (),
+            Inst::Eol if at_end => stack.push(pc + 1),
+            Inst::Eol | Inst::Byte(_) | Inst::Match => states.push(pc),
+        }
+    }
+    states.sort_unstable();
+    states
+}
+
+struct DfaState {
+    nfa_states: Vec<usize>,
+    is_match: bool,
+    next: Box<[Option<usize>; 256]>,
+}
+
+/// The DFA states built so far, indexed by their sets of NFA states.
+#[derive(Default)]
+struct Dfa {
+    states: Vec<DfaState>,
+    index: HashMap<Vec<usize>, usize>,
+}
+
+impl std::fmt::Debug for Dfa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dfa({} states)", self.states.len())
+    }
+}
+
+impl Dfa {
+    fn intern(&mut self, prog: &[Inst], nfa_states: Vec<usize>) -> usize {
+        if let Some(id) = self.index.get(&nfa_states) {
+            return *id;
+        }
+        let id = self.states.len();
+        self.index.insert(nfa_states.clone(), id);
+        self.states.push(DfaState {
+            is_match: nfa_states
+                .iter()
+                .any(|pc| matches!(prog[*pc], Inst::Match)),
+            nfa_states,
+            next: Box::new([None; 256]),
+        });
+        id
+    }
+
+    /// The state after reading `bt` in state `id`, which also starts a
+    /// new search, as the match may begin anywhere.
+    fn step(&mut self, prog: &[Inst], id: usize, bt: u8) -> usize {
+        if let Some(next) = self.states[id].next[bt as usize] {
+            return next;
+        }
+        let mut seeds = self.states[id]
+            .nfa_states
+            .iter()
+            .filter_map(|pc| match &prog[*pc] {
+                Inst::Byte(set) if set[bt as usize] => Some(pc + 1),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        seeds.push(0);
+        let nfa_states = closure(prog, &seeds, false, false);
+        if self.states.len() >= MAX_DFA_STATES {
+            let current = self.states[id].nfa_states.clone();
+            self.states.clear();
+            self.index.clear();
+            let id = self.intern(prog, current);
+            let next = self.intern(prog, nfa_states);
+            self.states[id].next[bt as usize] = Some(next);
+            return next;
+        }
+        let next = self.intern(prog, nfa_states);
+        self.states[id].next[bt as usize] = Some(next);
+        next
+    }
+
+    fn is_match(&mut self, prog: &[Inst], text: &[u8], start: usize) -> bool {
+        if start > text.len() {
+            return false;
+        }
+        let mut id = self.intern(prog, closure(prog, &[0], start == 0, false));
+        for bt in &text[start..] {
+            if self.states[id].is_match {
+                return true;
+            }
+            id = self.step(prog, id, *bt);
+        }
+        let at_start = start == text.len() && start == 0;
+        self.states[id].is_match
+            || closure(prog, &self.states[id].nfa_states, at_start, true)
+                .iter()
+                .any(|pc| matches!(prog[*pc], Inst::Match))
+    }
+}
+
+/// The threads of a Pike VM at one position, in priority order.
+struct Threads {
+    present: Vec<bool>,
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            present: vec![false; size],
+            threads: vec![],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.threads
+            .iter()
+            .for_each(|(pc, _)| self.present[*pc] = false);
+        self.threads.clear();
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+/// Adds a thread at `pc`, and those it leads to without reading a byte,
+/// to `list`; `seen` marks the states visited, which, like threads
+/// already in `list`, have higher priority.
+fn add_thread(
+    prog: &[Inst],
+    list: &mut Threads,
+    seen: &mut [bool],
+    pc: usize,
+    text: &[u8],
+    pos: usize,
+    slots: &mut [Option<usize>],
+) {
+    let mut stack = vec![Frame::Explore(pc)];
+    while let Some(frame) = stack.pop() {
+        let pc = match frame {
+            Frame::Restore(slot, old) => {
+                slots[slot] = old;
+                continue;
+            }
+            Frame::Explore(pc) => pc,
+        };
+        if seen[pc] {
+            continue;
+        }
+        seen[pc] = true;
+        match &prog[pc] {
+            Inst::Jmp(target) => stack.push(Frame::Explore(*target)),
+            Inst::Split(first, second) => {
+                stack.push(Frame::Explore(*second));
+                stack.push(Frame::Explore(*first));
+            }
+            Inst::Save(slot) => {
+                stack.push(Frame::Restore(*slot, slots[*slot]));
+                slots[*slot] = Some(pos);
+                stack.push(Frame::Explore(pc + 1));
+            }
+            Inst::Bol if pos == 0 => stack.push(Frame::Explore(pc + 1)),
+            Inst::Eol if pos == text.len() => {
+                stack.push(Frame::Explore(pc + 1))
+            }
+            Inst::Bol | Inst::Eol => (),
+            Inst::Byte(_) | Inst::Match => {
+                if !list.present[pc] {
+                    list.present[pc] = true;
+                    list.threads.push((pc, slots.to_vec()));
+                }
+            }
+        }
+    }
+}
+
+/// Runs the NFA over `text` from `start`, returning the capture slots of
+/// the preferred match.
+fn pike_vm(
+    prog: &[Inst],
+    slot_count: usize,
+    text: &[u8],
+    start: usize,
+) -> Option<Vec<Option<usize>>> {
+    let mut current = Threads::new(prog.len());
+    let mut next = Threads::new(prog.len());
+    let mut seen = vec![false; prog.len()];
+    let mut slots = vec![None; slot_count];
+    let mut matched = None;
+    for pos in start..=text.len() {
+        // The threads carried over were marked when added.
+        seen.iter_mut().for_each(|seen| *seen = false);
+        current.threads.iter().for_each(|(pc, _)| seen[*pc] = true);
+        if matched.is_none() {
+            add_thread(prog, &mut current, &mut seen, 0, text, pos, &mut slots);
+        }
+        if current.threads.is_empty() {
+            break;
+        }
+        seen.iter_mut().for_each(|seen| *seen = false);
+        for (pc, thread_slots) in current.threads.iter_mut() {
+            match &prog[*pc] {
+                Inst::Byte(set) => {
+                    if let Some(bt) = text.get(pos) {
+                        if set[*bt as usize] {
+                            add_thread(
+                                prog,
+                                &mut next,
+                                &mut seen,
+                                *pc + 1,
+                                text,
+                                pos + 1,
+                                thread_slots,
+                            );
+                        }
+                    }
+                }
+                Inst::Match => {
+                    matched = Some(thread_slots.clone());
+                    // Threads after this one have lower priority.
+                    break;
+                }
+                _ => (),
+            }
+        }
+        current.clear();
+        std::mem::swap(&mut current, &mut next);
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sorting::SplitMix64;
+
+    fn regex(pat: &str) -> Regex {
+        Regex::compile(pat.as_bytes(), &RegexConf::default()).unwrap()
+    }
+
+    fn find(pat: &str, text: &str) -> Option<(usize, usize)> {
+        regex(pat).find(text.as_bytes())
+    }
+
+    #[test]
+    fn test_regex_syntax() {
+        assert_eq!(find("b+c", "abbbcd"), Some((1, 5)));
+        assert_eq!(find("colou?r", "the color"), Some((4, 9)));
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("(a|b){2,3}c", "xababc"), Some((2, 6)));
+        assert_eq!(find("x{2}", "xxx"), Some((0, 2)));
+        assert_eq!(find("^a", "ba"), None);
+        assert_eq!(find("a$", "aba"), Some((2, 3)));
+        assert_eq!(find("[[:digit:]]+", "ab123c"), Some((2, 5)));
+        assert_eq!(find("[^a-c]", "abcd"), Some((3, 4)));
+        assert_eq!(find("[]a]+", "x]a]"), Some((1, 4)));
+        assert_eq!(find("a\\.b\\t", "axb a.b\t"), Some((4, 8)));
+        assert_eq!(find("a{,", "a{,"), Some((0, 3)));
+        assert_eq!(find("$^", ""), Some((0, 0)));
+        ["(a", "a)", "*a", "a{2,1}", "[a", "[[:foo:]]", "a{1001}"]
+            .iter()
+            .for_each(|pat| {
+                assert!(
+                    Regex::compile(pat.as_bytes(), &RegexConf::default())
+                        .is_err(),
+                    "{}",
+                    pat
+                )
+            });
+    }
+
+    #[test]
+    fn test_regex_stacked_quantifiers() {
+        let stars = format!("ba{}c", "*".repeat(100_000));
+        assert_eq!(find(&stars, "xbaaac"), Some((1, 6)));
+        assert_eq!(find(&stars, "xbc"), Some((1, 3)));
+        assert_eq!(find("ba+?+c", "bc"), Some((0, 2)));
+        assert_eq!(find("ba{1,2}{2}c", "baac baaac"), Some((0, 4)));
+        assert_eq!(find("^x{2}*$", "xxx"), None);
+        assert_eq!(find("^x{2}*$", "xxxx"), Some((0, 4)));
+        let conf = RegexConf::default();
+        let deep = format!("a{}", "{2}".repeat(10_000));
+        assert!(Regex::compile(deep.as_bytes(), &conf).is_err());
+        assert!(Regex::compile(b"a{1,100}{1,100}", &conf).is_err());
+    }
+
+    #[test]
+    fn test_regex_captures_and_case() {
+        let re = regex("([a-z]+)@([a-z]+)(\\.com)?");
+        let caps = re.captures(b"mail bob@example now").unwrap();
+        assert_eq!(re.group_count(), 3);
+        assert_eq!(caps.get(0), Some((5, 16)));
+        assert_eq!(caps.get(1), Some((5, 8)));
+        assert_eq!(caps.get(2), Some((9, 16)));
+        assert_eq!(caps.get(3), None);
+        let caps = regex("(a*)+b").captures(b"aab").unwrap();
+        assert_eq!(caps.get(0), Some((0, 3)));
+        let conf = RegexConf {
+            case_insensitive: true,
+        };
+        let re = Regex::compile(b"hello [w-z]orld", &conf).unwrap();
+        assert!(re.is_match(b"HeLLo World"));
+        assert_eq!(re.find_at(b"hello world hello xorld", 1), Some((12, 23)));
+    }
+
+    #[test]
+    fn test_regex_linear_time() {
+        // Catastrophic for backtracking matchers.
+        let text = vec![b'a'; 20_000];
+        let re = regex("(a|a)*(a*)*b");
+        assert!(!re.is_match(&text));
+        assert_eq!(re.find(&text), None);
+        let re = regex("(a?){30}a{30}");
+        assert_eq!(re.find(&text[..30]), Some((0, 30)));
+    }
+
+    #[test]
+    fn test_regex_dfa_agrees_with_pike_vm() {
+        // Patterns whose DFAs have many states, and anchors.
+        let patterns = ["(a|b)*a(a|b){12}", "^(ab|ba)*$", "a(b|c)?c+$|^b"];
+        let mut rng = SplitMix64::new(7);
+        patterns.iter().for_each(|pat| {
+            let re = regex(pat);
+            (0..300).for_each(|_| {
+                let len = rng.below(40);
+                let text =
+                    (0..len).map(|_| b"abc"[rng.below(3)]).collect::<Vec<u8>>();
+                let slot_count = 2 * (re.groups + 1);
+                let slots = pike_vm(&re.prog, slot_count, &text, 0);
+                assert_eq!(re.is_match(&text), slots.is_some(), "{}", pat);
+            });
+        });
+    }
+}
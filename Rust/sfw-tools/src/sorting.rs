@@ -0,0 +1,833 @@
+/*
+`sort` from chapter 4 of Software Tools sorts the lines of its input.
+Input that fits in memory is simply sorted there; otherwise, as in the
+book, the input is read in memory-sized pieces, each of which is sorted
+and written to a temporary file as a "run", and the runs are then merged
+(several at a time, if there are many) into the final output.
+
+The sort is stable: lines that compare equal keep their input order,
+even when reversed. Lines may be compared by a range of fields, as
+numbers, or ignoring case.
+
+`shuffle` does the opposite, putting lines in a random order with a
+Fisher-Yates shuffle. Given a sample size, it holds only that many lines,
+choosing them by reservoir sampling, so that streams too big for memory
+can be sampled; it can also sample with replacement. Its generator is a
+small SplitMix64, so that a seed reproduces the same output anywhere.
+ */
+
+use std::cmp::Ordering;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use funciter::Iterable;
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::{ByteLines, BytesIter};
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, opt_usize_flag, STD_STREAM_NAME};
+
+/// The most runs merged at once, which bounds the files held open.
+const MERGE_ORDER: usize = 16;
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+/// Numbers the temporary files of every sort in this process.
+static RUN_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The fields compared by `sort -k START[,END]`, counting from 1.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SortKey {
+    pub start: usize,
+    /// The last field compared; the key runs to the end of the line if
+    /// `None`.
+    pub end: Option<usize>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SortConf {
+    pub reverse: bool,
+    /// Compare the leading numbers of keys.
+    pub numeric: bool,
+    pub fold_case: bool,
+    /// Output only the first of each run of equal lines.
+    pub unique: bool,
+    pub key: Option<SortKey>,
+    /// Fields are separated by this byte, rather than by runs of blanks.
+    pub separator: Option<u8>,
+    /// Roughly the number of bytes of input sorted in memory at once.
+    pub memory_limit: usize,
+    /// Where runs are written; the system's temporary directory if
+    /// `None`.
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl Default for SortConf {
+    fn default() -> Self {
+        SortConf {
+            reverse: false,
+            numeric: false,
+            fold_case: false,
+            unique: false,
+            key: None,
+            separator: None,
+            memory_limit: DEFAULT_SORT_MEMORY,
+            temp_dir: None,
+        }
+    }
+}
+
+const SORT_USAGE: &str = r#"
+sort [OPTION]... [FILE]...
+
+Writes the lines of all FILEs (or STDIN) to STDOUT in sorted order.
+Lines that compare equal keep their input order.
+
+Valid options are:
+-r               reverse the order
+-n               compare numerically
+-f               fold lower case to upper case
+-u               output only the first of each run of equal lines
+-k START[,END]   compare fields START through END (default: the
+                 whole line); fields are counted from 1
+-t CHAR          fields are separated by CHAR, rather than by blanks
+-S SIZE          sort about SIZE bytes in memory at once, writing
+                 larger input to temporary files; K, M and G suffixes
+                 are allowed (default 64M)
+-T DIR           write temporary files to DIR
+
+"#;
+
+pub fn run_sort_seahorse_cmd() -> Command {
+    Command::new("sort")
+        .description("sort: sort lines of text; output to STDOUT")
+        .usage(SORT_USAGE)
+        .action(run_sort_seahorse_action)
+        .flag(
+            Flag::new("reverse", FlagType::Bool)
+                .alias("r")
+                .description("sort -r some_file"),
+        )
+        .flag(
+            Flag::new("numeric", FlagType::Bool)
+                .alias("n")
+                .description("sort -n some_file"),
+        )
+        .flag(
+            Flag::new("fold-case", FlagType::Bool)
+                .alias("f")
+                .description("sort -f some_file"),
+        )
+        .flag(
+            Flag::new("unique", FlagType::Bool)
+                .alias("u")
+                .description("sort -u some_file"),
+        )
+        .flag(
+            Flag::new("key", FlagType::String)
+                .alias("k")
+                .description("sort -k 2,3 some_file"),
+        )
+        .flag(
+            Flag::new("separator", FlagType::String)
+                .alias("t")
+                .description("sort -t : -k 3 some_file"),
+        )
+        .flag(
+            Flag::new("buffer-size", FlagType::String)
+                .alias("S")
+                .description("sort -S 10M some_file"),
+        )
+        .flag(
+            Flag::new("temp-dir", FlagType::String)
+                .alias("T")
+                .description("sort -T /var/tmp some_file"),
+        )
+}
+
+pub fn run_sort_seahorse_action(ctxt: &Context) {
+    let defaults = SortConf::default();
+    let key = ctxt
+        .string_flag("key")
+        .ok()
+        .map(|key| parse_key(&key))
+        .transpose()
+        .user_err("Error in sort");
+    let separator = ctxt
+        .string_flag("separator")
+        .ok()
+        .map(|sep| match sep.as_bytes() {
+            [sep] => Ok(*sep),
+            _ => Err(SfwError::usage("sort: -t expects a single character")),
+        })
+        .transpose()
+        .user_err("Error in sort");
+    let memory_limit = ctxt
+        .string_flag("buffer-size")
+        .ok()
+        .map_or(Ok(defaults.memory_limit), |size| parse_size(&size))
+        .user_err("Error in sort");
+    let conf = SortConf {
+        reverse: ctxt.bool_flag("reverse"),
+        numeric: ctxt.bool_flag("numeric"),
+        fold_case: ctxt.bool_flag("fold-case"),
+        unique: ctxt.bool_flag("unique"),
+        key,
+        separator,
+        memory_limit,
+        temp_dir: ctxt.string_flag("temp-dir").ok().map(PathBuf::from),
+    };
+    run_sort(&conf, &ctxt.args);
+}
+
+/// Convenience function for running sort in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_sort(conf: &SortConf, srcs: &[String]) {
+    let default_src = [String::from(STD_STREAM_NAME)];
+    let srcs = if srcs.is_empty() { &default_src } else { srcs };
+    let f_ins = srcs
+        .iter()
+        .map(|src| open_input(Some(src)))
+        .collect::<Result<Vec<Box<dyn Read>>, SfwError>>()
+        .user_err("Error in sort");
+    sort(conf, f_ins, BufWriter::new(io::stdout())).user_err("Error in sort");
+}
+
+/// Parses `START[,END]`.
+pub fn parse_key(spec: &str) -> Result<SortKey, SfwError> {
+    let field = |num: &str| match num.trim().parse::<usize>() {
+        Ok(num) if num > 0 => Ok(num),
+        _ => Err(SfwError::usage(format!(
+            "sort: invalid key \"{}\": fields are counted from 1",
+            spec
+        ))),
+    };
+    let mut parts = spec.splitn(2, ',');
+    let start = field(parts.next().unwrap_or_default())?;
+    let end = parts.next().map(field).transpose()?;
+    match end {
+        Some(end) if end < start => Err(SfwError::usage(format!(
+            "sort: invalid key \"{}\": END comes before START",
+            spec
+        ))),
+        _ => Ok(SortKey { start, end }),
+    }
+}
+
+/// Parses a number of bytes, with an optional `K`, `M` or `G` suffix.
+pub fn parse_size(size: &str) -> Result<usize, SfwError> {
+    let (num, scale) = match size.char_indices().last() {
+        Some((ix, 'K')) | Some((ix, 'k')) => (&size[..ix], 1 << 10),
+        Some((ix, 'M')) | Some((ix, 'm')) => (&size[..ix], 1 << 20),
+        Some((ix, 'G')) | Some((ix, 'g')) => (&size[..ix], 1 << 30),
+        _ => (size, 1),
+    };
+    num.parse::<usize>()
+        .ok()
+        .and_then(|num| num.checked_mul(scale))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| {
+            SfwError::usage(format!("sort: invalid memory size \"{}\"", size))
+        })
+}
+
+/// Sorts the lines of all of `f_ins`, taken together, into `f_out`.
+pub fn sort<R: Read, W: Write>(
+    conf: &SortConf,
+    f_ins: Vec<R>,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let mut runs: Vec<SortRun> = vec![];
+    let mut lines: Vec<Vec<u8>> = vec![];
+    let mut size = 0;
+    for line in input_lines(f_ins) {
+        let line = line?;
+        size += line.len() + mem::size_of::<Vec<u8>>();
+        lines.push(line);
+        if size >= conf.memory_limit {
+            let sorted = sort_lines(conf, mem::take(&mut lines));
+            runs.push(SortRun::write(conf, sorted)?);
+            size = 0;
+        }
+    }
+    let sorted = sort_lines(conf, lines);
+    if runs.is_empty() {
+        write_unique(conf, sorted.into_iter().map(Ok), &mut f_out)?;
+    } else {
+        if !sorted.is_empty() {
+            runs.push(SortRun::write(conf, sorted)?);
+        }
+        while runs.len() > MERGE_ORDER {
+            let mut groups = runs.into_iter().peekable();
+            runs = vec![];
+            while groups.peek().is_some() {
+                let group = groups.by_ref().take(MERGE_ORDER).collect();
+                runs.push(SortRun::merge(conf, group)?);
+            }
+        }
+        write_unique(conf, merge_runs(conf, &runs)?, &mut f_out)?;
+    }
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+fn sort_lines(conf: &SortConf, lines: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    lines.sorted_by(|line1, line2| compare_lines(conf, line1, line2))
+}
+
+/// The lines of each of `f_ins` in turn, without their newlines.
+fn input_lines<R: Read>(
+    f_ins: Vec<R>,
+) -> impl Iterator<Item = Result<Vec<u8>, SfwError>> {
+    f_ins
+        .into_iter()
+        .flat_map(|f_in| BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines())
+        .map(|line| line.sfw_err("Couldn't read input"))
+}
+
+fn next_run_line(
+    run: &mut ByteLines<File>,
+) -> Result<Option<Vec<u8>>, SfwError> {
+    run.next()
+        .transpose()
+        .sfw_err("Couldn't read temporary file")
+}
+
+/// Writes each line, except those equal to the one before when `unique`
+/// is set.
+fn write_unique<I, W>(
+    conf: &SortConf,
+    lines: I,
+    f_out: &mut W,
+) -> Result<(), SfwError>
+where
+    I: Iterator<Item = Result<Vec<u8>, SfwError>>,
+    W: Write,
+{
+    let mut prev: Option<Vec<u8>> = None;
+    for line in lines {
+        let line = line?;
+        let repeated = prev.as_ref().is_some_and(|prev| {
+            compare_lines(conf, prev, &line) == Ordering::Equal
+        });
+        if !(conf.unique && repeated) {
+            f_out.write_all(&line)?;
+            f_out.write_all(b"\n")?;
+        }
+        prev = Some(line);
+    }
+    Ok(())
+}
+
+/// Compares two lines according to `conf`, without a final comparison
+/// of whole lines, so that the sort is stable.
+pub fn compare_lines(conf: &SortConf, line1: &[u8], line2: &[u8]) -> Ordering {
+    let key1 = sort_key(conf, line1);
+    let key2 = sort_key(conf, line2);
+    let order = if conf.numeric {
+        leading_number(key1)
+            .partial_cmp(&leading_number(key2))
+            .unwrap_or(Ordering::Equal)
+    } else if conf.fold_case {
+        let fold = |key: &'_ [u8]| {
+            key.iter().map(u8::to_ascii_uppercase).collect::<Vec<u8>>()
+        };
+        fold(key1).cmp(&fold(key2))
+    } else {
+        key1.cmp(key2)
+    };
+    if conf.reverse {
+        order.reverse()
+    } else {
+        order
+    }
+}
+
+/// The part of `line` compared, as chosen by `conf.key`. Without a
+/// separator, fields are runs of non-blanks and the key starts at the
+/// first non-blank of its first field.
+fn sort_key<'a>(conf: &SortConf, line: &'a [u8]) -> &'a [u8] {
+    let key = match conf.key {
+        None => return line,
+        Some(key) => key,
+    };
+    let fields = field_spans(line, conf.separator);
+    let start = fields.get(key.start - 1).map_or(line.len(), |fd| fd.0);
+    let end = key
+        .end
+        .and_then(|end| fields.get(end - 1))
+        .map_or(line.len(), |fd| fd.1);
+    &line[start..end.max(start)]
+}
+
+/// The (start, end) of each field of `line`.
+fn field_spans(line: &[u8], separator: Option<u8>) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = 0;
+    match separator {
+        Some(sep) => {
+            line.iter().enumerate().for_each(|(ix, bt)| {
+                if *bt == sep {
+                    spans.push((start, ix));
+                    start = ix + 1;
+                }
+            });
+            spans.push((start, line.len()));
+        }
+        None => {
+            let mut in_field = false;
+            line.iter().enumerate().for_each(|(ix, bt)| {
+                let blank = *bt == b' ' || *bt == b'\t';
+                if !blank && !in_field {
+                    start = ix;
+                } else if blank && in_field {
+                    spans.push((start, ix));
+                }
+                in_field = !blank;
+            });
+            if in_field {
+                spans.push((start, line.len()));
+            }
+        }
+    }
+    spans
+}
+
+/// The number at the start of `key` (after any blanks), or 0 if there
+/// isn't one.
+fn leading_number(key: &[u8]) -> f64 {
+    let key = String::from_utf8_lossy(key);
+    let key = key.trim_start();
+    let len = key
+        .char_indices()
+        .take_while(|(ix, ch)| {
+            ch.is_ascii_digit() || *ch == '.' || (*ix == 0 && *ch == '-')
+        })
+        .count();
+    (1..=len)
+        .rev()
+        .find_map(|len| key[..len].parse::<f64>().ok())
+        .unwrap_or(0.0)
+}
+
+/// A sorted run of lines, in a temporary file removed when dropped.
+struct SortRun {
+    path: PathBuf,
+}
+
+impl SortRun {
+    fn create(conf: &SortConf) -> Result<(Self, File), SfwError> {
+        let num = RUN_COUNT.fetch_add(1, AtomicOrdering::Relaxed);
+        let dir = conf.temp_dir.clone().unwrap_or_else(env::temp_dir);
+        let path = dir.join(format!("sfwtools-sort.{}.{}", process::id(), num));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .sfw_path_err("Couldn't create temporary file", &path)?;
+        Ok((SortRun { path }, file))
+    }
+
+    fn write(conf: &SortConf, lines: Vec<Vec<u8>>) -> Result<Self, SfwError> {
+        let (run, file) = SortRun::create(conf)?;
+        let mut f_out = BufWriter::new(file);
+        lines.iter().try_for_each(|line| {
+            f_out.write_all(line)?;
+            f_out.write_all(b"\n")
+        })?;
+        f_out.flush().sfw_path_err("Couldn't write", &run.path)?;
+        Ok(run)
+    }
+
+    /// Merges `runs` into a single new run.
+    fn merge(conf: &SortConf, runs: Vec<SortRun>) -> Result<Self, SfwError> {
+        let (run, file) = SortRun::create(conf)?;
+        let mut f_out = BufWriter::new(file);
+        merge_runs(conf, &runs)?.try_for_each(|line| {
+            f_out.write_all(&line?)?;
+            f_out.write_all(b"\n")?;
+            Ok::<(), SfwError>(())
+        })?;
+        f_out.flush().sfw_path_err("Couldn't write", &run.path)?;
+        Ok(run)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SortRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The lines of `runs`, merged in order; of equal lines, those from
+/// earlier runs come first.
+fn merge_runs<'a>(
+    conf: &'a SortConf,
+    runs: &[SortRun],
+) -> Result<MergedRuns<'a>, SfwError> {
+    let mut readers = runs
+        .iter()
+        .map(|run| {
+            File::open(run.path())
+                .map(|file| BytesIter::new(file, DEFAULT_BUF_SIZE).lines())
+                .sfw_path_err("Couldn't open temporary file", run.path())
+        })
+        .collect::<Result<Vec<_>, SfwError>>()?;
+    let heads = readers
+        .iter_mut()
+        .map(next_run_line)
+        .collect::<Result<Vec<_>, SfwError>>()?;
+    Ok(MergedRuns {
+        conf,
+        readers,
+        heads,
+    })
+}
+
+struct MergedRuns<'a> {
+    conf: &'a SortConf,
+    readers: Vec<ByteLines<File>>,
+    /// The next line of each run.
+    heads: Vec<Option<Vec<u8>>>,
+}
+
+impl Iterator for MergedRuns<'_> {
+    type Item = Result<Vec<u8>, SfwError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let conf = self.conf;
+        let (ix, _) = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, head)| head.as_ref().map(|line| (ix, line)))
+            .reduce(|least, next| {
+                match compare_lines(conf, next.1, least.1) {
+                    Ordering::Less => next,
+                    _ => least,
+                }
+            })?;
+        let next = next_run_line(&mut self.readers[ix]);
+        let line = match next {
+            Ok(next) => mem::replace(&mut self.heads[ix], next),
+            Err(err) => return Some(Err(err)),
+        };
+        line.map(Ok)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct ShuffleConf {
+    /// Output at most this many lines.
+    pub count: Option<usize>,
+    /// Sample with replacement, so lines may be repeated; without a
+    /// `count`, this goes on forever.
+    pub repeat: bool,
+    /// Seeds the generator; from the clock if `None`.
+    pub seed: Option<u64>,
+}
+
+/// The SplitMix64 generator of Steele, Lea and Flood: tiny, fast, and
+/// good enough for shuffling, though not for cryptography.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    /// Seeds a generator from the clock and the process ID.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_nanos() as u64)
+            .unwrap_or_default();
+        SplitMix64::new(nanos ^ (u64::from(process::id()) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let z = self.state;
+        let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed number in `0..bound`, which must not be
+    /// 0; values that would bias the result are drawn again.
+    pub fn below(&mut self, bound: usize) -> usize {
+        let bound = bound as u64;
+        let limit = u64::MAX - (u64::MAX % bound + 1) % bound;
+        loop {
+            let value = self.next_u64();
+            if value <= limit {
+                return (value % bound) as usize;
+            }
+        }
+    }
+
+    /// Shuffles `items` in place, with every order equally likely.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        (1..items.len()).rev().for_each(|ix| {
+            let other = self.below(ix + 1);
+            items.swap(ix, other);
+        });
+    }
+}
+
+const SHUFFLE_USAGE: &str = r#"
+shuffle [OPTION]... [FILE]...
+
+Writes the lines of all FILEs (or STDIN) to STDOUT in a random order.
+
+Valid options are:
+-n K          output at most K lines; only K lines are held in memory
+              unless --repeat is given
+--repeat      choose lines with replacement, so that they may be
+              repeated; without -n, lines are output forever
+--seed N      seed the random numbers, to reproduce an earlier shuffle
+
+"#;
+
+pub fn run_shuffle_seahorse_cmd() -> Command {
+    Command::new("shuffle")
+        .description("shuffle: randomly permute lines; output to STDOUT")
+        .usage(SHUFFLE_USAGE)
+        .action(run_shuffle_seahorse_action)
+        .flag(
+            Flag::new("count", FlagType::Int)
+                .alias("n")
+                .description("shuffle -n 10 some_file"),
+        )
+        .flag(
+            Flag::new("repeat", FlagType::Bool)
+                .description("shuffle --repeat -n 10 some_file"),
+        )
+        .flag(
+            Flag::new("seed", FlagType::String)
+                .description("shuffle --seed 42 some_file"),
+        )
+}
+
+pub fn run_shuffle_seahorse_action(ctxt: &Context) {
+    let seed = ctxt
+        .string_flag("seed")
+        .ok()
+        .map(|seed| {
+            seed.parse::<u64>().map_err(|_| {
+                SfwError::usage(format!("shuffle: invalid seed \"{}\"", seed))
+            })
+        })
+        .transpose()
+        .user_err("Error in shuffle");
+    let conf = ShuffleConf {
+        count: opt_usize_flag(ctxt, "count").user_err("Error in shuffle"),
+        repeat: ctxt.bool_flag("repeat"),
+        seed,
+    };
+    run_shuffle(&conf, &ctxt.args);
+}
+
+/// Convenience function for running shuffle in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_shuffle(conf: &ShuffleConf, srcs: &[String]) {
+    let default_src = [String::from(STD_STREAM_NAME)];
+    let srcs = if srcs.is_empty() { &default_src } else { srcs };
+    let f_ins = srcs
+        .iter()
+        .map(|src| open_input(Some(src)))
+        .collect::<Result<Vec<Box<dyn Read>>, SfwError>>()
+        .user_err("Error in shuffle");
+    shuffle(conf, f_ins, BufWriter::new(io::stdout()))
+        .user_err("Error in shuffle");
+}
+
+/// Writes the lines of all of `f_ins`, taken together, to `f_out` in a
+/// random order, or a random sample of them.
+pub fn shuffle<R: Read, W: Write>(
+    conf: &ShuffleConf,
+    f_ins: Vec<R>,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let mut rng = conf
+        .seed
+        .map_or_else(SplitMix64::from_time, SplitMix64::new);
+    let mut write_line = |line: &[u8]| {
+        f_out.write_all(line)?;
+        f_out.write_all(b"\n")
+    };
+    match (conf.count, conf.repeat) {
+        (_, true) => {
+            let lines = input_lines(f_ins).collect::<Result<Vec<_>, _>>()?;
+            if !lines.is_empty() {
+                (0..conf.count.unwrap_or(usize::MAX)).try_for_each(|_| {
+                    write_line(&lines[rng.below(lines.len())])
+                })?;
+            }
+        }
+        (Some(count), false) => {
+            let mut sample = Vec::with_capacity(count.min(DEFAULT_BUF_SIZE));
+            input_lines(f_ins).enumerate().try_for_each(|(ix, line)| {
+                let line = line?;
+                if ix < count {
+                    sample.push(line);
+                } else {
+                    let slot = rng.below(ix + 1);
+                    if slot < count {
+                        sample[slot] = line;
+                    }
+                }
+                Ok::<(), SfwError>(())
+            })?;
+            rng.shuffle(&mut sample);
+            sample.iter().try_for_each(|line| write_line(line))?;
+        }
+        (None, false) => {
+            let mut lines =
+                input_lines(f_ins).collect::<Result<Vec<_>, _>>()?;
+            rng.shuffle(&mut lines);
+            lines.iter().try_for_each(|line| write_line(line))?;
+        }
+    }
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort_str(conf: &SortConf, input: &str) -> String {
+        let mut out = vec![];
+        sort(conf, vec![input.as_bytes()], &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sort_keys_and_options() {
+        let input = "b 10 x\na 9 y\nC 10 z\nc 2 w";
+        let conf = SortConf::default();
+        assert_eq!(sort_str(&conf, input), "C 10 z\na 9 y\nb 10 x\nc 2 w\n");
+        let conf = SortConf {
+            fold_case: true,
+            unique: true,
+            key: Some(SortKey {
+                start: 1,
+                end: Some(1),
+            }),
+            ..SortConf::default()
+        };
+        assert_eq!(sort_str(&conf, input), "a 9 y\nb 10 x\nC 10 z\n");
+        // Stable: the equal keys 10 keep their input order when reversed.
+        let conf = SortConf {
+            numeric: true,
+            reverse: true,
+            key: parse_key("2,2").ok(),
+            ..SortConf::default()
+        };
+        assert_eq!(sort_str(&conf, input), "b 10 x\nC 10 z\na 9 y\nc 2 w\n");
+        let conf = SortConf {
+            separator: Some(b':'),
+            key: parse_key("2").ok(),
+            ..SortConf::default()
+        };
+        assert_eq!(sort_str(&conf, "x:b:1\ny:a:2\n"), "y:a:2\nx:b:1\n");
+    }
+
+    #[test]
+    fn test_sort_external_merge() {
+        // Several levels of merging, with many equal keys to keep stable.
+        let input = (0..2000)
+            .map(|ix| format!("{} {}\n", (ix * 7919) % 97, ix))
+            .collect::<String>();
+        let conf = SortConf {
+            numeric: true,
+            memory_limit: 256,
+            ..SortConf::default()
+        };
+        let mut expected = input.lines().collect::<Vec<&str>>();
+        expected.sort_by_key(|line| {
+            line.split(' ').next().unwrap().parse::<u32>().unwrap()
+        });
+        let expected = expected.join("\n") + "\n";
+        assert_eq!(sort_str(&conf, &input), expected);
+        let temp = env::temp_dir();
+        let prefix = format!("sfwtools-sort.{}.", process::id());
+        assert!(!fs::read_dir(temp).unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&prefix)));
+    }
+
+    fn shuffle_lines(conf: &ShuffleConf, input: &str) -> Vec<String> {
+        let mut out = vec![];
+        shuffle(conf, vec![input.as_bytes()], &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_splitmix64() {
+        // The reference outputs for seed 1234567.
+        let mut rng = SplitMix64::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(rng.next_u64(), 3203168211198807973);
+        assert!((0..1000).all(|_| rng.below(7) < 7));
+    }
+
+    #[test]
+    fn test_shuffle_and_sample() {
+        let input = (0..100).map(|ix| format!("{}\n", ix)).collect::<String>();
+        let all = input.lines().map(String::from).collect::<Vec<_>>();
+        let conf = ShuffleConf {
+            seed: Some(42),
+            ..ShuffleConf::default()
+        };
+        let shuffled = shuffle_lines(&conf, &input);
+        assert_eq!(shuffled, shuffle_lines(&conf, &input));
+        assert_ne!(shuffled, all);
+        let mut sorted = shuffled;
+        sorted.sort_by_key(|line| line.parse::<u32>().unwrap());
+        assert_eq!(sorted, all);
+
+        let conf = ShuffleConf {
+            count: Some(10),
+            ..conf
+        };
+        let sample = shuffle_lines(&conf, &input);
+        assert_eq!(sample.len(), 10);
+        assert!(sample.iter().all(|line| all.contains(line)));
+        assert_eq!(shuffle_lines(&conf, "a\nb\n").len(), 2);
+
+        let conf = ShuffleConf {
+            count: Some(50),
+            repeat: true,
+            ..conf
+        };
+        assert_eq!(shuffle_lines(&conf, "x\n"), vec!["x"; 50]);
+        assert!(shuffle_lines(&conf, "").is_empty());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("10").unwrap(), 10);
+        assert_eq!(parse_size("2M").unwrap(), 2 << 20);
+        assert!(parse_size("x").is_err());
+        assert!(parse_key("0").is_err());
+        assert!(parse_key("3,2").is_err());
+    }
+}
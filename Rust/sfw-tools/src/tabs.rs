@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{Error, Read, Write};
+use std::io::{Read, Write};
 
 use seahorse::{App, Command, Context};
 use tailcall::tailcall;
@@ -54,15 +54,15 @@ pub fn run_detab(src: &str, dst: Box<dyn Write>) {
     detab(src, dst).user_err("Error in detab");
 }
 
-pub fn detab<W: Write>(src: &str, mut f_out: W) -> Result<(), Error> {
-    let f_in = File::open(src).sfw_err("Couldn't open source")?;
+pub fn detab<W: Write>(src: &str, mut f_out: W) -> Result<(), SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     let f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
     detab_go(
         &TabConf::TabConstant(2),
         &mut f_out,
         f_in_iter,
         vec![].into_iter(),
-        0,
+        TabState::default(),
     )
 }
 
@@ -86,42 +86,95 @@ pub fn tab_pos_to_space(tab_config: &TabConf, pos: usize) -> usize {
 
 const SPACE_ARRAY: [u8; 256] = [b' '; 256];
 
+/// How far along the current line the output is: the column, and the
+/// number of tabs expanded so far (the position a `TabMap` is keyed by).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+struct TabState {
+    col: usize,
+    tab_pos: usize,
+}
+
+/// Writes `byte` with any tab expanded to the next tab stop, i.e. the
+/// next multiple of the tab's width, returning the new state.
+fn detab_byte<W: Write>(
+    tab_cnf: &TabConf,
+    f_out: &mut W,
+    byte: u8,
+    state: TabState,
+) -> Result<TabState, SfwError> {
+    match byte {
+        b'\t' => {
+            let width = tab_pos_to_space(tab_cnf, state.tab_pos).max(1);
+            let spc_count = width - state.col % width;
+            write_spaces(f_out, spc_count)?;
+            Ok(TabState {
+                col: state.col + spc_count,
+                tab_pos: state.tab_pos + 1,
+            })
+        }
+        b'\n' => {
+            write_u8(f_out, byte)?;
+            Ok(TabState::default())
+        }
+        _ => {
+            write_u8(f_out, byte)?;
+            // UTF-8 continuation bytes don't start a new column.
+            let col_width = usize::from(byte & 0xC0 != 0x80);
+            Ok(TabState {
+                col: state.col + col_width,
+                ..state
+            })
+        }
+    }
+}
+
+/// Writes `count` spaces, a `SPACE_ARRAY` at a time.
+fn write_spaces<W: Write>(f_out: &mut W, count: usize) -> Result<(), SfwError> {
+    let mut left = count;
+    while left > 0 {
+        let chunk = left.min(SPACE_ARRAY.len());
+        f_out.write_all(&SPACE_ARRAY[..chunk])?;
+        left -= chunk;
+    }
+    Ok(())
+}
+
+/// Writes a single line (or the start of one) with its tabs expanded
+/// as `detab` would, for tools that lay out text themselves.
+pub fn detab_line<W: Write>(
+    tab_cnf: &TabConf,
+    line: &[u8],
+    f_out: &mut W,
+) -> Result<(), SfwError> {
+    line.iter()
+        .try_fold(TabState::default(), |state, byte| {
+            detab_byte(tab_cnf, f_out, *byte, state)
+        })
+        .map(|_| ())
+}
+
 #[tailcall]
 fn detab_go<'a, R, W>(
     tab_cnf: &TabConf,
     f_out: &mut W,
     mut bytes_iter: BytesIter<R>,
     mut buf_iter: std::vec::IntoIter<u8>,
-    tab_pos: usize,
-) -> Result<(), Error>
+    state: TabState,
+) -> Result<(), SfwError>
 where
     R: Read,
     W: Write,
 {
     match buf_iter.next() {
         Some(byte) => {
-            let tab_pos_new = match byte {
-                b'\t' => {
-                    let spc_count = tab_pos_to_space(tab_cnf, tab_pos);
-                    f_out.write_all(&SPACE_ARRAY[0..spc_count])?;
-                    tab_pos + 1
-                }
-                b'\n' => {
-                    write_u8(f_out, byte)?;
-                    0
-                }
-                _ => {
-                    write_u8(f_out, byte)?;
-                    tab_pos
-                }
-            };
-            detab_go(tab_cnf, f_out, bytes_iter, buf_iter, tab_pos_new)
+            let state_new = detab_byte(tab_cnf, f_out, byte, state)?;
+            detab_go(tab_cnf, f_out, bytes_iter, buf_iter, state_new)
         }
         None => {
             match bytes_iter.next() {
                 Some(buf_new) => {
                     let buf_iter = buf_new?.into_iter(); //shadow
-                    detab_go(tab_cnf, f_out, bytes_iter, buf_iter, tab_pos)
+                    detab_go(tab_cnf, f_out, bytes_iter, buf_iter, state)
                 }
                 None => Ok(()), /* Finished */
             }
@@ -166,8 +219,8 @@ pub fn run_entab(src: &str, dst: Box<dyn Write>) {
     entab(src, dst).user_err("Error in entab");
 }
 
-pub fn entab<W: Write>(src: &str, mut f_out: W) -> Result<(), Error> {
-    let f_in = File::open(src).sfw_err("Couldn't open source")?;
+pub fn entab<W: Write>(src: &str, mut f_out: W) -> Result<(), SfwError> {
+    let f_in = File::open(src).sfw_path_err("Couldn't open source", src)?;
     let f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
     entab_go(
         &TabConf::TabConstant(2),
@@ -187,7 +240,7 @@ fn entab_go<'a, R, W>(
     mut buf_iter: std::vec::IntoIter<u8>,
     tab_pos: usize,
     spc_count: usize,
-) -> Result<(), Error>
+) -> Result<(), SfwError>
 where
     R: Read,
     W: Write,
@@ -0,0 +1,278 @@
+/*
+The transliteration filter from chapter 2 of Software Tools. As in the
+book, `translit FROM TO` maps each character of FROM to the character in
+the same position of TO. If TO is shorter than FROM, the remaining
+characters of FROM all map to the last character of TO, and runs of them
+are squeezed down to a single copy; if TO is empty, the characters of
+FROM are deleted instead. A leading `^` negates FROM, so that every
+character *not* in FROM is mapped (and squeezed) or deleted.
+ */
+
+use std::io::{Read, Write};
+
+use seahorse::{Command, Context};
+use tailcall::tailcall;
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, write_u8};
+
+const NEGATE: u8 = b'^';
+const DASH: u8 = b'-';
+const ESCAPE: u8 = b'@';
+const BACKSLASH: u8 = b'\\';
+
+/// What happens to a single byte of input.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TranslitAction {
+    Keep,
+    Map(u8),
+    /// Map to the given byte, collapsing runs of squeezed bytes into one.
+    Squeeze(u8),
+    Delete,
+}
+
+/// A compiled transliteration: one action for each possible byte value.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TranslitConf {
+    table: Vec<TranslitAction>,
+}
+
+impl TranslitConf {
+    /// Compiles FROM and TO character sets, as given on the command line.
+    pub fn new(from: &str, to: &str) -> Result<Self, SfwError> {
+        let (negated, from) = match from.as_bytes() {
+            [NEGATE, rest @ ..] => (true, rest),
+            other => (false, other),
+        };
+        let from_set = expand_set(from)?;
+        let to_set = expand_set(to.as_bytes())?;
+        if !negated && to_set.len() > from_set.len() {
+            return Err(SfwError::usage(
+                "translit: TO set is longer than FROM set",
+            ));
+        }
+        let squash = negated || from_set.len() > to_set.len();
+        let last_to = to_set.last().copied();
+        let table = (0..=u8::MAX)
+            .map(|byte| {
+                let index = from_set.iter().position(|bt| *bt == byte);
+                let index = match (negated, index) {
+                    (true, Some(_)) => None,
+                    (true, None) => Some(to_set.len().saturating_sub(1)),
+                    (false, index) => index,
+                };
+                match (index, last_to) {
+                    (None, _) => TranslitAction::Keep,
+                    (Some(_), None) => TranslitAction::Delete,
+                    (Some(ii), Some(last))
+                        if squash && ii + 1 >= to_set.len() =>
+                    {
+                        TranslitAction::Squeeze(last)
+                    }
+                    (Some(ii), Some(_)) => TranslitAction::Map(to_set[ii]),
+                }
+            })
+            .collect();
+        Ok(TranslitConf { table })
+    }
+
+    pub fn action(&self, byte: u8) -> TranslitAction {
+        self.table[byte as usize]
+    }
+}
+
+/// Interprets escapes (`@n`, `@t`, `\n`, `\t`, and `@c` or `\c` for a
+/// literal `c`) and expands ranges such as `a-z` into the full set.
+/// A dash at either end of the set, or one that doesn't form an
+/// ascending range, stands for itself.
+pub fn expand_set(set: &[u8]) -> Result<Vec<u8>, SfwError> {
+    let chars = unescape(set)?;
+    Ok(expand_ranges(&chars, vec![]))
+}
+
+/// Escaped characters are returned as `(byte, true)` so that an escaped
+/// dash is never taken as a range.
+fn unescape(set: &[u8]) -> Result<Vec<(u8, bool)>, SfwError> {
+    let mut bytes = set.iter().copied();
+    let mut out = Vec::with_capacity(set.len());
+    while let Some(byte) = bytes.next() {
+        match byte {
+            ESCAPE | BACKSLASH => {
+                let escaped = bytes.next().ok_or_else(|| {
+                    SfwError::usage(format!(
+                        "translit: dangling '{}' at end of set",
+                        byte as char
+                    ))
+                })?;
+                out.push((escape_char(escaped), true));
+            }
+            _ => out.push((byte, false)),
+        }
+    }
+    Ok(out)
+}
+
+pub const fn escape_char(byte: u8) -> u8 {
+    match byte {
+        b'n' => b'\n',
+        b't' => b'\t',
+        b'b' => 0x08,
+        b'r' => b'\r',
+        b'0' => 0,
+        other => other,
+    }
+}
+
+#[tailcall]
+fn expand_ranges(chars: &[(u8, bool)], mut acc: Vec<u8>) -> Vec<u8> {
+    match chars {
+        [(lo, _), (DASH, false), (hi, _), rest @ ..] if lo <= hi => {
+            acc.extend(*lo..=*hi);
+            expand_ranges(rest, acc)
+        }
+        [(byte, _), rest @ ..] => {
+            acc.push(*byte);
+            expand_ranges(rest, acc)
+        }
+        [] => acc,
+    }
+}
+
+const TRANSLIT_USAGE: &str = r#"
+translit [^]FROM TO [SOURCE_FILE [DEST_FILE]]
+
+Characters in FROM are replaced by the corresponding characters in TO.
+Ranges (a-z) and escapes (@n, @t, \n, \t, @c) may be used in both sets.
+If TO is shorter than FROM, the excess characters of FROM map to the
+last character of TO, and runs of them are squeezed into one.
+If TO is empty (""), characters in FROM are deleted.
+A leading ^ applies the translation to all characters NOT in FROM.
+Input is read from STDIN if no SOURCE_FILE is given.
+"#;
+
+pub fn run_translit_seahorse_cmd() -> Command {
+    Command::new("translit")
+        .description(
+            "translit: transliterate, squeeze or delete characters\
+            ; output to STDOUT is the default",
+        )
+        .usage(TRANSLIT_USAGE)
+        .action(run_translit_seahorse_action)
+}
+
+pub fn run_translit_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let from = args.next().user_err("translit: missing FROM set");
+    let to = args.next().map_or("", String::as_str);
+    let conf = TranslitConf::new(from, to).user_err("Error in translit");
+    let f_in = open_input(args.next().map(String::as_str))
+        .user_err("Error in translit");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in translit");
+    run_translit(&conf, f_in, f_out);
+}
+
+/// Convenience function for running translit in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_translit(
+    conf: &TranslitConf,
+    src: Box<dyn Read>,
+    dst: Box<dyn Write>,
+) {
+    translit(conf, src, dst).user_err("Error in translit");
+}
+
+pub fn translit<R: Read, W: Write>(
+    conf: &TranslitConf,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let f_in_iter = BytesIter::new(f_in, DEFAULT_BUF_SIZE);
+    translit_go(conf, &mut f_out, f_in_iter, vec![].into_iter(), false)?;
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+#[tailcall]
+fn translit_go<'a, R, W>(
+    conf: &TranslitConf,
+    f_out: &mut W,
+    mut bytes_iter: BytesIter<R>,
+    mut buf_iter: std::vec::IntoIter<u8>,
+    squeezing: bool,
+) -> Result<(), SfwError>
+where
+    R: Read,
+    W: Write,
+{
+    match buf_iter.next() {
+        Some(byte) => {
+            let squeezing_new = match conf.action(byte) {
+                TranslitAction::Keep => {
+                    write_u8(f_out, byte)?;
+                    false
+                }
+                TranslitAction::Map(to) => {
+                    write_u8(f_out, to)?;
+                    false
+                }
+                TranslitAction::Squeeze(to) => {
+                    if !squeezing {
+                        write_u8(f_out, to)?;
+                    }
+                    true
+                }
+                TranslitAction::Delete => false,
+            };
+            translit_go(conf, f_out, bytes_iter, buf_iter, squeezing_new)
+        }
+        None => {
+            match bytes_iter.next() {
+                Some(buf_new) => {
+                    let buf_iter = buf_new?.into_iter(); //shadow
+                    translit_go(conf, f_out, bytes_iter, buf_iter, squeezing)
+                }
+                None => Ok(()), /* Finished */
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translit_str(from: &str, to: &str, input: &str) -> String {
+        let conf = TranslitConf::new(from, to).unwrap();
+        let mut out = vec![];
+        translit(&conf, input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_expand_set() {
+        assert_eq!(expand_set(b"a-e").unwrap(), b"abcde");
+        assert_eq!(expand_set(b"-a-c-").unwrap(), b"-abc-");
+        assert_eq!(expand_set(b"a@-c@n\\t").unwrap(), b"a-c\n\t");
+    }
+
+    #[test]
+    fn test_translit_map() {
+        assert_eq!(translit_str("a-z", "A-Z", "Hello, world"), "HELLO, WORLD");
+    }
+
+    #[test]
+    fn test_translit_delete() {
+        assert_eq!(translit_str("aeiou", "", "education"), "dctn");
+    }
+
+    #[test]
+    fn test_translit_squeeze() {
+        assert_eq!(translit_str("a-z", "x", "ab1cd2ef"), "x1x2x");
+        assert_eq!(
+            translit_str("^a-z", "@n", "one, two  three"),
+            "one\ntwo\nthree"
+        );
+    }
+}
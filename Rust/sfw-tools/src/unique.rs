@@ -0,0 +1,256 @@
+/*
+`unique` from chapter 4 of Software Tools (better known as `uniq`)
+copies its input to its output, collapsing each run of adjacent
+duplicate lines into one; on sorted input, this leaves every line
+once. Runs may instead be counted, or only the duplicated (or only the
+unique) lines printed, and lines may be compared ignoring case or after
+skipping leading fields and characters.
+
+Lines are read through `BytesIter::lines`, so a line split across chunks
+is compared whole, and only the current run is held in memory.
+ */
+
+use std::io::{Read, Write};
+
+use seahorse::{Command, Context, Flag, FlagType};
+
+use crate::bytes_iter::BytesIter;
+use crate::constants::*;
+use crate::error::*;
+use crate::util::{open_input, open_output, opt_usize_flag};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct UniqueConf {
+    /// Prefix each line with the length of its run.
+    pub count: bool,
+    /// Print only lines that were repeated.
+    pub repeated_only: bool,
+    /// Print only lines that weren't repeated.
+    pub unique_only: bool,
+    /// Fields (blanks then non-blanks) ignored at the start of each line.
+    pub skip_fields: usize,
+    /// Characters ignored after the skipped fields.
+    pub skip_chars: usize,
+    pub ignore_case: bool,
+}
+
+const UNIQUE_USAGE: &str = r#"
+unique [OPTION]... [SOURCE_FILE [DEST_FILE]]
+
+Copies SOURCE_FILE (or STDIN), writing each run of adjacent identical
+lines only once. Also available as uniq.
+
+Valid options are:
+-c            prefix lines with the number of times they occurred
+-d            only print lines that were repeated
+-u            only print lines that weren't repeated
+-f N          ignore the first N fields (blanks then non-blanks)
+-s N          ignore the first N characters, after any skipped fields
+-i            ignore case when comparing
+
+"#;
+
+pub fn run_unique_seahorse_cmd() -> Command {
+    Command::new("unique")
+        .alias("uniq")
+        .description(
+            "unique: remove adjacent duplicate lines\
+            ; output to STDOUT is the default",
+        )
+        .usage(UNIQUE_USAGE)
+        .action(run_unique_seahorse_action)
+        .flag(
+            Flag::new("count", FlagType::Bool)
+                .alias("c")
+                .description("unique -c some_file"),
+        )
+        .flag(
+            Flag::new("repeated", FlagType::Bool)
+                .alias("d")
+                .description("unique -d some_file"),
+        )
+        .flag(
+            Flag::new("unique", FlagType::Bool)
+                .alias("u")
+                .description("unique -u some_file"),
+        )
+        .flag(
+            Flag::new("skip-fields", FlagType::Int)
+                .alias("f")
+                .description("unique -f 1 some_file"),
+        )
+        .flag(
+            Flag::new("skip-chars", FlagType::Int)
+                .alias("s")
+                .description("unique -s 3 some_file"),
+        )
+        .flag(
+            Flag::new("ignore-case", FlagType::Bool)
+                .alias("i")
+                .description("unique -i some_file"),
+        )
+}
+
+pub fn run_unique_seahorse_action(ctxt: &Context) {
+    let args = &mut ctxt.args.iter();
+    let f_in =
+        open_input(args.next().map(String::as_str)).user_err("Error in unique");
+    let f_out = open_output(args.next().map(String::as_str))
+        .user_err("Error in unique");
+    let conf = UniqueConf {
+        count: ctxt.bool_flag("count"),
+        repeated_only: ctxt.bool_flag("repeated"),
+        unique_only: ctxt.bool_flag("unique"),
+        skip_fields: opt_usize_flag(ctxt, "skip-fields")
+            .user_err("Error in unique")
+            .unwrap_or_default(),
+        skip_chars: opt_usize_flag(ctxt, "skip-chars")
+            .user_err("Error in unique")
+            .unwrap_or_default(),
+        ignore_case: ctxt.bool_flag("ignore-case"),
+    };
+    run_unique(&conf, f_in, f_out);
+}
+
+/// Convenience function for running unique in idiomatic fashion
+/// (i.e.) errors are printed to user and the program exits.
+pub fn run_unique(
+    conf: &UniqueConf,
+    f_in: Box<dyn Read>,
+    f_out: Box<dyn Write>,
+) {
+    unique(conf, f_in, f_out).user_err("Error in unique");
+}
+
+pub fn unique<R: Read, W: Write>(
+    conf: &UniqueConf,
+    f_in: R,
+    mut f_out: W,
+) -> Result<(), SfwError> {
+    let mut run: Option<(Vec<u8>, u64)> = None;
+    for line in BytesIter::new(f_in, DEFAULT_BUF_SIZE).lines() {
+        let line = line.sfw_err("Couldn't read input")?;
+        run = match run {
+            Some((first, count)) if same_lines(conf, &first, &line) => {
+                Some((first, count + 1))
+            }
+            Some((first, count)) => {
+                write_run(conf, &first, count, &mut f_out)?;
+                Some((line, 1))
+            }
+            None => Some((line, 1)),
+        };
+    }
+    if let Some((first, count)) = run {
+        write_run(conf, &first, count, &mut f_out)?;
+    }
+    f_out.flush().sfw_err("Couldn't flush output")
+}
+
+/// Writes the first line of a run of `count` equal lines, if it is
+/// wanted.
+fn write_run<W: Write>(
+    conf: &UniqueConf,
+    line: &[u8],
+    count: u64,
+    f_out: &mut W,
+) -> Result<(), SfwError> {
+    let wanted = if count > 1 {
+        !conf.unique_only
+    } else {
+        !conf.repeated_only
+    };
+    if wanted {
+        if conf.count {
+            write!(f_out, "{:7} ", count)?;
+        }
+        f_out.write_all(line)?;
+        f_out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn same_lines(conf: &UniqueConf, line1: &[u8], line2: &[u8]) -> bool {
+    let key1 = compared_part(conf, line1);
+    let key2 = compared_part(conf, line2);
+    if conf.ignore_case {
+        key1.eq_ignore_ascii_case(key2)
+    } else {
+        key1 == key2
+    }
+}
+
+/// What is left of `line` after skipping `skip_fields` fields and then
+/// `skip_chars` characters, where a UTF-8 character is skipped whole.
+pub fn compared_part<'a>(conf: &UniqueConf, line: &'a [u8]) -> &'a [u8] {
+    let is_blank = |bt: &u8| *bt == b' ' || *bt == b'\t';
+    let after_fields = (0..conf.skip_fields).fold(line, |rest, _| {
+        let start = rest.iter().position(|bt| !is_blank(bt));
+        let rest = &rest[start.unwrap_or(rest.len())..];
+        let end = rest.iter().position(is_blank);
+        &rest[end.unwrap_or(rest.len())..]
+    });
+    let start = after_fields
+        .iter()
+        .enumerate()
+        .filter(|(_, bt)| *bt & 0xC0 != 0x80)
+        .nth(conf.skip_chars)
+        .map_or(after_fields.len(), |(ix, _)| ix);
+    &after_fields[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique_str(conf: &UniqueConf, input: &str) -> String {
+        let mut out = vec![];
+        unique(conf, input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const INPUT: &str = "a\na\nb\nc\nc\nc\na";
+
+    #[test]
+    fn test_unique_counts_and_filters() {
+        let conf = UniqueConf::default();
+        assert_eq!(unique_str(&conf, INPUT), "a\nb\nc\na\n");
+        let conf = UniqueConf {
+            count: true,
+            repeated_only: true,
+            ..UniqueConf::default()
+        };
+        assert_eq!(unique_str(&conf, INPUT), "      2 a\n      3 c\n");
+        let conf = UniqueConf {
+            unique_only: true,
+            ..UniqueConf::default()
+        };
+        assert_eq!(unique_str(&conf, INPUT), "b\na\n");
+    }
+
+    #[test]
+    fn test_unique_skipping_and_case() {
+        let conf = UniqueConf {
+            skip_fields: 1,
+            skip_chars: 2,
+            ignore_case: true,
+            ..UniqueConf::default()
+        };
+        assert_eq!(
+            unique_str(&conf, "1 xAb\n2 yaB\n3 zac\n"),
+            "1 xAb\n3 zac\n"
+        );
+        let conf = UniqueConf {
+            skip_chars: 1,
+            ..UniqueConf::default()
+        };
+        assert_eq!(
+            compared_part(&conf, "\u{e9}t\u{e9}".as_bytes()),
+            "t\u{e9}".as_bytes()
+        );
+        assert_eq!(
+            unique_str(&conf, "\u{e9}x\n\u{4e2d}x\nax\n\u{e9}y\n"),
+            "\u{e9}x\n\u{e9}y\n"
+        );
+    }
+}
@@ -1,4 +1,11 @@
-use std::io::{Error, Write};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, Read, Write};
+use std::path::Path;
+use std::process;
+
+use seahorse::{error::FlagError, Context};
+
+use crate::error::*;
 
 pub const fn is_newline(bt: u8) -> bool {
     bt == b'\n'
@@ -19,6 +26,93 @@ pub fn opt_as_empty_str<T: ToString>(str_opt: Option<T>) -> String {
         .unwrap_or_else(|| String::from(""))
 }
 
+/// The conventional file name standing in for STDIN or STDOUT.
+pub const STD_STREAM_NAME: &str = "-";
+
+/// Opens `src` for reading, falling back to STDIN when no file (or `-`)
+/// is given, as is usual for filters.
+pub fn open_input(src: Option<&str>) -> Result<Box<dyn Read>, SfwError> {
+    match src {
+        Some(src) if src != STD_STREAM_NAME => Ok(Box::new(
+            File::open(src).sfw_path_err("Couldn't open source", src)?,
+        )),
+        _ => Ok(Box::new(io::stdin())),
+    }
+}
+
+/// Creates `dst` for writing, falling back to STDOUT when no file (or `-`)
+/// is given. The writer is buffered, so callers should flush it when done
+/// in order to see any errors.
+pub fn open_output(dst: Option<&str>) -> Result<Box<dyn Write>, SfwError> {
+    match dst {
+        Some(dst) if dst != STD_STREAM_NAME => Ok(Box::new(BufWriter::new(
+            File::create(dst).sfw_path_err("Couldn't open destination", dst)?,
+        ))),
+        _ => Ok(Box::new(BufWriter::new(io::stdout()))),
+    }
+}
+
+/// Writes `path` by way of a temporary file in the same directory, which
+/// is renamed over `path` only once it has been written in full; readers
+/// thus see either the old contents or the new, never a partial file.
+/// The permissions of an existing file are kept.
+pub fn write_atomically<P, F>(path: P, write: F) -> Result<(), SfwError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut dyn Write) -> Result<(), SfwError>,
+{
+    let path = path.as_ref();
+    let file_name = path.file_name().ok_or_else(|| {
+        SfwError::usage(format!("{}: not a file name", path.display()))
+    })?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let res = File::create(&tmp_path)
+        .sfw_path_err("Couldn't create temporary file", &tmp_path)
+        .and_then(|file| {
+            let mut f_out = BufWriter::new(file);
+            write(&mut f_out)?;
+            let file = f_out
+                .into_inner()
+                .map_err(|err| err.into_error())
+                .and_then(|file| file.sync_all().map(|_| file))
+                .sfw_path_err("Couldn't write", &tmp_path)?;
+            if let Ok(meta) = fs::metadata(path) {
+                file.set_permissions(meta.permissions())
+                    .sfw_path_err("Couldn't set permissions", &tmp_path)?;
+            }
+            fs::rename(&tmp_path, path).sfw_path_err("Couldn't replace", path)
+        });
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+/// The value of an optional numeric flag, or `None` if it wasn't given.
+pub fn opt_usize_flag(
+    ctxt: &Context,
+    name: &str,
+) -> Result<Option<usize>, SfwError> {
+    match ctxt.int_flag(name) {
+        Ok(value) if value >= 0 => Ok(Some(value as usize)),
+        Err(FlagError::NotFound) => Ok(None),
+        _ => Err(SfwError::usage(format!(
+            "--{}: expected a non-negative number",
+            name
+        ))),
+    }
+}
+
+/// Like `println!`, but returns write errors (such as a closed pipe)
+/// instead of panicking, so they can be handled like any other error.
+pub fn print_line(line: &str) -> Result<(), Error> {
+    writeln!(io::stdout(), "{}", line)
+}
+
 // Based on write_u8 from byteorder
 #[inline]
 pub fn write_u8<W: Write>(writer: &mut W, n: u8) -> Result<(), Error> {
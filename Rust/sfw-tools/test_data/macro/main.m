@@ -0,0 +1 @@
+define(greet, `hello $1')include(part.m)done
@@ -0,0 +1 @@
+greet(world)
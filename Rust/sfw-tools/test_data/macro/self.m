@@ -0,0 +1,2 @@
+first
+include(self.m)
@@ -0,0 +1,2 @@
+include "part.r"   # declarations
+x = N
@@ -0,0 +1,2 @@
+define N 10  # size
+integer x
\ No newline at end of file